@@ -0,0 +1,216 @@
+// The part of the DOM that client and hydrated components use, to run them with Node.js
+const VOID_ELEMENTS = new Set(["br", "hr", "img", "input", "link", "meta"]);
+
+const unescapeHtml = (text) =>
+  text
+    .replace(/&lt;/g, "<")
+    .replace(/&gt;/g, ">")
+    .replace(/&quot;/g, '"')
+    .replace(/&#039;/g, "'")
+    .replace(/&amp;/g, "&");
+
+class Node {
+  static ELEMENT_NODE = 1;
+  static TEXT_NODE = 3;
+  static COMMENT_NODE = 8;
+
+  parentNode = null;
+  childNodes = [];
+
+  get firstChild() {
+    return this.childNodes[0] ?? null;
+  }
+  get nextSibling() {
+    const siblings = this.parentNode?.childNodes ?? [];
+    return siblings[siblings.indexOf(this) + 1] ?? null;
+  }
+  insertBefore(node, reference) {
+    node.remove();
+    const index =
+      reference === null ? this.childNodes.length : this.childNodes.indexOf(reference);
+    this.childNodes.splice(index, 0, node);
+    node.parentNode = this;
+    return node;
+  }
+  appendChild(node) {
+    return this.insertBefore(node, null);
+  }
+  removeChild(node) {
+    this.childNodes.splice(this.childNodes.indexOf(node), 1);
+    node.parentNode = null;
+    return node;
+  }
+  remove() {
+    this.parentNode?.removeChild(this);
+  }
+  replaceWith(node) {
+    this.parentNode.insertBefore(node, this);
+    this.remove();
+  }
+  getRootNode() {
+    let node = this;
+    while (node.parentNode) node = node.parentNode;
+    return node;
+  }
+}
+
+// Components of the corpus are not custom elements, so none is created
+class ShadowRoot extends Node {}
+
+class Text extends Node {
+  nodeType = Node.TEXT_NODE;
+  constructor(data) {
+    super();
+    this.data = data;
+  }
+  get textContent() {
+    return this.data;
+  }
+  set textContent(text) {
+    this.data = String(text);
+  }
+  get outerHTML() {
+    return this.data.replace(/&/g, "&amp;").replace(/</g, "&lt;").replace(/>/g, "&gt;");
+  }
+  cloneNode() {
+    return new Text(this.data);
+  }
+}
+
+class Comment extends Node {
+  nodeType = Node.COMMENT_NODE;
+  constructor(data) {
+    super();
+    this.data = data;
+  }
+  get textContent() {
+    return "";
+  }
+  get outerHTML() {
+    return `<!--${this.data}-->`;
+  }
+  cloneNode() {
+    return new Comment(this.data);
+  }
+}
+
+class Element extends Node {
+  nodeType = Node.ELEMENT_NODE;
+  attrs = new Map();
+  listeners = {};
+  constructor(tag) {
+    super();
+    this.tagName = tag.toUpperCase();
+  }
+  get attributes() {
+    return [...this.attrs].map(([name, value]) => ({ name, value }));
+  }
+  setAttribute(name, value) {
+    this.attrs.set(name, String(value));
+  }
+  getAttribute(name) {
+    return this.attrs.get(name) ?? null;
+  }
+  removeAttribute(name) {
+    this.attrs.delete(name);
+  }
+  get firstElementChild() {
+    return this.childNodes.find((node) => node.nodeType === Node.ELEMENT_NODE) ?? null;
+  }
+  getElementById(id) {
+    for (const node of this.childNodes) {
+      if (node.nodeType !== Node.ELEMENT_NODE) continue;
+      if (node.getAttribute("id") === id) return node;
+      const found = node.getElementById(id);
+      if (found) return found;
+    }
+    return null;
+  }
+  addEventListener(type, listener) {
+    (this.listeners[type] ??= []).push(listener);
+  }
+  click() {
+    for (const listener of this.listeners.click ?? []) listener({ type: "click", target: this });
+  }
+  get textContent() {
+    return this.childNodes.map((node) => node.textContent).join("");
+  }
+  set textContent(text) {
+    this.replaceChildren(...(String(text) === "" ? [] : [new Text(String(text))]));
+  }
+  get innerHTML() {
+    return this.childNodes.map((node) => node.outerHTML).join("");
+  }
+  set innerHTML(html) {
+    this.replaceChildren(...parseHtml(html));
+  }
+  get outerHTML() {
+    const tag = this.tagName.toLowerCase();
+    const attrs = [...this.attrs]
+      .map(([name, value]) => ` ${name}="${value.replace(/&/g, "&amp;").replace(/"/g, "&quot;")}"`)
+      .join("");
+    return VOID_ELEMENTS.has(tag) ? `<${tag}${attrs}>` : `<${tag}${attrs}>${this.innerHTML}</${tag}>`;
+  }
+  replaceChildren(...nodes) {
+    for (const node of [...this.childNodes]) this.removeChild(node);
+    for (const node of nodes) this.appendChild(node);
+  }
+  cloneNode(deep) {
+    const element = new Element(this.tagName);
+    element.attrs = new Map(this.attrs);
+    if (deep) for (const node of this.childNodes) element.appendChild(node.cloneNode(true));
+    return element;
+  }
+}
+
+class Template extends Element {
+  content = new Element("fragment");
+  set innerHTML(html) {
+    this.content.innerHTML = html;
+  }
+}
+
+function parseHtml(html) {
+  const root = new Element("fragment");
+  let parent = root;
+  let i = 0;
+  while (i < html.length) {
+    if (html.startsWith("<!--", i)) {
+      const end = html.indexOf("-->", i);
+      parent.appendChild(new Comment(html.slice(i + 4, end)));
+      i = end + 3;
+    } else if (html.startsWith("</", i)) {
+      parent = parent.parentNode;
+      i = html.indexOf(">", i) + 1;
+    } else if (html[i] === "<") {
+      const end = html.indexOf(">", i);
+      const [, tag, attrs, selfClosing] = html.slice(i, end + 1).match(/^<([\w-]+)(.*?)(\/?)>$/s);
+      const element = new Element(tag);
+      for (const [, name, double, single] of attrs.matchAll(/([^\s=]+)(?:="([^"]*)"|='([^']*)')?/g)) {
+        element.setAttribute(name, unescapeHtml(double ?? single ?? ""));
+      }
+      parent.appendChild(element);
+      if (!selfClosing && !VOID_ELEMENTS.has(tag.toLowerCase())) parent = element;
+      i = end + 1;
+    } else {
+      const end = html.indexOf("<", i) === -1 ? html.length : html.indexOf("<", i);
+      parent.appendChild(new Text(unescapeHtml(html.slice(i, end))));
+      i = end;
+    }
+  }
+  const nodes = [...root.childNodes];
+  root.replaceChildren();
+  return nodes;
+}
+
+const body = new Element("body");
+globalThis.Node = Node;
+globalThis.ShadowRoot = ShadowRoot;
+globalThis.Text = Text;
+globalThis.Comment = Comment;
+globalThis.document = {
+  body,
+  createElement: (tag) => (tag === "template" ? new Template(tag) : new Element(tag)),
+  createTextNode: (data) => new Text(data),
+  getElementById: (id) => body.getElementById(id),
+};
@@ -0,0 +1,19 @@
+// The helpers of the runtime that server-rendered components import
+export function $$lunasEscapeHtml(text) {
+  const map = {
+    "&": "&amp;",
+    "<": "&lt;",
+    ">": "&gt;",
+    '"': "&quot;",
+    "'": "&#039;",
+  };
+
+  return String(text).replace(/[&<>"']/g, function (m) {
+    return map[m];
+  });
+}
+
+export function $$lunasRenderAttr(key, content) {
+  if (content === undefined) return "";
+  return ` ${key}="${$$lunasEscapeHtml(content)}"`;
+}
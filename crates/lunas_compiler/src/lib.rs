@@ -1,4 +1,12 @@
-use lunas_generator::lunas_compile_from_block;
+pub mod project;
+
+use std::collections::HashMap;
+
+pub use lunas_generator::CompileOptions;
+use lunas_generator::{
+    lunas_compile_from_block, lunas_compile_from_block_with_options,
+    lunas_generate_types_from_block, CompileTarget, ImportResolver, ModuleFormat, UpdateDispatch,
+};
 use lunas_parser::parse_lunas_file;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
@@ -40,3 +48,161 @@ pub fn compile(
         css: code.1,
     })
 }
+
+/// Options of `compile_with_options`, as given from JavaScript.
+/// Every field is optional and defaults to the option of `CompileOptions`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct LunasCompilerOptions {
+    pub runtime_path: Option<String>,
+    /// `client`, `server`, `hydrate`, or `customElement`.
+    pub target: Option<String>,
+    /// `esm`, `cjs`, `iife`, or `umd`.
+    pub format: Option<String>,
+    pub global_name: Option<String>,
+    /// Maps the specifier of an import to the global variable holding it in IIFE and UMD builds.
+    pub globals: Option<HashMap<String, String>>,
+    pub inlined_runtime: Option<String>,
+    pub minify: bool,
+    /// `linear` or `perVariable`.
+    pub update_dispatch: Option<String>,
+    pub env: HashMap<String, String>,
+    pub generated_routes_path: Option<String>,
+}
+
+impl TryFrom<LunasCompilerOptions> for CompileOptions {
+    type Error = String;
+
+    fn try_from(options: LunasCompilerOptions) -> Result<Self, Self::Error> {
+        let target = match options.target.as_deref() {
+            None | Some("client") => CompileTarget::Client,
+            Some("server") => CompileTarget::Server,
+            Some("hydrate") => CompileTarget::Hydrate,
+            Some("customElement") => CompileTarget::CustomElement,
+            Some(target) => return Err(format!("unknown compile target: {}", target)),
+        };
+        let format = match options.format.as_deref() {
+            None | Some("esm") => ModuleFormat::Esm,
+            Some("cjs") => ModuleFormat::CommonJs,
+            Some("iife") => ModuleFormat::Iife,
+            Some("umd") => ModuleFormat::Umd,
+            Some(format) => return Err(format!("unknown module format: {}", format)),
+        };
+        let update_dispatch = match options.update_dispatch.as_deref() {
+            None | Some("linear") => UpdateDispatch::Linear,
+            Some("perVariable") => UpdateDispatch::PerVariable,
+            Some(dispatch) => return Err(format!("unknown update dispatch: {}", dispatch)),
+        };
+        let globals = options.globals.map(|globals| -> ImportResolver {
+            Box::new(move |source: &str| match globals.get(source) {
+                Some(name) => Ok(name.clone()),
+                None => Err(format!(
+                    "`{}` has no global name; set `globals` to import it in an IIFE or UMD build",
+                    source
+                )),
+            })
+        });
+        Ok(CompileOptions {
+            runtime_path: options.runtime_path,
+            target,
+            import_resolver: None,
+            generated_routes_path: options.generated_routes_path,
+            format,
+            global_name: options.global_name,
+            globals,
+            inlined_runtime: options.inlined_runtime,
+            minify: options.minify,
+            update_dispatch,
+            env: options.env,
+        })
+    }
+}
+
+/// Compiles a component with `options`, an object of the fields of `LunasCompilerOptions`
+/// such as `{ target: "server", minify: true }`.
+#[wasm_bindgen]
+pub fn compile_with_options(
+    lunas_code: String,
+    options: JsValue,
+) -> Result<LunasCompilerOutput, String> {
+    let options: LunasCompilerOptions = if options.is_undefined() || options.is_null() {
+        LunasCompilerOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options).map_err(|e| e.to_string())?
+    };
+    compile_with(&lunas_code, &options.try_into()?)
+}
+
+/// Compiles a component with `options`, for callers outside of JavaScript.
+pub fn compile_with(
+    lunas_code: &str,
+    options: &CompileOptions,
+) -> Result<LunasCompilerOutput, String> {
+    let blocks = parse_lunas_file(lunas_code)?;
+    let code = lunas_compile_from_block_with_options(&blocks, options)?;
+    Ok(LunasCompilerOutput {
+        js: code.0,
+        css: code.1,
+    })
+}
+
+/// Generates the type declarations (`.d.ts`) of the module that `compile` produces,
+/// including the bindings exported from `script:`.
+#[wasm_bindgen]
+pub fn generate_type_declarations(
+    lunas_code: String,
+    runtime_path: Option<String>,
+) -> Result<String, String> {
+    let blocks = parse_lunas_file(&lunas_code)?;
+    let options = CompileOptions {
+        runtime_path,
+        ..Default::default()
+    };
+    lunas_generate_types_from_block(&blocks, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_options(json: &str) -> Result<CompileOptions, String> {
+        let options: LunasCompilerOptions =
+            serde_json::from_str(json).map_err(|e| e.to_string())?;
+        options.try_into()
+    }
+
+    #[test]
+    fn combines_options_given_from_javascript() {
+        let options = parse_options(
+            r#"{ "target": "server", "format": "cjs", "minify": true, "env": { "MODE": "\"prod\"" } }"#,
+        )
+        .unwrap();
+        assert_eq!(options.target, CompileTarget::Server);
+        assert_eq!(options.format, ModuleFormat::CommonJs);
+        assert!(options.minify);
+        assert_eq!(options.env["MODE"], "\"prod\"");
+        assert_eq!(options.update_dispatch, UpdateDispatch::Linear);
+
+        let options =
+            parse_options(r#"{ "format": "iife", "globals": { "./child.lunas": "Child" } }"#)
+                .unwrap();
+        let globals = options.globals.unwrap();
+        assert_eq!(globals("./child.lunas"), Ok("Child".to_string()));
+        assert!(globals("./other.lunas").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert_eq!(
+            parse_options(r#"{ "target": "worker" }"#).err().unwrap(),
+            "unknown compile target: worker"
+        );
+        assert_eq!(
+            parse_options(r#"{ "updateDispatch": "tree" }"#)
+                .err()
+                .unwrap(),
+            "unknown update dispatch: tree"
+        );
+        assert!(parse_options(r#"{ "minified": true }"#).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use lunas_html_parser::{Dom, Node};
+
+use super::loader::SourceLoader;
+
+const COMPONENT_EXTENSIONS: [&str; 2] = ["lunas", "blv"];
+
+/// Components found in the configured component directories, keyed by tag name.
+#[derive(Debug, Default, Clone)]
+pub struct ComponentIndex {
+    components: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl ComponentIndex {
+    pub fn scan<L: SourceLoader>(loader: &L, dirs: &[PathBuf]) -> Self {
+        let mut components: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for dir in dirs {
+            for path in loader.list_files(dir) {
+                if let Some(name) = component_name_from_path(&path) {
+                    components.entry(name).or_default().push(path);
+                }
+            }
+        }
+        ComponentIndex { components }
+    }
+
+    /// Every file that provides a component named `name`.
+    pub fn lookup(&self, name: &str) -> &[PathBuf] {
+        match self.components.get(name) {
+            Some(paths) => paths,
+            None => &[],
+        }
+    }
+}
+
+/// `my-button.lunas` and `MyButton.lunas` both provide `MyButton`.
+pub fn component_name_from_path(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?;
+    if !COMPONENT_EXTENSIONS.contains(&ext) {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let name = stem
+        .split(['-', '_', '.'])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    match name.is_empty() {
+        true => None,
+        false => Some(name),
+    }
+}
+
+/// Tag names in the template that start with an uppercase letter.
+pub fn find_pascal_case_tags(dom: &Dom) -> BTreeSet<String> {
+    let mut tags = BTreeSet::new();
+    for node in &dom.children {
+        collect_pascal_case_tags(node, &mut tags);
+    }
+    tags
+}
+
+fn collect_pascal_case_tags(node: &Node, tags: &mut BTreeSet<String>) {
+    if let Node::Element(elm) = node {
+        if elm.name.starts_with(|c: char| c.is_ascii_uppercase()) {
+            tags.insert(elm.name.clone());
+        }
+        for child in &elm.children {
+            collect_pascal_case_tags(child, tags);
+        }
+    }
+}
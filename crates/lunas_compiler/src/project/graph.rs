@@ -0,0 +1,125 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use lunas_parser::PropsInput;
+
+/// A `@use` statement of a component, with the path it resolved to.
+#[derive(Debug, Clone)]
+pub struct ComponentUse {
+    pub component_name: String,
+    pub specifier: String,
+    pub resolved_path: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComponentNode {
+    pub path: PathBuf,
+    pub uses: Vec<ComponentUse>,
+    pub inputs: Vec<PropsInput>,
+    /// Specifiers of `@use` statements and script imports, mapped to the specifiers to emit.
+    pub resolved_specifiers: BTreeMap<String, String>,
+}
+
+/// Dependency graph of the components of a project.
+/// Edges point from a component to the components it uses.
+#[derive(Debug, Default, Clone)]
+pub struct ComponentGraph {
+    nodes: BTreeMap<PathBuf, ComponentNode>,
+}
+
+impl ComponentGraph {
+    pub fn insert(&mut self, node: ComponentNode) {
+        self.nodes.insert(node.path.clone(), node);
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<ComponentNode> {
+        self.nodes.remove(path)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&ComponentNode> {
+        self.nodes.get(path)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.nodes.contains_key(path)
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = &ComponentNode> {
+        self.nodes.values()
+    }
+
+    /// Components directly used by `path`.
+    pub fn dependencies(&self, path: &Path) -> Vec<&Path> {
+        match self.nodes.get(path) {
+            Some(node) => node
+                .uses
+                .iter()
+                .map(|u| u.resolved_path.as_path())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Components that directly use `path`.
+    pub fn dependents(&self, path: &Path) -> Vec<&Path> {
+        self.nodes
+            .values()
+            .filter(|node| node.uses.iter().any(|u| u.resolved_path == path))
+            .map(|node| node.path.as_path())
+            .collect()
+    }
+
+    /// Returns `path` and every component that transitively depends on it,
+    /// i.e. everything that has to be recompiled when `path` changes.
+    pub fn affected_by(&self, path: &Path) -> BTreeSet<PathBuf> {
+        let mut affected = BTreeSet::new();
+        let mut stack = vec![path.to_path_buf()];
+        while let Some(current) = stack.pop() {
+            if !affected.insert(current.clone()) {
+                continue;
+            }
+            for dependent in self.dependents(&current) {
+                stack.push(dependent.to_path_buf());
+            }
+        }
+        affected
+    }
+
+    /// Finds import cycles. Each cycle is returned as the list of components
+    /// on it, starting and ending with the same component.
+    pub fn find_cycles(&self) -> Vec<Vec<PathBuf>> {
+        let mut cycles = vec![];
+        let mut finished = BTreeSet::new();
+        for path in self.nodes.keys() {
+            let mut stack = vec![];
+            self.visit_for_cycles(path, &mut stack, &mut finished, &mut cycles);
+        }
+        cycles
+    }
+
+    fn visit_for_cycles(
+        &self,
+        path: &Path,
+        stack: &mut Vec<PathBuf>,
+        finished: &mut BTreeSet<PathBuf>,
+        cycles: &mut Vec<Vec<PathBuf>>,
+    ) {
+        if finished.contains(path) {
+            return;
+        }
+        if let Some(start) = stack.iter().position(|p| p == path) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(path.to_path_buf());
+            cycles.push(cycle);
+            return;
+        }
+        stack.push(path.to_path_buf());
+        for dependency in self.dependencies(path) {
+            self.visit_for_cycles(dependency, stack, finished, cycles);
+        }
+        stack.pop();
+        finished.insert(path.to_path_buf());
+    }
+}
@@ -0,0 +1,112 @@
+use lunas_html_parser::{Dom, Node};
+use lunas_parser::PropsInput;
+
+/// An attribute passed to a child component in a template.
+#[derive(Debug, Clone)]
+pub struct PassedAttribute {
+    pub name: String,
+    pub value: Option<String>,
+    pub bind: bool,
+}
+
+/// Collects the attributes passed to each usage of `component_name` in the template.
+pub fn find_component_usages(dom: &Dom, component_name: &str) -> Vec<Vec<PassedAttribute>> {
+    let mut usages = vec![];
+    for node in &dom.children {
+        collect_usages(node, component_name, &mut usages);
+    }
+    usages
+}
+
+fn collect_usages(node: &Node, component_name: &str, usages: &mut Vec<Vec<PassedAttribute>>) {
+    if let Node::Element(elm) = node {
+        if elm.name == component_name {
+            let mut attrs = elm
+                .attributes
+                .iter()
+                .filter(|(key, _)| {
+                    !key.starts_with('@') && key.as_str() != ":if" && key.as_str() != "ref"
+                })
+                .map(|(key, value)| PassedAttribute {
+                    name: key.trim_start_matches(':').to_string(),
+                    value: value.clone(),
+                    bind: key.starts_with(':'),
+                })
+                .collect::<Vec<_>>();
+            attrs.sort_by(|a, b| a.name.cmp(&b.name));
+            usages.push(attrs);
+        }
+        for child in &elm.children {
+            collect_usages(child, component_name, usages);
+        }
+    }
+}
+
+/// Checks the attributes of one usage against the `@input`s declared by the child.
+pub fn check_passed_attributes(
+    component_name: &str,
+    attrs: &[PassedAttribute],
+    inputs: &[PropsInput],
+) -> Vec<String> {
+    let mut errors = vec![];
+    for attr in attrs {
+        match inputs.iter().find(|i| i.variable_name == attr.name) {
+            Some(input) => {
+                if let Some(literal_type) = literal_type_of(attr) {
+                    if !type_accepts(&input.type_of_value, literal_type) {
+                        errors.push(format!(
+                            "attribute `{}` of <{}> is a {} literal, but the input is declared as `{}`",
+                            attr.name, component_name, literal_type, input.type_of_value
+                        ));
+                    }
+                }
+            }
+            None => errors.push(format!(
+                "<{}> has no input named `{}`",
+                component_name, attr.name
+            )),
+        }
+    }
+    for input in inputs {
+        let is_required = !input.is_nullable && input.initial_value.is_none();
+        if is_required && !attrs.iter().any(|a| a.name == input.variable_name) {
+            errors.push(format!(
+                "<{}> is missing the required input `{}`",
+                component_name, input.variable_name
+            ));
+        }
+    }
+    errors
+}
+
+/// Returns the type of the attribute value when it is a literal, `None` for other expressions.
+fn literal_type_of(attr: &PassedAttribute) -> Option<&'static str> {
+    let value = match &attr.value {
+        Some(value) => value.trim(),
+        None => return Some("boolean"),
+    };
+    if !attr.bind {
+        return Some("string");
+    }
+    if value == "true" || value == "false" {
+        Some("boolean")
+    } else if value.parse::<f64>().is_ok() {
+        Some("number")
+    } else if value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\''))
+            || (value.starts_with('`') && value.ends_with('`')))
+    {
+        Some("string")
+    } else {
+        None
+    }
+}
+
+fn type_accepts(declared: &str, literal_type: &str) -> bool {
+    match declared {
+        "number" | "string" | "boolean" => declared == literal_type,
+        // Types other than primitives (e.g. `any` or object types) are not checked
+        _ => true,
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Reads component sources for the project compiler.
+pub trait SourceLoader {
+    fn load(&self, path: &Path) -> Option<String>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.load(path).is_some()
+    }
+
+    /// Lists every file under `dir`, recursively.
+    fn list_files(&self, dir: &Path) -> Vec<PathBuf>;
+}
+
+/// Loads sources from the local filesystem.
+#[derive(Debug, Default, Clone)]
+pub struct FsLoader;
+
+impl SourceLoader for FsLoader {
+    fn load(&self, path: &Path) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn list_files(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(current) = dirs.pop() {
+            let entries = match fs::read_dir(&current) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                match path.is_dir() {
+                    true => dirs.push(path),
+                    false => files.push(normalize_path(&path)),
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+}
+
+/// In-memory sources, keyed by normalized path.
+impl SourceLoader for HashMap<PathBuf, String> {
+    fn load(&self, path: &Path) -> Option<String> {
+        self.get(&normalize_path(path)).cloned()
+    }
+
+    fn list_files(&self, dir: &Path) -> Vec<PathBuf> {
+        let dir = normalize_path(dir);
+        let mut files = self
+            .keys()
+            .filter(|path| path.starts_with(&dir))
+            .cloned()
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+}
+
+/// Lexically removes `.` and `..` components without touching the filesystem.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            c => normalized.push(c.as_os_str()),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,930 @@
+pub mod discovery;
+pub mod graph;
+pub mod input_check;
+pub mod loader;
+pub mod resolver;
+pub mod routes;
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use lunas_generator::{
+    lunas_compile_from_block_with_options, lunas_generate_types_from_block, CompileOptions,
+    CompileTarget, ModuleFormat, UpdateDispatch, ROUTER_COMPONENTS,
+};
+use lunas_parser::{
+    parse_lunas_file, DetailedBlock, DetailedMetaData, RouteDeclaration, RouteTarget,
+    UseComponentStatement,
+};
+use serde_json::Value;
+
+use discovery::{find_pascal_case_tags, ComponentIndex};
+use graph::{ComponentGraph, ComponentNode, ComponentUse};
+use input_check::{check_passed_attributes, find_component_usages};
+use loader::{normalize_path, FsLoader, SourceLoader};
+use resolver::{relative_specifier, Resolver, ResolverOptions};
+use routes::{collect_page_routes, default_routes_module_path, generate_routes_module};
+
+/// An error found while compiling a project, attributed to a component file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectDiagnostic {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ProjectDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProjectOptions {
+    pub runtime_path: Option<String>,
+    pub target: CompileTarget,
+    /// Format of the compiled components. The routes module is always an ES module.
+    pub format: ModuleFormat,
+    /// Minifies the compiled components and their CSS for production.
+    pub minify: bool,
+    /// How compiled components find the bindings to refresh when their variables change.
+    pub update_dispatch: UpdateDispatch,
+    /// Values that `Lunas.env.<KEY>` is replaced with in every component.
+    pub env: HashMap<String, String>,
+    pub resolver: ResolverOptions,
+    /// Directories scanned for components when `auto_import_components` is enabled.
+    pub component_dirs: Vec<PathBuf>,
+    /// Imports components referenced by PascalCase tags without an explicit `@use`.
+    pub auto_import_components: bool,
+    /// Directory of page components used to generate the routes of `@useAutoRouting`.
+    pub pages_dir: Option<PathBuf>,
+    /// Where the generated routes module is written.
+    /// Defaults to `generated-routes.js` next to the pages directory.
+    pub routes_module_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledComponent {
+    pub js: String,
+    pub css: Option<String>,
+    /// Type declarations of `js`, to be written next to it as a `.d.ts` file.
+    pub types: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedModule {
+    pub path: PathBuf,
+    pub code: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectOutput {
+    pub components: BTreeMap<PathBuf, CompiledComponent>,
+    /// The routes module, when a pages directory is configured.
+    pub routes_module: Option<GeneratedModule>,
+}
+
+/// Compiles a set of components together, following their `@use` statements.
+///
+/// Compiled output is cached per component; call [`ProjectCompiler::invalidate`]
+/// when a file changes so that it and its dependents are recompiled.
+pub struct ProjectCompiler<L: SourceLoader = FsLoader> {
+    loader: L,
+    runtime_path: Option<String>,
+    target: CompileTarget,
+    format: ModuleFormat,
+    minify: bool,
+    update_dispatch: UpdateDispatch,
+    env: HashMap<String, String>,
+    resolver: Resolver,
+    component_dirs: Vec<PathBuf>,
+    auto_import_components: bool,
+    pages_dir: Option<PathBuf>,
+    routes_module_path: Option<PathBuf>,
+    graph: ComponentGraph,
+    outputs: BTreeMap<PathBuf, CompiledComponent>,
+}
+
+impl ProjectCompiler<FsLoader> {
+    pub fn new(options: ProjectOptions) -> Self {
+        Self::with_loader(FsLoader, options)
+    }
+}
+
+impl<L: SourceLoader> ProjectCompiler<L> {
+    pub fn with_loader(loader: L, options: ProjectOptions) -> Self {
+        ProjectCompiler {
+            loader,
+            runtime_path: options.runtime_path,
+            target: options.target,
+            format: options.format,
+            minify: options.minify,
+            update_dispatch: options.update_dispatch,
+            env: options.env,
+            resolver: Resolver::new(options.resolver),
+            component_dirs: options.component_dirs,
+            auto_import_components: options.auto_import_components,
+            routes_module_path: match (&options.routes_module_path, &options.pages_dir) {
+                (Some(path), _) => Some(normalize_path(path)),
+                (None, Some(pages_dir)) => {
+                    Some(default_routes_module_path(&normalize_path(pages_dir)))
+                }
+                (None, None) => None,
+            },
+            pages_dir: options.pages_dir.map(|p| normalize_path(&p)),
+            graph: ComponentGraph::default(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    pub fn graph(&self) -> &ComponentGraph {
+        &self.graph
+    }
+
+    /// Forgets `path` and everything depending on it, returning the affected components.
+    pub fn invalidate(&mut self, path: &Path) -> Vec<PathBuf> {
+        let affected = self.graph.affected_by(&normalize_path(path));
+        for path in &affected {
+            self.graph.remove(path);
+            self.outputs.remove(path);
+        }
+        affected.into_iter().collect()
+    }
+
+    /// Compiles `entries` and every component reachable from them.
+    /// When a pages directory is configured, every page is an entry as well.
+    pub fn compile(
+        &mut self,
+        entries: &[PathBuf],
+    ) -> Result<ProjectOutput, Vec<ProjectDiagnostic>> {
+        let mut diagnostics = vec![];
+        let mut entries = entries.to_vec();
+        let routes_module = match (&self.pages_dir, &self.routes_module_path) {
+            (Some(pages_dir), Some(module_path)) => {
+                match collect_page_routes(&self.loader, pages_dir) {
+                    Ok(routes) => {
+                        entries
+                            .extend(routes.iter().flat_map(|r| r.files()).map(Path::to_path_buf));
+                        Some(GeneratedModule {
+                            path: module_path.clone(),
+                            code: generate_routes_module(
+                                &routes,
+                                module_path.parent().unwrap_or_else(|| Path::new("")),
+                            ),
+                        })
+                    }
+                    Err(errors) => {
+                        diagnostics.extend(
+                            errors
+                                .into_iter()
+                                .map(|(path, message)| ProjectDiagnostic { path, message }),
+                        );
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let mut blocks = BTreeMap::new();
+        let component_index = match self.auto_import_components {
+            true => Some(ComponentIndex::scan(&self.loader, &self.component_dirs)),
+            false => None,
+        };
+
+        let mut queue = entries
+            .iter()
+            .map(|e| normalize_path(e))
+            .collect::<VecDeque<_>>();
+        while let Some(path) = queue.pop_front() {
+            if self.graph.contains(&path) || blocks.contains_key(&path) {
+                continue;
+            }
+            let source = match self.loader.load(&path) {
+                Some(source) => source,
+                None => {
+                    diagnostics.push(ProjectDiagnostic {
+                        path: path.clone(),
+                        message: "file not found".to_string(),
+                    });
+                    continue;
+                }
+            };
+            let mut block = match parse_lunas_file(&source) {
+                Ok(block) => block,
+                Err(e) => {
+                    diagnostics.push(ProjectDiagnostic {
+                        path: path.clone(),
+                        message: e,
+                    });
+                    continue;
+                }
+            };
+            if let Some(component_index) = &component_index {
+                add_auto_imports(component_index, &path, &mut block, &mut diagnostics);
+            }
+            let node = self.create_node(&path, &block, &mut diagnostics);
+            for component_use in &node.uses {
+                queue.push_back(component_use.resolved_path.clone());
+            }
+            self.graph.insert(node);
+            blocks.insert(path, block);
+        }
+
+        for cycle in self.graph.find_cycles() {
+            let cycle_str = cycle
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            diagnostics.push(ProjectDiagnostic {
+                path: cycle[0].clone(),
+                message: format!("import cycle detected: {}", cycle_str),
+            });
+        }
+
+        for (path, block) in &blocks {
+            diagnostics.extend(self.check_inputs_of_children(path, block));
+        }
+
+        if !diagnostics.is_empty() {
+            // Do not cache components of a failed build so that they are checked again
+            for path in blocks.keys() {
+                self.graph.remove(path);
+            }
+            return Err(diagnostics);
+        }
+
+        for (path, block) in &blocks {
+            let resolved_specifiers = self
+                .graph
+                .get(path)
+                .map(|node| node.resolved_specifiers.clone())
+                .unwrap_or_default();
+            let generated_routes_path = self.routes_module_path.as_ref().map(|module_path| {
+                relative_specifier(path.parent().unwrap_or_else(|| Path::new("")), module_path)
+            });
+            // Children of a custom element are rendered into its shadow root as plain components
+            let target = match self.target {
+                CompileTarget::CustomElement
+                    if !block.detailed_meta_data.iter().any(|meta_data| {
+                        matches!(meta_data, DetailedMetaData::CustomElementDeclaration(_))
+                    }) =>
+                {
+                    CompileTarget::Client
+                }
+                target => target,
+            };
+            let options = CompileOptions {
+                runtime_path: self.runtime_path.clone(),
+                target,
+                format: self.format,
+                minify: self.minify,
+                update_dispatch: self.update_dispatch,
+                env: self.env.clone(),
+                generated_routes_path,
+                import_resolver: Some(Box::new(move |specifier: &str| {
+                    resolved_specifiers
+                        .get(specifier)
+                        .cloned()
+                        .ok_or_else(|| format!("cannot resolve `{}`", specifier))
+                })),
+                ..Default::default()
+            };
+            let compiled =
+                lunas_compile_from_block_with_options(block, &options).and_then(|(js, css)| {
+                    let types = lunas_generate_types_from_block(block, &options)?;
+                    Ok(CompiledComponent { js, css, types })
+                });
+            match compiled {
+                Ok(component) => {
+                    self.outputs.insert(path.clone(), component);
+                }
+                Err(message) => diagnostics.push(ProjectDiagnostic {
+                    path: path.clone(),
+                    message,
+                }),
+            }
+        }
+
+        match diagnostics.is_empty() {
+            true => Ok(ProjectOutput {
+                components: self.outputs.clone(),
+                routes_module,
+            }),
+            false => Err(diagnostics),
+        }
+    }
+
+    fn create_node(
+        &self,
+        path: &Path,
+        block: &DetailedBlock,
+        diagnostics: &mut Vec<ProjectDiagnostic>,
+    ) -> ComponentNode {
+        let mut uses = vec![];
+        let mut inputs = vec![];
+        let mut resolved_specifiers = BTreeMap::new();
+        for meta_data in &block.detailed_meta_data {
+            match meta_data {
+                DetailedMetaData::UseComponentStatement(use_component) => {
+                    let specifier = &use_component.component_path;
+                    match self.resolver.resolve(&self.loader, path, specifier) {
+                        Ok(resolved_path) => {
+                            resolved_specifiers.insert(
+                                specifier.clone(),
+                                relative_specifier(
+                                    path.parent().unwrap_or_else(|| Path::new("")),
+                                    &resolved_path,
+                                ),
+                            );
+                            uses.push(ComponentUse {
+                                component_name: use_component.component_name.clone(),
+                                specifier: specifier.clone(),
+                                resolved_path,
+                            })
+                        }
+                        Err(message) => diagnostics.push(ProjectDiagnostic {
+                            path: path.to_path_buf(),
+                            message: format!(
+                                "{} (component `{}`)",
+                                message, use_component.component_name
+                            ),
+                        }),
+                    }
+                }
+                DetailedMetaData::PropsInput(input) => inputs.push(input.clone()),
+                _ => {}
+            }
+        }
+        let scripts = [
+            &block.detailed_language_blocks.module_js,
+            &block.detailed_language_blocks.js,
+        ];
+        for js in scripts.into_iter().flatten() {
+            for specifier in find_script_import_specifiers(&js.ast) {
+                match self
+                    .resolver
+                    .resolve_specifier(&self.loader, path, &specifier)
+                {
+                    Ok(resolved) => {
+                        resolved_specifiers.insert(specifier, resolved);
+                    }
+                    Err(message) => diagnostics.push(ProjectDiagnostic {
+                        path: path.to_path_buf(),
+                        message: format!("{} (script import)", message),
+                    }),
+                }
+            }
+        }
+        ComponentNode {
+            path: path.to_path_buf(),
+            uses,
+            inputs,
+            resolved_specifiers,
+        }
+    }
+
+    fn check_inputs_of_children(
+        &self,
+        path: &Path,
+        block: &DetailedBlock,
+    ) -> Vec<ProjectDiagnostic> {
+        let mut diagnostics = vec![];
+        let node = match self.graph.get(path) {
+            Some(node) => node,
+            None => return diagnostics,
+        };
+        for component_use in &node.uses {
+            let child = match self.graph.get(&component_use.resolved_path) {
+                Some(child) => child,
+                None => continue,
+            };
+            let usages = find_component_usages(
+                &block.detailed_language_blocks.dom,
+                &component_use.component_name,
+            );
+            for attrs in usages {
+                for message in
+                    check_passed_attributes(&component_use.component_name, &attrs, &child.inputs)
+                {
+                    diagnostics.push(ProjectDiagnostic {
+                        path: path.to_path_buf(),
+                        message,
+                    });
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+/// Adds a `@use` statement for each PascalCase tag that is provided by exactly one
+/// discovered component and is not imported explicitly.
+fn add_auto_imports(
+    component_index: &ComponentIndex,
+    path: &Path,
+    block: &mut DetailedBlock,
+    diagnostics: &mut Vec<ProjectDiagnostic>,
+) {
+    let imported_names = block
+        .detailed_meta_data
+        .iter()
+        .filter_map(|meta_data| match meta_data {
+            DetailedMetaData::UseComponentStatement(use_component) => {
+                Some(use_component.component_name.clone())
+            }
+            _ => None,
+        })
+        .collect::<Vec<String>>();
+    let importer_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut referenced_names = find_pascal_case_tags(&block.detailed_language_blocks.dom);
+    for meta_data in &block.detailed_meta_data {
+        if let DetailedMetaData::RouteDeclaration(RouteDeclaration {
+            target: RouteTarget::Component(component),
+            ..
+        }) = meta_data
+        {
+            referenced_names.insert(component.clone());
+        }
+    }
+
+    for tag in referenced_names {
+        if imported_names.contains(&tag) || ROUTER_COMPONENTS.contains(&tag.as_str()) {
+            continue;
+        }
+        match component_index.lookup(&tag) {
+            [] => diagnostics.push(ProjectDiagnostic {
+                path: path.to_path_buf(),
+                message: format!("unknown component <{}>", tag),
+            }),
+            [component_path] => {
+                block
+                    .detailed_meta_data
+                    .push(DetailedMetaData::UseComponentStatement(
+                        UseComponentStatement {
+                            component_name: tag,
+                            component_path: relative_specifier(importer_dir, component_path),
+                        },
+                    ))
+            }
+            candidates => diagnostics.push(ProjectDiagnostic {
+                path: path.to_path_buf(),
+                message: format!(
+                    "ambiguous component <{}>: provided by {}",
+                    tag,
+                    candidates
+                        .iter()
+                        .map(|c| c.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }),
+        }
+    }
+}
+
+// Give: import a from "a"; export { b } from "b"; export * from "c";
+// Want: ["a", "b", "c"]
+fn find_script_import_specifiers(ast: &Value) -> Vec<String> {
+    match ast.get("body") {
+        Some(Value::Array(body)) => body
+            .iter()
+            .filter(|item| {
+                matches!(
+                    item["type"].as_str(),
+                    Some("ImportDeclaration" | "ExportNamedDeclaration" | "ExportAllDeclaration")
+                )
+            })
+            .filter_map(|item| item["source"]["value"].as_str())
+            .map(|specifier| specifier.to_string())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use super::{CompileTarget, ProjectCompiler, ProjectOptions, ResolverOptions};
+
+    fn sources(files: &[(&str, &str)]) -> HashMap<PathBuf, String> {
+        files
+            .iter()
+            .map(|(p, s)| (PathBuf::from(p), s.to_string()))
+            .collect()
+    }
+
+    const CHILD: &str =
+        "@input title:string\n@input count:number = 0\nhtml:\n  <div>${title}</div>\n";
+
+    #[test]
+    fn builds_graph_and_compiles_children() {
+        let files = sources(&[
+            (
+                "src/app.lunas",
+                "@use Child from './components/child.lunas'\nhtml:\n  <div><Child title=\"hi\"></Child></div>\n",
+            ),
+            ("src/components/child.lunas", CHILD),
+        ]);
+        let mut compiler = ProjectCompiler::with_loader(files, ProjectOptions::default());
+        let outputs = compiler
+            .compile(&[PathBuf::from("src/app.lunas")])
+            .unwrap()
+            .components;
+        assert_eq!(outputs.len(), 2);
+        let child = PathBuf::from("src/components/child.lunas");
+        assert_eq!(
+            compiler.graph().dependents(&child),
+            vec![PathBuf::from("src/app.lunas").as_path()]
+        );
+        assert_eq!(compiler.invalidate(&child).len(), 2);
+    }
+
+    #[test]
+    fn reports_missing_files_cycles_and_input_errors() {
+        let files = sources(&[
+            (
+                "a.lunas",
+                "@use B from './b.lunas'\n@use Missing from './missing.lunas'\nhtml:\n  <div><B :count=\"'1'\" foo=\"x\"></B></div>\n",
+            ),
+            ("b.lunas", "@use A from './a.lunas'\n@input title:string\n@input count:number = 0\nhtml:\n  <div></div>\n"),
+        ]);
+        let mut compiler = ProjectCompiler::with_loader(files, ProjectOptions::default());
+        let messages = compiler
+            .compile(&[PathBuf::from("a.lunas")])
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert!(messages.iter().any(|m| m.contains("missing.lunas")));
+        assert!(messages
+            .iter()
+            .any(|m| m.starts_with("import cycle detected")));
+        assert!(messages.iter().any(|m| m == "<B> has no input named `foo`"));
+        assert!(messages
+            .iter()
+            .any(|m| m == "<B> is missing the required input `title`"));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("`count` of <B> is a string literal")));
+    }
+
+    #[test]
+    fn rewrites_aliased_and_package_imports() {
+        let files = sources(&[
+            (
+                "src/app.lunas",
+                "@use Child from '@/components/child'\nhtml:\n  <div><Child title=\"hi\"></Child></div>\nscript:\n  import dayjs from 'dayjs'\n  import missing from 'missing-pkg'\nscript module:\n  export { format } from 'dayjs/format'\n",
+            ),
+            ("src/components/child.lunas", CHILD),
+            ("node_modules/dayjs/index.js", ""),
+            ("node_modules/dayjs/format.js", ""),
+        ]);
+        let options = ProjectOptions {
+            resolver: ResolverOptions {
+                aliases: vec![("@".to_string(), PathBuf::from("src"))],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut compiler = ProjectCompiler::with_loader(files.clone(), options.clone());
+        let diagnostics = compiler
+            .compile(&[PathBuf::from("src/app.lunas")])
+            .unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "cannot resolve `missing-pkg` (script import)"
+        );
+
+        let mut files = files;
+        files.insert(
+            PathBuf::from("node_modules/missing-pkg/index.mjs"),
+            String::new(),
+        );
+        let mut compiler = ProjectCompiler::with_loader(files, options);
+        let outputs = compiler
+            .compile(&[PathBuf::from("src/app.lunas")])
+            .unwrap()
+            .components;
+        let js = &outputs[&PathBuf::from("src/app.lunas")].js;
+        assert!(js.contains("import Child from \"./components/child.lunas\";"));
+        // Packages are left to the runtime or the bundler, which know their `exports` conditions
+        assert!(js.contains("import dayjs from \"dayjs\""));
+        assert!(js.contains("export { format } from \"dayjs/format\""));
+    }
+
+    #[test]
+    fn auto_imports_components_from_component_dirs() {
+        let app = "html:\n  <div><MyCard title=\"a\"></MyCard></div>\n";
+        let files = sources(&[
+            ("src/app.lunas", app),
+            ("src/components/my-card.lunas", CHILD),
+            (
+                "src/components/form/Button.lunas",
+                "html:\n  <button></button>\n",
+            ),
+            ("src/ui/Button.blv", "html:\n  <button></button>\n"),
+        ]);
+        let options = ProjectOptions {
+            component_dirs: vec![PathBuf::from("src/components"), PathBuf::from("src/ui")],
+            auto_import_components: true,
+            ..Default::default()
+        };
+        let mut compiler = ProjectCompiler::with_loader(files.clone(), options.clone());
+        let outputs = compiler
+            .compile(&[PathBuf::from("src/app.lunas")])
+            .unwrap()
+            .components;
+        assert!(outputs[&PathBuf::from("src/app.lunas")]
+            .js
+            .contains("import MyCard from \"./components/my-card.lunas\";"));
+
+        let mut files = files;
+        files.insert(
+            PathBuf::from("src/app.lunas"),
+            "html:\n  <div><Button></Button><Unknown></Unknown></div>\n".to_string(),
+        );
+        let mut compiler = ProjectCompiler::with_loader(files, options);
+        let messages = compiler
+            .compile(&[PathBuf::from("src/app.lunas")])
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "ambiguous component <Button>: provided by src/components/form/Button.lunas, src/ui/Button.blv",
+                "unknown component <Unknown>",
+            ]
+        );
+    }
+
+    #[test]
+    fn auto_imports_route_components() {
+        let app = "@useRouting\n@route '/' Home\n@route(name: user) '/users/:id' UserPage\n@route '/old' redirect '/'\n\nhtml:\n  <div><RouterView /></div>\n";
+        let files = sources(&[
+            ("src/app.lunas", app),
+            ("src/pages/Home.lunas", "html:\n  <div></div>\n"),
+            (
+                "src/pages/UserPage.lunas",
+                "@input id: string\n\nhtml:\n  <div></div>\n",
+            ),
+        ]);
+        let options = ProjectOptions {
+            component_dirs: vec![PathBuf::from("src/pages")],
+            auto_import_components: true,
+            ..Default::default()
+        };
+        let mut compiler = ProjectCompiler::with_loader(files, options);
+        let outputs = compiler
+            .compile(&[PathBuf::from("src/app.lunas")])
+            .unwrap()
+            .components;
+        let js = &outputs[&PathBuf::from("src/app.lunas")].js;
+        // Route components are imported like the components used in the markup
+        assert!(js.contains("import Home from \"./pages/Home.lunas\";"));
+        assert!(js.contains("import UserPage from \"./pages/UserPage.lunas\";"));
+    }
+
+    // Components whose builds are compared with and without minifying
+    const CORPUS: [(&str, &str); 6] = [
+        ("child.lunas", include_str!("../../corpus/child.lunas")),
+        ("comment.lunas", include_str!("../../corpus/comment.lunas")),
+        ("counter.lunas", include_str!("../../corpus/counter.lunas")),
+        ("escape.lunas", include_str!("../../corpus/escape.lunas")),
+        ("parent.lunas", include_str!("../../corpus/parent.lunas")),
+        (
+            "whitespace.lunas",
+            include_str!("../../corpus/whitespace.lunas"),
+        ),
+    ];
+
+    fn import_sources(js: &str) -> Vec<&str> {
+        js.split("from")
+            .skip(1)
+            .filter_map(|rest| rest.trim_start().strip_prefix('"')?.split('"').next())
+            .collect()
+    }
+
+    #[test]
+    fn minifies_the_corpus() {
+        let files = sources(&CORPUS);
+        let entries = CORPUS
+            .iter()
+            .map(|(path, _)| PathBuf::from(path))
+            .collect::<Vec<_>>();
+        for target in [
+            CompileTarget::Client,
+            CompileTarget::Server,
+            CompileTarget::Hydrate,
+        ] {
+            let compile = |minify| {
+                let options = ProjectOptions {
+                    target,
+                    minify,
+                    ..Default::default()
+                };
+                ProjectCompiler::with_loader(files.clone(), options)
+                    .compile(&entries)
+                    .unwrap()
+                    .components
+            };
+            let (outputs, minified_outputs) = (compile(false), compile(true));
+            for (path, output) in &outputs {
+                let minified = &minified_outputs[path];
+                assert!(minified.js.len() < output.js.len(), "{:?}", path);
+                assert_eq!(import_sources(&minified.js), import_sources(&output.js));
+                assert!(!minified.js.contains("<!--note"), "{:?}", path);
+            }
+        }
+
+        let options = ProjectOptions {
+            minify: true,
+            ..Default::default()
+        };
+        let outputs = ProjectCompiler::with_loader(files, options)
+            .compile(&[PathBuf::from("whitespace.lunas")])
+            .unwrap()
+            .components;
+        let output = &outputs[&PathBuf::from("whitespace.lunas")];
+        assert_eq!(output.css.as_deref(), Some(".box>h1{color:red}"));
+        assert!(output.js.contains("> ',\" and more </h1>\"],\"h1\")"));
+        assert!(output.js.contains("<pre>  keep\n"));
+    }
+
+    // The runtime that client and hydrated components import, with the types of its source removed by swc
+    fn client_runtime() -> String {
+        use swc_common::{sync::Lrc, FileName, Mark, SourceMap, GLOBALS};
+        use swc_ecma_ast::EsVersion;
+        use swc_ecma_codegen::{text_writer::JsWriter, Config, Emitter};
+        use swc_ecma_parser::{lexer::Lexer, Parser, StringInput, Syntax};
+        use swc_ecma_transforms_base::resolver;
+        use swc_ecma_transforms_typescript::strip;
+        use swc_ecma_visit::VisitMutWith;
+
+        let source = include_str!("../../../../npm-pkgs/lunas/src/runtime/index.ts");
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Anon, source.to_string());
+        let lexer = Lexer::new(
+            Syntax::Typescript(Default::default()),
+            EsVersion::latest(),
+            StringInput::from(&*fm),
+            None,
+        );
+        let mut module = Parser::new_from(lexer).parse_module().unwrap();
+        let mut buf = vec![];
+        GLOBALS.set(&Default::default(), || {
+            let top_level_mark = Mark::new();
+            module.visit_mut_with(&mut resolver(Mark::new(), top_level_mark, true));
+            module.visit_mut_with(&mut strip(top_level_mark));
+            let mut emitter = Emitter {
+                cfg: Config::default(),
+                cm: cm.clone(),
+                comments: None,
+                wr: JsWriter::new(cm, "\n", &mut buf, None),
+            };
+            emitter.emit_module(&module).unwrap();
+        });
+        String::from_utf8(buf).unwrap()
+    }
+
+    // Renders every component of the corpus compiled for `target` with Node.js, with a few sets of args.
+    // Client components are mounted and hydrated ones attach to the server-rendered markup,
+    // and both are printed again after each of their buttons is clicked.
+    // Comments and runs of whitespace, which minifying drops or collapses, are removed from the HTML.
+    fn render_with_node(target: CompileTarget, minify: bool) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "lunas-minify-{}-{:?}-{}",
+            std::process::id(),
+            target,
+            minify
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write_components = |target, minify, runtime: &str, extension: &str| {
+            let options = ProjectOptions {
+                runtime_path: Some(format!("./{}", runtime)),
+                target,
+                minify,
+                ..Default::default()
+            };
+            let outputs = ProjectCompiler::with_loader(sources(&CORPUS), options)
+                .compile(&CORPUS.map(|(path, _)| PathBuf::from(path)))
+                .unwrap()
+                .components;
+            // Node only loads ES modules from `.mjs` files
+            for (path, output) in &outputs {
+                let js = output
+                    .js
+                    .replace(".lunas\"", &format!(".lunas.{}\"", extension));
+                std::fs::write(
+                    dir.join(path)
+                        .with_extension(format!("lunas.{}", extension)),
+                    js,
+                )
+                .unwrap();
+            }
+        };
+        std::fs::write(
+            dir.join("server_runtime.mjs"),
+            include_str!("../../corpus/server_runtime.mjs"),
+        )
+        .unwrap();
+        std::fs::write(dir.join("runtime.mjs"), client_runtime()).unwrap();
+        std::fs::write(dir.join("dom.mjs"), include_str!("../../corpus/dom.mjs")).unwrap();
+        let runtime = match target {
+            CompileTarget::Server => "server_runtime.mjs",
+            _ => "runtime.mjs",
+        };
+        write_components(target, minify, runtime, "mjs");
+        // Hydrated components attach to the markup of the server build
+        if target == CompileTarget::Hydrate {
+            write_components(
+                CompileTarget::Server,
+                minify,
+                "server_runtime.mjs",
+                "server.mjs",
+            );
+        }
+
+        let target_name = format!("{:?}", target);
+        let script = format!(
+            "import \"./dom.mjs\";\n\
+             import {{ $$lunasCreateNonReactive }} from \"./runtime.mjs\";\n\
+             const args = [{{ start: 1, label: \"a\", note: \"b\" }}, {{ start: 5, label: \"<b>&\", note: \"'n'\" }}];\n\
+             const normalize = (html) => html.replace(/<!--.*?-->/gs, \"\").replace(/\\s+/g, \" \");\n\
+             const buttons = (node) => node.nodeType !== Node.ELEMENT_NODE ? [] :\n\
+               [...(node.tagName === \"BUTTON\" ? [node] : []), ...node.childNodes.flatMap(buttons)];\n\
+             // Client components read their inputs from the values that parents pass to them\n\
+             const inputs = (a) => Object.fromEntries(Object.entries(a).map(([k, v]) => [k, $$lunasCreateNonReactive(v)]));\n\
+             const warnings = [];\n\
+             console.warn = (message) => warnings.push(message);\n\
+             for (const path of {:?}) {{\n\
+               const component = (await import(`./${{path}}.mjs`)).default;\n\
+               for (const a of args) {{\n\
+                 if ({target_name:?} === \"Server\") {{\n\
+                   console.log(path, normalize(component(a)));\n\
+                   continue;\n\
+                 }}\n\
+                 const root = document.createElement(\"div\");\n\
+                 document.body.replaceChildren(root);\n\
+                 if ({target_name:?} === \"Hydrate\") {{\n\
+                   root.innerHTML = (await import(`./${{path}}.server.mjs`)).default(a);\n\
+                   component(inputs(a)).hydrate(root.firstElementChild);\n\
+                 }} else {{\n\
+                   component(inputs(a)).mount(root);\n\
+                 }}\n\
+                 console.log(path, normalize(root.innerHTML));\n\
+                 for (const button of buttons(root)) {{\n\
+                   button.click();\n\
+                   await new Promise((resolve) => setTimeout(resolve));\n\
+                   console.log(path, normalize(root.innerHTML));\n\
+                 }}\n\
+               }}\n\
+             }}\n\
+             console.log(\"warnings:\", warnings);\n",
+            CORPUS.map(|(path, _)| path),
+        );
+        let output = std::process::Command::new("node")
+            .args(["--input-type=module", "-e", &script])
+            .current_dir(&dir)
+            .output();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let output = output.expect("`node` is not installed");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs Node.js"]
+    fn minified_server_output_renders_the_same_html() {
+        let html = render_with_node(CompileTarget::Server, false);
+        assert!(html.contains("counter.lunas <div><p>5</p><button>+</button></div>"));
+        assert!(html.contains("child.lunas <span>&lt;b&gt;&amp; / &#039;n&#039;</span>"));
+        assert_eq!(render_with_node(CompileTarget::Server, true), html);
+    }
+
+    #[test]
+    #[ignore = "needs Node.js"]
+    fn minified_client_output_renders_and_updates_the_same() {
+        for target in [CompileTarget::Client, CompileTarget::Hydrate] {
+            let html = render_with_node(target, false);
+            assert!(
+                html.contains("counter.lunas <div><p>6</p><button>+</button></div>"),
+                "{}",
+                html
+            );
+            assert!(html.ends_with("warnings: []\n"), "{}", html);
+            assert_eq!(render_with_node(target, true), html, "{:?}", target);
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::path::{Component, Path, PathBuf};
+
+use serde_json::Value;
+
+use super::loader::{normalize_path, SourceLoader};
+
+/// Conditions of package `exports` that apply to the browser modules Lunas compiles to, by priority.
+const EXPORT_CONDITIONS: [&str; 4] = ["browser", "import", "module", "default"];
+
+/// Configuration of how import specifiers are mapped to files.
+#[derive(Debug, Clone)]
+pub struct ResolverOptions {
+    /// Directory that absolute specifiers (`/foo`) and relative alias targets are resolved from.
+    pub base_dir: PathBuf,
+    /// Prefix aliases, e.g. `("@", "src")` makes `@/ui/Button` resolve to `src/ui/Button`.
+    pub aliases: Vec<(String, PathBuf)>,
+    /// Extensions tried, in order, when the specifier does not point at an existing file.
+    pub extensions: Vec<String>,
+}
+
+impl Default for ResolverOptions {
+    fn default() -> Self {
+        ResolverOptions {
+            base_dir: PathBuf::new(),
+            aliases: vec![],
+            extensions: vec![
+                ".lunas".to_string(),
+                ".blv".to_string(),
+                ".js".to_string(),
+                ".mjs".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    pub options: ResolverOptions,
+}
+
+impl Resolver {
+    pub fn new(options: ResolverOptions) -> Self {
+        Resolver { options }
+    }
+
+    /// Resolves `specifier` imported from `importer` to a file.
+    pub fn resolve<L: SourceLoader>(
+        &self,
+        loader: &L,
+        importer: &Path,
+        specifier: &str,
+    ) -> Result<PathBuf, String> {
+        let importer_dir = importer.parent().unwrap_or_else(|| Path::new(""));
+        let candidate = if specifier.starts_with("./") || specifier.starts_with("../") {
+            Some(importer_dir.join(specifier))
+        } else if let Some(stripped) = specifier.strip_prefix('/') {
+            Some(self.options.base_dir.join(stripped))
+        } else {
+            self.apply_alias(specifier)
+        };
+
+        let resolved = match candidate {
+            Some(candidate) => self.probe(loader, &normalize_path(&candidate)),
+            None => self.resolve_package(loader, importer_dir, specifier),
+        };
+        resolved.ok_or_else(|| format!("cannot resolve `{}`", specifier))
+    }
+
+    /// Resolves `specifier` and returns the specifier to emit in the importer's output:
+    /// a path relative to the importer, or the package specifier itself,
+    /// so that packages are still looked up by whatever bundles or serves the output.
+    pub fn resolve_specifier<L: SourceLoader>(
+        &self,
+        loader: &L,
+        importer: &Path,
+        specifier: &str,
+    ) -> Result<String, String> {
+        let resolved = self.resolve(loader, importer, specifier)?;
+        match self.is_package_specifier(specifier) {
+            true => Ok(specifier.to_string()),
+            false => Ok(relative_specifier(
+                importer.parent().unwrap_or_else(|| Path::new("")),
+                &resolved,
+            )),
+        }
+    }
+
+    fn is_package_specifier(&self, specifier: &str) -> bool {
+        !specifier.starts_with("./")
+            && !specifier.starts_with("../")
+            && !specifier.starts_with('/')
+            && self.apply_alias(specifier).is_none()
+    }
+
+    fn apply_alias(&self, specifier: &str) -> Option<PathBuf> {
+        let (alias, target) = self
+            .options
+            .aliases
+            .iter()
+            .filter(|(alias, _)| {
+                specifier == alias || specifier.starts_with(&format!("{}/", alias))
+            })
+            .max_by_key(|(alias, _)| alias.len())?;
+        let rest = specifier[alias.len()..].trim_start_matches('/');
+        let target = match target.is_absolute() {
+            true => target.clone(),
+            false => self.options.base_dir.join(target),
+        };
+        Some(match rest.is_empty() {
+            true => target,
+            false => target.join(rest),
+        })
+    }
+
+    fn probe<L: SourceLoader>(&self, loader: &L, path: &Path) -> Option<PathBuf> {
+        if loader.exists(path) {
+            return Some(path.to_path_buf());
+        }
+        for ext in &self.options.extensions {
+            let with_ext = PathBuf::from(format!("{}{}", path.display(), ext));
+            if loader.exists(&with_ext) {
+                return Some(with_ext);
+            }
+        }
+        for ext in &self.options.extensions {
+            let index = path.join(format!("index{}", ext));
+            if loader.exists(&index) {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// Looks up a bare specifier in the `node_modules` directories above the importer.
+    fn resolve_package<L: SourceLoader>(
+        &self,
+        loader: &L,
+        importer_dir: &Path,
+        specifier: &str,
+    ) -> Option<PathBuf> {
+        let segments = specifier.split('/').collect::<Vec<_>>();
+        let name_len = match specifier.starts_with('@') {
+            true => 2,
+            false => 1,
+        };
+        if segments.len() < name_len {
+            return None;
+        }
+        let package_name = segments[..name_len].join("/");
+        let subpath = segments[name_len..].join("/");
+
+        let mut dir = Some(normalize_path(importer_dir));
+        while let Some(current) = dir {
+            let package_dir = current.join("node_modules").join(&package_name);
+            if let Some(resolved) = self.resolve_in_package(loader, &package_dir, &subpath) {
+                return Some(resolved);
+            }
+            dir = match current.as_os_str().is_empty() {
+                true => None,
+                false => current.parent().map(Path::to_path_buf),
+            };
+        }
+        None
+    }
+
+    fn resolve_in_package<L: SourceLoader>(
+        &self,
+        loader: &L,
+        package_dir: &Path,
+        subpath: &str,
+    ) -> Option<PathBuf> {
+        let manifest = loader
+            .load(&package_dir.join("package.json"))
+            .and_then(|m| serde_json::from_str::<Value>(&m).ok());
+        // `exports` lists every module of the package that can be imported
+        if let Some(exports) = manifest.as_ref().map(|m| &m["exports"]) {
+            if !exports.is_null() {
+                let target = find_export_target(exports, subpath)?;
+                return self.probe(loader, &normalize_path(&package_dir.join(target)));
+            }
+        }
+        if !subpath.is_empty() {
+            return self.probe(loader, &package_dir.join(subpath));
+        }
+        if let Some(manifest) = manifest {
+            for field in ["module", "main"] {
+                if let Some(entry) = manifest[field].as_str() {
+                    if let Some(resolved) =
+                        self.probe(loader, &normalize_path(&package_dir.join(entry)))
+                    {
+                        return Some(resolved);
+                    }
+                }
+            }
+        }
+        self.probe(loader, &package_dir.join("index"))
+    }
+}
+
+// Give: { ".": { "import": "./index.mjs", "require": "./index.cjs" }, "./locale/*": "./locale/*.js" }
+//   with "" or "locale/ja"
+// Want: "./index.mjs" or "./locale/ja.js"
+fn find_export_target(exports: &Value, subpath: &str) -> Option<String> {
+    let key = match subpath.is_empty() {
+        true => ".".to_string(),
+        false => format!("./{}", subpath),
+    };
+    let subpaths = match exports {
+        Value::Object(map) if map.keys().any(|k| k.starts_with('.')) => map,
+        // Without subpaths, `exports` is the entry of the package
+        _ => {
+            return match key == "." {
+                true => select_export_condition(exports),
+                false => None,
+            }
+        }
+    };
+    if let Some(target) = subpaths.get(&key) {
+        return select_export_condition(target);
+    }
+    // The pattern with the longest prefix is used, as Node.js does
+    subpaths
+        .iter()
+        .filter_map(|(pattern, target)| {
+            let (prefix, suffix) = pattern.split_once('*')?;
+            let matched = key.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some((prefix.len(), target, matched))
+        })
+        .max_by_key(|(prefix_len, _, _)| *prefix_len)
+        .and_then(|(_, target, matched)| {
+            select_export_condition(target).map(|t| t.replace('*', matched))
+        })
+}
+
+fn select_export_condition(target: &Value) -> Option<String> {
+    match target {
+        Value::String(path) => Some(path.clone()),
+        Value::Array(targets) => targets.iter().find_map(select_export_condition),
+        Value::Object(conditions) => EXPORT_CONDITIONS
+            .iter()
+            .filter_map(|condition| conditions.get(*condition))
+            .find_map(select_export_condition),
+        // `null` excludes the subpath from the package
+        _ => None,
+    }
+}
+
+/// Builds a `./`-prefixed specifier pointing from `from_dir` to `to`.
+pub fn relative_specifier(from_dir: &Path, to: &Path) -> String {
+    let from = normalize_path(from_dir);
+    let to = normalize_path(to);
+    let from_components = from.components().collect::<Vec<Component>>();
+    let to_components = to.components().collect::<Vec<Component>>();
+    let common = from_components
+        .iter()
+        .zip(to_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut segments = vec![];
+    for _ in common..from_components.len() {
+        segments.push("..".to_string());
+    }
+    for component in &to_components[common..] {
+        segments.push(component.as_os_str().to_string_lossy().to_string());
+    }
+    let joined = segments.join("/");
+    match joined.starts_with("../") {
+        true => joined,
+        false => format!("./{}", joined),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use super::{Resolver, ResolverOptions};
+
+    #[test]
+    fn resolves_aliases_extensions_and_packages() {
+        let files: HashMap<PathBuf, String> = [
+            ("src/ui/Button.lunas", ""),
+            ("src/pages/index.lunas", ""),
+            (
+                "node_modules/dayjs/package.json",
+                r#"{"main": "dayjs.min.js"}"#,
+            ),
+            ("node_modules/dayjs/dayjs.min.js", ""),
+            ("node_modules/@acme/ui/card.blv", ""),
+        ]
+        .iter()
+        .map(|(p, s)| (PathBuf::from(p), s.to_string()))
+        .collect();
+        let resolver = Resolver::new(ResolverOptions {
+            aliases: vec![("@".to_string(), PathBuf::from("src"))],
+            ..Default::default()
+        });
+        let importer = PathBuf::from("src/pages/index.lunas");
+        let resolve = |s: &str| resolver.resolve_specifier(&files, &importer, s);
+
+        assert_eq!(resolve("@/ui/Button").unwrap(), "../ui/Button.lunas");
+        assert_eq!(resolve("../ui/Button.lunas").unwrap(), "../ui/Button.lunas");
+        assert_eq!(resolve("/src/ui/Button").unwrap(), "../ui/Button.lunas");
+        assert_eq!(resolve("dayjs").unwrap(), "dayjs");
+        assert_eq!(resolve("@acme/ui/card").unwrap(), "@acme/ui/card");
+        assert_eq!(
+            resolver.resolve(&files, &importer, "dayjs").unwrap(),
+            PathBuf::from("node_modules/dayjs/dayjs.min.js")
+        );
+        assert_eq!(
+            resolve("@/ui/Missing").unwrap_err(),
+            "cannot resolve `@/ui/Missing`"
+        );
+    }
+
+    #[test]
+    fn resolves_package_exports() {
+        let manifest = r#"{
+            "main": "./dist/index.cjs",
+            "exports": {
+                ".": { "require": "./dist/index.cjs", "import": "./dist/index.mjs" },
+                "./locale/*": "./dist/locale/*.js",
+                "./locale/internal": null,
+                "./ui": [{ "node": "./dist/ui.node.js" }, "./dist/ui.js"]
+            }
+        }"#;
+        let files: HashMap<PathBuf, String> = [
+            ("node_modules/intl/package.json", manifest),
+            ("node_modules/intl/dist/index.cjs", ""),
+            ("node_modules/intl/dist/index.mjs", ""),
+            ("node_modules/intl/dist/locale/ja.js", ""),
+            ("node_modules/intl/dist/locale/internal.js", ""),
+            ("node_modules/intl/dist/ui.js", ""),
+            ("node_modules/intl/dist/secret.js", ""),
+            (
+                "node_modules/plain/package.json",
+                r#"{"exports": "./main.js"}"#,
+            ),
+            ("node_modules/plain/main.js", ""),
+        ]
+        .iter()
+        .map(|(p, s)| (PathBuf::from(p), s.to_string()))
+        .collect();
+        let resolver = Resolver::default();
+        let importer = PathBuf::from("src/app.lunas");
+        let resolve = |s: &str| resolver.resolve(&files, &importer, s);
+
+        assert_eq!(
+            resolve("intl").unwrap(),
+            PathBuf::from("node_modules/intl/dist/index.mjs")
+        );
+        assert_eq!(
+            resolve("intl/locale/ja").unwrap(),
+            PathBuf::from("node_modules/intl/dist/locale/ja.js")
+        );
+        assert_eq!(
+            resolve("intl/ui").unwrap(),
+            PathBuf::from("node_modules/intl/dist/ui.js")
+        );
+        assert_eq!(
+            resolve("plain").unwrap(),
+            PathBuf::from("node_modules/plain/main.js")
+        );
+        // Modules that are not exported cannot be imported, even if the file exists
+        assert!(resolve("intl/locale/internal").is_err());
+        assert!(resolve("intl/dist/secret.js").is_err());
+        assert!(resolve("plain/main.js").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+};
+
+use lunas_generator::GENERATED_ROUTES_FILE_NAME;
+
+use super::{
+    discovery::component_name_from_path, loader::SourceLoader, resolver::relative_specifier,
+};
+
+/// A page found under the pages directory and the route path it is served at.
+/// Layouts (`_layout.lunas`) have the pages of their directory as children,
+/// whose paths are relative to the layout's path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRoute {
+    pub path: String,
+    pub file: PathBuf,
+    pub children: Vec<PageRoute>,
+}
+
+impl PageRoute {
+    /// The component files of this route and its nested routes.
+    pub fn files(&self) -> Vec<&Path> {
+        let mut files = vec![self.file.as_path()];
+        for child in &self.children {
+            files.extend(child.files());
+        }
+        files
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum RouteSegment {
+    Static(String),
+    Dynamic(String),
+    CatchAll(String),
+}
+
+impl RouteSegment {
+    fn parse(segment: &str) -> Result<Self, String> {
+        let param = match segment.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            Some(param) => param,
+            None => return Ok(RouteSegment::Static(segment.to_string())),
+        };
+        let (param, is_catch_all) = match param.strip_prefix("...") {
+            Some(param) => (param, true),
+            None => (param, false),
+        };
+        if param.is_empty() || !param.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("invalid route parameter `{}`", segment));
+        }
+        Ok(match is_catch_all {
+            true => RouteSegment::CatchAll(param.to_string()),
+            false => RouteSegment::Dynamic(param.to_string()),
+        })
+    }
+
+    fn to_pattern(&self) -> String {
+        match self {
+            RouteSegment::Static(s) => s.clone(),
+            RouteSegment::Dynamic(param) => format!(":{}", param),
+            RouteSegment::CatchAll(param) => format!("*{}", param),
+        }
+    }
+
+    /// Static segments are matched before dynamic ones, and catch-all segments last.
+    fn rank(&self) -> u8 {
+        match self {
+            RouteSegment::Static(_) => 0,
+            RouteSegment::Dynamic(_) => 1,
+            RouteSegment::CatchAll(_) => 2,
+        }
+    }
+}
+
+const LAYOUT_FILE_STEM: &str = "_layout";
+
+fn route_segments(parts: &[String]) -> Result<Vec<RouteSegment>, String> {
+    let segments = parts
+        .iter()
+        .map(|p| RouteSegment::parse(p))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(pos) = segments
+        .iter()
+        .position(|s| matches!(s, RouteSegment::CatchAll(_)))
+    {
+        if pos != segments.len() - 1 {
+            return Err("a catch-all segment must be the last segment of a route".to_string());
+        }
+    }
+    Ok(segments)
+}
+
+fn compare_segments(a: &[RouteSegment], b: &[RouteSegment]) -> Ordering {
+    for (a, b) in a.iter().zip(b.iter()) {
+        let ordering = a.rank().cmp(&b.rank()).then_with(|| a.cmp(b));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+fn format_segments(segments: &[RouteSegment]) -> String {
+    segments
+        .iter()
+        .map(|s| s.to_pattern())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+struct RouteFile {
+    /// Directory of the file, relative to the pages directory.
+    dir: PathBuf,
+    segments: Vec<RouteSegment>,
+    file: PathBuf,
+    is_layout: bool,
+}
+
+/// Collects the routes of every page component under `pages_dir`, most specific first.
+/// `users/[id].lunas` is served at `/users/:id` and `index.lunas` at the path of its directory.
+/// Files whose name starts with `_` are not pages, except `_layout.lunas`,
+/// which wraps the pages of its directory and its subdirectories.
+pub fn collect_page_routes<L: SourceLoader>(
+    loader: &L,
+    pages_dir: &Path,
+) -> Result<Vec<PageRoute>, Vec<(PathBuf, String)>> {
+    let mut errors = vec![];
+    let mut route_files = vec![];
+    for file in loader.list_files(pages_dir) {
+        if component_name_from_path(&file).is_none() {
+            continue;
+        }
+        let relative = match file.strip_prefix(pages_dir) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => continue,
+        };
+        let dir = relative
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf();
+        let stem = relative
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let is_layout = stem == LAYOUT_FILE_STEM;
+        let mut parts = dir
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        if parts.iter().any(|p| p.starts_with('_')) || (stem.starts_with('_') && !is_layout) {
+            continue;
+        }
+        if !is_layout && stem != "index" {
+            parts.push(stem);
+        }
+        match route_segments(&parts) {
+            Ok(segments) => route_files.push(RouteFile {
+                dir,
+                segments,
+                file,
+                is_layout,
+            }),
+            Err(e) => errors.push((file, e)),
+        }
+    }
+
+    let mut defined: Vec<(String, &PathBuf)> = vec![];
+    for page in route_files.iter().filter(|f| !f.is_layout) {
+        let path = format!("/{}", format_segments(&page.segments));
+        if let Some((_, existing)) = defined.iter().find(|(p, _)| *p == path) {
+            errors.push((
+                page.file.clone(),
+                format!(
+                    "route `{}` is already defined by {}",
+                    path,
+                    existing.display()
+                ),
+            ));
+            continue;
+        }
+        defined.push((path, &page.file));
+    }
+
+    match errors.is_empty() {
+        true => Ok(build_route_tree(&route_files, None)),
+        false => Err(errors),
+    }
+}
+
+/// The innermost layout wrapping `file`, excluding the layout itself.
+fn find_layout<'a>(route_files: &'a [RouteFile], file: &RouteFile) -> Option<&'a RouteFile> {
+    route_files
+        .iter()
+        .filter(|l| l.is_layout && l.file != file.file && file.dir.starts_with(&l.dir))
+        .filter(|l| !file.is_layout || l.dir != file.dir)
+        .max_by_key(|l| l.dir.components().count())
+}
+
+fn build_route_tree(route_files: &[RouteFile], layout: Option<&RouteFile>) -> Vec<PageRoute> {
+    let mut members = route_files
+        .iter()
+        .filter(|f| find_layout(route_files, f).map(|l| &l.file) == layout.map(|l| &l.file))
+        .collect::<Vec<_>>();
+    // A layout is tried before a page at the same path, which only matches when no nested route does
+    members.sort_by(|a, b| {
+        compare_segments(&a.segments, &b.segments).then_with(|| b.is_layout.cmp(&a.is_layout))
+    });
+
+    members
+        .into_iter()
+        .map(|f| {
+            let path = match layout {
+                Some(layout) => format_segments(&f.segments[layout.segments.len()..]),
+                None => format!("/{}", format_segments(&f.segments)),
+            };
+            PageRoute {
+                path,
+                file: f.file.clone(),
+                children: match f.is_layout {
+                    true => build_route_tree(route_files, Some(f)),
+                    false => vec![],
+                },
+            }
+        })
+        .collect()
+}
+
+/// Where the routes module of `pages_dir` is written unless another path is configured.
+pub fn default_routes_module_path(pages_dir: &Path) -> PathBuf {
+    pages_dir
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(GENERATED_ROUTES_FILE_NAME)
+}
+
+/// Generates the module imported by components using `@useAutoRouting`.
+/// `module_dir` is the directory the module is written to; loaders import pages relative to it.
+pub fn generate_routes_module(routes: &[PageRoute], module_dir: &Path) -> String {
+    format!(
+        "export const routes = [\n{}\n];\n",
+        generate_route_entries(routes, module_dir, 1)
+    )
+}
+
+fn generate_route_entries(routes: &[PageRoute], module_dir: &Path, depth: usize) -> String {
+    let indent = "  ".repeat(depth);
+    routes
+        .iter()
+        .map(|route| {
+            let fields = format!(
+                "path: {}, component: () => import({})",
+                serde_json::to_string(&route.path).unwrap(),
+                serde_json::to_string(&relative_specifier(module_dir, &route.file)).unwrap()
+            );
+            match route.children.is_empty() {
+                true => format!("{}{{ {} }},", indent, fields),
+                false => format!(
+                    "{}{{ {}, children: [\n{}\n{}] }},",
+                    indent,
+                    fields,
+                    generate_route_entries(&route.children, module_dir, depth + 1),
+                    indent
+                ),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use super::{collect_page_routes, generate_routes_module};
+
+    #[test]
+    fn generates_routes_from_pages_dir() {
+        let files: HashMap<PathBuf, String> = [
+            "src/pages/index.lunas",
+            "src/pages/about.lunas",
+            "src/pages/users/index.lunas",
+            "src/pages/users/[id].lunas",
+            "src/pages/users/new.lunas",
+            "src/pages/[...rest].lunas",
+            "src/pages/_components/card.lunas",
+        ]
+        .iter()
+        .map(|p| (PathBuf::from(p), String::new()))
+        .collect();
+        let routes = collect_page_routes(&files, &PathBuf::from("src/pages")).unwrap();
+        assert_eq!(
+            generate_routes_module(&routes, &PathBuf::from("src")),
+            r#"export const routes = [
+  { path: "/", component: () => import("./pages/index.lunas") },
+  { path: "/about", component: () => import("./pages/about.lunas") },
+  { path: "/users", component: () => import("./pages/users/index.lunas") },
+  { path: "/users/new", component: () => import("./pages/users/new.lunas") },
+  { path: "/users/:id", component: () => import("./pages/users/[id].lunas") },
+  { path: "/*rest", component: () => import("./pages/[...rest].lunas") },
+];
+"#
+        );
+    }
+
+    #[test]
+    fn nests_pages_under_layouts() {
+        let files: HashMap<PathBuf, String> = [
+            "pages/index.lunas",
+            "pages/users/_layout.lunas",
+            "pages/users/index.lunas",
+            "pages/users/[id]/_layout.lunas",
+            "pages/users/[id]/posts.lunas",
+        ]
+        .iter()
+        .map(|p| (PathBuf::from(p), String::new()))
+        .collect();
+        let routes = collect_page_routes(&files, &PathBuf::from("pages")).unwrap();
+        assert_eq!(
+            generate_routes_module(&routes, &PathBuf::from("")),
+            r#"export const routes = [
+  { path: "/", component: () => import("./pages/index.lunas") },
+  { path: "/users", component: () => import("./pages/users/_layout.lunas"), children: [
+    { path: "", component: () => import("./pages/users/index.lunas") },
+    { path: ":id", component: () => import("./pages/users/[id]/_layout.lunas"), children: [
+      { path: "posts", component: () => import("./pages/users/[id]/posts.lunas") },
+    ] },
+  ] },
+];
+"#
+        );
+    }
+}
@@ -1,4 +1,17 @@
-use lunas_compiler::compile;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use lunas_compiler::{
+    compile_with,
+    project::{
+        loader::FsLoader,
+        resolver::relative_specifier,
+        routes::{collect_page_routes, default_routes_module_path, generate_routes_module},
+    },
+    CompileOptions, LunasCompilerOutput,
+};
 use warp::Filter;
 
 #[tokio::main]
@@ -6,29 +19,96 @@ async fn main() {
     let compile = warp::path("compile")
         .and(warp::post())
         .and(warp::body::json())
-        .map(|body: serde_json::Value| {
-            let code = match &body["code"] {
-                serde_json::Value::String(s) => s.to_string(),
-                _ => panic!("code is not a string"),
-            };
-            let runtime_path = match body.get("runtimePath") {
-                Some(v) => Some(
-                    v.as_str()
-                        .expect("runtime_path is not a string")
-                        .to_string(),
-                ),
-                None => None,
-            };
-            match compile(code.clone(), runtime_path) {
-                Ok(r) => {
-                    warp::reply::with_status(warp::reply::json(&r), warp::http::StatusCode::OK)
-                }
-                Err(e) => warp::reply::with_status(
-                    warp::reply::json(&e),
-                    warp::http::StatusCode::BAD_REQUEST,
-                ),
+        .map(|body: serde_json::Value| match compile_request(&body) {
+            Ok(r) => warp::reply::with_status(warp::reply::json(&r), warp::http::StatusCode::OK),
+            Err(e) => {
+                warp::reply::with_status(warp::reply::json(&e), warp::http::StatusCode::BAD_REQUEST)
+            }
+        });
+
+    let routes = warp::path("routes")
+        .and(warp::post())
+        .and(warp::body::json())
+        .map(|body: serde_json::Value| match generate_routes(&body) {
+            Ok(code) => {
+                warp::reply::with_status(warp::reply::json(&code), warp::http::StatusCode::OK)
+            }
+            Err(e) => {
+                warp::reply::with_status(warp::reply::json(&e), warp::http::StatusCode::BAD_REQUEST)
+            }
+        });
+
+    // Serves the routes module itself, so that components can import it without a bundler
+    let routes_module = warp::path("routes")
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .map(|query: HashMap<String, String>| {
+            let body = serde_json::to_value(&query).unwrap();
+            match generate_routes(&body) {
+                Ok(code) => warp::http::Response::builder()
+                    .header("content-type", "application/javascript")
+                    .body(code),
+                Err(e) => warp::http::Response::builder()
+                    .status(warp::http::StatusCode::BAD_REQUEST)
+                    .body(e.join("\n")),
             }
         });
 
-    warp::serve(compile).run(([127, 0, 0, 1], 3030)).await;
+    warp::serve(compile.or(routes).or(routes_module))
+        .run(([127, 0, 0, 1], 3030))
+        .await;
+}
+
+fn compile_request(body: &serde_json::Value) -> Result<LunasCompilerOutput, String> {
+    let code = string_field(body, "code")?.ok_or("code is not a string")?;
+    let runtime_path = string_field(body, "runtimePath")?;
+    // `@useAutoRouting` imports the module served by `GET /routes`, relative to the component at `path`
+    let generated_routes_path = match (routes_module_path(body)?, string_field(body, "path")?) {
+        (Some(module_path), Some(path)) => Some(relative_specifier(
+            Path::new(&path).parent().unwrap_or_else(|| Path::new("")),
+            &module_path,
+        )),
+        _ => None,
+    };
+    let options = CompileOptions {
+        runtime_path,
+        generated_routes_path,
+        ..Default::default()
+    };
+    compile_with(&code, &options)
+}
+
+// The string at `key` of a request body, if it is given
+fn string_field(body: &serde_json::Value, key: &str) -> Result<Option<String>, String> {
+    match body.get(key) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(s)) => Ok(Some(s.to_string())),
+        Some(_) => Err(format!("{} is not a string", key)),
+    }
+}
+
+// The routes module of `pagesDir`, which is `routesModulePath` when it is given
+fn routes_module_path(body: &serde_json::Value) -> Result<Option<PathBuf>, String> {
+    let Some(pages_dir) = string_field(body, "pagesDir")? else {
+        return Ok(None);
+    };
+    Ok(Some(match string_field(body, "routesModulePath")? {
+        Some(path) => PathBuf::from(path),
+        None => default_routes_module_path(Path::new(&pages_dir)),
+    }))
+}
+
+fn generate_routes(body: &serde_json::Value) -> Result<String, Vec<String>> {
+    let module_path = routes_module_path(body)
+        .map_err(|e| vec![e])?
+        .ok_or_else(|| vec!["pagesDir is required".to_string()])?;
+    let pages_dir = PathBuf::from(body["pagesDir"].as_str().unwrap_or_default());
+    let module_dir = module_path.parent().unwrap_or_else(|| Path::new(""));
+    match collect_page_routes(&FsLoader, &pages_dir) {
+        Ok(r) => Ok(generate_routes_module(&r, module_dir)),
+        Err(e) => Err(e
+            .iter()
+            .map(|(path, message)| format!("{}: {}", path.display(), message))
+            .collect()),
+    }
 }
@@ -1,2 +1,11 @@
 pub const ROUTER_VIEW: &str = "RouterView";
 pub const ROUTER_COMPONENTS: [&str; 1] = [ROUTER_VIEW];
+pub const DEFAULT_RUNTIME_PATH: &str = "lunas/dist/runtime";
+pub const ROUTER_RUNTIME_PATH: &str = "lunas/dist/runtime/router";
+/// File name of the routes module generated from the pages directory, which is written next to it.
+pub const GENERATED_ROUTES_FILE_NAME: &str = "generated-routes.js";
+/// Elements that cannot have children, so they have no closing tag.
+pub const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
@@ -1,32 +1,44 @@
-use lunas_parser::{DetailedBlock, DetailedMetaData, PropsInput, UseComponentStatement};
+use lunas_parser::{
+    DetailedBlock, DetailedMetaData, PropsInput, RouteDeclaration, UseComponentStatement,
+};
 use std::collections::HashSet;
 
 use crate::{
-    consts::ROUTER_VIEW,
+    consts::{DEFAULT_RUNTIME_PATH, GENERATED_ROUTES_FILE_NAME, ROUTER_VIEW},
     generate_statements::{
         gen_if_blk::gen_render_if_blk_func,
+        gen_update_groups::gen_grouped_update_func,
         utils::{create_indent, gen_binary_map_from_bool},
     },
     orig_html_struct::structs::{Node, NodeContent},
     structs::{
+        compile_options::{CompileOptions, CompileTarget, UpdateDispatch},
         transform_info::{
             sort_if_blocks, ActionAndTarget, CustomComponentBlockInfo, IfBlockInfo, NeededIdName,
-            TextNodeRendererGroup, VariableNameAndAssignedNumber,
+            RefAndTarget, TextNodeRendererGroup, VariableNameAndAssignedNumber,
         },
         transform_targets::{sort_elm_and_reactive_info, NodeAndReactiveInfo},
     },
     transformers::{
+        codegen::string_literal,
+        exports::ExportedBinding,
         html_utils::{check_html_elms, create_lunas_internal_component_statement},
-        imports::generate_import_string,
+        hydration::{hydration_key_arg, HydrationLocations},
+        imports::resolve_import_source,
         inputs::generate_input_variable_decl,
-        js_utils::analyze_js,
-        router::generate_router_initialization_code,
+        intrinsics::IntrinsicContext,
+        js_utils::{analyze_js, extract_module_script, find_emitted_events},
+        module_format::{find_used_helpers, generate_module},
+        router::{
+            generate_route_table, generate_router_initialization_code,
+            generate_router_view_registration_code, has_router_view,
+        },
     },
 };
 
 pub fn generate_js_from_blocks(
     blocks: &DetailedBlock,
-    runtime_path: Option<String>,
+    options: &CompileOptions,
 ) -> Result<(String, Option<String>), String> {
     let use_component_statements = blocks
         .detailed_meta_data
@@ -52,11 +64,6 @@ pub fn generate_js_from_blocks(
 
     let mut imports = vec![];
 
-    #[cfg(not(feature = "playground"))]
-    {
-        imports.push("import { $$lunasRouter } from \"lunas/dist/runtime/router\";".to_string());
-    }
-
     let using_auto_routing = blocks
         .detailed_meta_data
         .iter()
@@ -66,41 +73,126 @@ pub fn generate_js_from_blocks(
         });
 
     if using_auto_routing {
-        imports.push(
-            "import { routes as $$lunasGeneratedRoutes } from \"virtual:generated-routes\";"
-                .to_string(),
-        );
+        let generated_routes_path = match &options.generated_routes_path {
+            Some(path) => path.clone(),
+            None => format!("./{}", GENERATED_ROUTES_FILE_NAME),
+        };
+        imports.push(format!(
+            "import {{ routes as $$lunasGeneratedRoutes }} from {};",
+            string_literal(&generated_routes_path)
+        ));
         component_names.push(ROUTER_VIEW.to_string());
     }
 
-    // TODO: add manual routing
-    // let using_routing = blocks
-    //     .detailed_meta_data
-    //     .iter()
-    //     .any(|meta_data| match meta_data {
-    //         DetailedMetaData::UseRoutingStatement => true,
-    //         _ => false,
-    //     });
-
-    let runtime_path = match runtime_path.is_none() {
-        true => "lunas/dist/runtime".to_string(),
-        false => runtime_path.unwrap(),
+    let using_routing = blocks
+        .detailed_meta_data
+        .iter()
+        .any(|meta_data| matches!(meta_data, DetailedMetaData::UseRoutingStatement));
+    let route_declarations = blocks
+        .detailed_meta_data
+        .iter()
+        .filter_map(|meta_data| match meta_data {
+            DetailedMetaData::RouteDeclaration(route) => Some(route),
+            _ => None,
+        })
+        .collect::<Vec<&RouteDeclaration>>();
+
+    if using_routing && using_auto_routing {
+        return Err("@useRouting cannot be used together with @useAutoRouting".to_string());
+    }
+    if !using_routing && !route_declarations.is_empty() {
+        return Err("@route can only be used in a component with @useRouting".to_string());
+    }
+    let route_table = match using_routing {
+        true => Some(generate_route_table(&route_declarations, &component_names)?),
+        false => None,
+    };
+    // A `RouterView` in a component without routing metadata renders the nested routes of a layout
+    let is_layout = !using_routing
+        && !using_auto_routing
+        && has_router_view(&blocks.detailed_language_blocks.dom);
+    if using_routing || is_layout {
+        component_names.push(ROUTER_VIEW.to_string());
+    }
+
+    let custom_element_name =
+        blocks
+            .detailed_meta_data
+            .iter()
+            .find_map(|meta_data| match meta_data {
+                DetailedMetaData::CustomElementDeclaration(name) => Some(name),
+                _ => None,
+            });
+    let custom_element_name = match (options.target, custom_element_name) {
+        (CompileTarget::CustomElement, None) => Err(
+            "@customElement is required to compile a component into a custom element".to_string(),
+        )?,
+        (CompileTarget::CustomElement, Some(name)) => Some(name),
+        _ => None,
+    };
+    let declared_events = blocks
+        .detailed_meta_data
+        .iter()
+        .filter_map(|meta_data| match meta_data {
+            DetailedMetaData::EventDeclaration(name) => Some(name),
+            _ => None,
+        })
+        .collect::<Vec<&String>>();
+    if let Some(js_block) = &blocks.detailed_language_blocks.js {
+        for event in find_emitted_events(&js_block.ast) {
+            if !declared_events.contains(&&event) {
+                return Err(format!("event `{}` is not declared with @event", event));
+            }
+        }
+    }
+
+    let runtime_path = match options.runtime_path.is_none() {
+        true => DEFAULT_RUNTIME_PATH.to_string(),
+        false => options.runtime_path.clone().unwrap(),
     };
 
     let mut variables = vec![];
 
     let props_assignment = generate_input_variable_decl(&inputs, &mut variables);
 
-    let (variable_names, imports_in_script, js_output) =
-        analyze_js(blocks, inputs.len() as u32, &mut variables);
+    let intrinsics = IntrinsicContext {
+        inputs: inputs.iter().map(|i| i.variable_name.clone()).collect(),
+        env: Some(&options.env),
+        script: blocks
+            .detailed_language_blocks
+            .js
+            .as_ref()
+            .map_or("", |js_block| &js_block.raw),
+        module_scope: false,
+    };
+    let (imports_in_module_script, module_script) = extract_module_script(blocks, options)?;
+    let mut exports = vec![];
+    let (variable_names, imports_in_script, js_output) = analyze_js(
+        blocks,
+        inputs.len() as u32,
+        &mut variables,
+        &mut exports,
+        &intrinsics,
+    )?;
 
     let mut codes = vec![js_output];
+    codes.extend(route_table);
 
-    imports.extend(imports_in_script.clone());
+    for import in imports_in_module_script.into_iter().chain(imports_in_script) {
+        imports.push(match &options.import_resolver {
+            Some(resolver) => resolve_import_source(&import, resolver)?,
+            None => import,
+        });
+    }
     for use_component in use_component_statements {
+        let component_path = match &options.import_resolver {
+            Some(resolver) => resolver(&use_component.component_path)?,
+            None => use_component.component_path.clone(),
+        };
         imports.push(format!(
-            "import {} from \"{}\";",
-            use_component.component_name, use_component.component_path
+            "import {} from {};",
+            use_component.component_name,
+            string_literal(&component_path)
         ));
     }
 
@@ -109,11 +201,16 @@ pub fn generate_js_from_blocks(
 
     let mut elm_and_var_relation = vec![];
     let mut action_and_target = vec![];
+    let mut ref_and_target = vec![];
     let mut if_blocks_info = vec![];
     let mut custom_component_blocks_info = vec![];
     let mut text_node_renderer = vec![];
 
     let mut new_node = Node::new_from_dom(&blocks.detailed_language_blocks.dom)?;
+    let hydration = match options.target {
+        CompileTarget::Hydrate => Some(HydrationLocations::new(&new_node)),
+        _ => None,
+    };
 
     // Analyze HTML
     check_html_elms(
@@ -123,6 +220,7 @@ pub fn generate_js_from_blocks(
         &mut needed_id,
         &mut elm_and_var_relation,
         &mut action_and_target,
+        &mut ref_and_target,
         None,
         &mut vec![],
         &mut if_blocks_info,
@@ -135,6 +233,11 @@ pub fn generate_js_from_blocks(
     )?;
 
     sort_if_blocks(&mut if_blocks_info);
+    collect_refs_in_if_blocks(
+        &mut if_blocks_info,
+        &ref_and_target,
+        &custom_component_blocks_info,
+    );
     sort_elm_and_reactive_info(&mut elm_and_var_relation);
 
     // TODO: reconsider about this unwrap
@@ -144,9 +247,10 @@ pub fn generate_js_from_blocks(
     };
 
     // Generate JavaScript
+    let mut templates = vec![];
     let html_insert = format!(
-        "{};",
-        create_lunas_internal_component_statement(&new_elm, "$$lunasSetComponentElement")
+        "$$lunasSetComponentElement({});",
+        create_lunas_internal_component_statement(&new_elm, &mut templates)?
     );
     codes.push(html_insert);
     match props_assignment.is_some() {
@@ -162,15 +266,18 @@ pub fn generate_js_from_blocks(
 
     // Generate AfterMount
     let mut after_mount_code_array = vec![];
-    let ref_getter_expression = gen_ref_getter_from_needed_ids(&needed_id, &None, &None);
+    let ref_getter_expression =
+        gen_ref_getter_from_needed_ids(&needed_id, &None, &None, hydration.as_ref());
     after_mount_code_array.push(ref_getter_expression);
     let if_block_elm_decl =
         generate_if_block_ref_var_decl(&if_blocks_info, &needed_id, &text_node_renderer_group);
     after_mount_code_array.extend(if_block_elm_decl);
-    let create_anchor_statements = gen_create_anchor_statements(&text_node_renderer_group, &vec![]);
+    let create_anchor_statements =
+        gen_create_anchor_statements(&text_node_renderer_group, &vec![], hydration.as_ref());
     after_mount_code_array.extend(create_anchor_statements);
     let event_listener_codes = create_event_listener(&action_and_target, &vec![]);
     after_mount_code_array.extend(event_listener_codes);
+    after_mount_code_array.extend(gen_ref_assignments(&ref_and_target, &vec![]));
     let render_if = gen_render_if_blk_func(
         &if_blocks_info,
         &needed_id,
@@ -178,21 +285,36 @@ pub fn generate_js_from_blocks(
         &text_node_renderer_group,
         &custom_component_blocks_info,
         &variable_names,
-    );
+        hydration.as_ref(),
+        &mut templates,
+    )?;
     after_mount_code_array.extend(render_if);
     let render_component = gen_render_custom_component_statements(
         &custom_component_blocks_info,
         &vec![],
         &variable_names,
+        hydration.as_ref(),
     );
-    if using_auto_routing {
+    if using_auto_routing || using_routing {
         after_mount_code_array.push(generate_router_initialization_code(
-            custom_component_blocks_info,
+            &custom_component_blocks_info,
+        )?);
+    }
+    if is_layout {
+        after_mount_code_array.push(generate_router_view_registration_code(
+            &custom_component_blocks_info,
         )?);
     }
     after_mount_code_array.extend(render_component);
     after_mount_code_array.push("this.blkUpdateMap = 0".to_string());
-    let update_func_code = gen_on_update_func(elm_and_var_relation, variables, if_blocks_info);
+    let update_func_code = match options.update_dispatch {
+        UpdateDispatch::Linear => {
+            gen_on_update_func(elm_and_var_relation, variables, if_blocks_info)
+        }
+        UpdateDispatch::PerVariable => {
+            gen_grouped_update_func(&elm_and_var_relation, &variables, &if_blocks_info)?
+        }
+    };
     after_mount_code_array.push(update_func_code);
     let after_mount_code = after_mount_code_array
         .iter()
@@ -208,27 +330,159 @@ pub fn generate_js_from_blocks(
     );
     codes.push(after_mount_func_code);
 
-    codes.push("return $$lunasComponentReturn;".to_string());
+    codes.push(gen_component_return(&exports, &variable_names));
 
-    let full_js_code = gen_full_code(runtime_path, imports, codes, inputs);
     let css_code = blocks.detailed_language_blocks.css.clone();
+    let component_function = gen_component_function(codes, &inputs);
+
+    #[cfg(not(feature = "playground"))]
+    if !find_used_helpers(&component_function, &["$$lunasRouter"]).is_empty() {
+        imports.insert(
+            0,
+            format!(
+                "import {{ $$lunasRouter }} from {};",
+                string_literal(crate::consts::ROUTER_RUNTIME_PATH)
+            ),
+        );
+    }
 
+    let (exported, css_code) = match custom_element_name {
+        // The style is injected into the shadow root instead of being emitted as CSS
+        Some(name) => (
+            gen_custom_element_definition(component_function, &inputs, name, css_code.as_ref()),
+            None,
+        ),
+        None => (component_function, css_code),
+    };
+    let runtime_helpers = find_used_helpers(
+        &format!("{}\n{}", templates.join("\n"), exported),
+        &RUNTIME_IMPORTS,
+    );
+    // `script module:` runs before the templates are created, once when the module is loaded
+    let declarations = match module_script.is_empty() {
+        true => templates,
+        false => [vec![module_script], templates].concat(),
+    };
+    let full_js_code = generate_module(
+        &runtime_helpers,
+        &runtime_path,
+        &imports,
+        &declarations,
+        &exported,
+        options,
+    )?;
     Ok((full_js_code, css_code))
 }
 
-fn gen_full_code(
-    runtime_path: String,
-    imports_string: Vec<String>,
-    codes: Vec<String>,
-    inputs: Vec<&PropsInput>,
+// Give: export function reset() {} export let count = 0; (with count written)
+// Want: return $$lunasExpose({ reset, get count() { return count.v; } });
+fn gen_component_return(exports: &[ExportedBinding], variable_names: &[String]) -> String {
+    if exports.is_empty() {
+        return "return $$lunasComponentReturn;".to_string();
+    }
+    let members = exports
+        .iter()
+        .map(|export| match variable_names.contains(&export.local) {
+            // Reactive values are read when they are accessed, so the parent sees their changes
+            true => format!("get {}() {{ return {}.v; }}", export.name, export.local),
+            false if export.name == export.local => export.name.clone(),
+            false => format!("{}: {}", export.name, export.local),
+        })
+        .collect::<Vec<String>>();
+    format!("return $$lunasExpose({{ {} }});", members.join(", "))
+}
+
+fn gen_custom_element_definition(
+    component_function: String,
+    inputs: &[&PropsInput],
+    tag_name: &str,
+    css: Option<&String>,
 ) -> String {
-    let imports_string = generate_import_string(&imports_string);
+    let props = inputs
+        .iter()
+        .map(|input| {
+            format!(
+                "{}: [{}, {}, () => ({})]",
+                string_literal(&input.variable_name),
+                string_literal(&to_kebab_case(&input.variable_name)),
+                string_literal(&input.type_of_value),
+                input.initial_value.as_deref().unwrap_or("undefined")
+            )
+        })
+        .collect::<Vec<String>>();
+    let style = match css {
+        Some(css) => string_literal(css),
+        None => "null".to_string(),
+    };
+    format!(
+        "$$lunasDefineCustomElement({}, {}, {{{}}}, {});",
+        string_literal(tag_name),
+        component_function,
+        props.join(", "),
+        style
+    )
+}
+
+const RUNTIME_IMPORTS: [&str; 14] = [
+    "$$lunasAddEvListener",
+    "$$lunasEscapeHtml",
+    "$$lunasGetElmRefs",
+    "$$lunasInitComponent",
+    "$$lunasReplaceInnerHtml",
+    "$$lunasReplaceText",
+    "$$lunasReplaceAttr",
+    "$$lunasInsertEmpty",
+    "$$lunasInsertContent",
+    "$$lunasCreateTemplate",
+    "$$lunasUseTemplate",
+    "$$lunasCreateNonReactive",
+    "$$lunasDefineCustomElement",
+    "$$lunasNextTick",
+];
+
+// Created for each instance by `$$lunasInitComponent`
+const COMPONENT_HELPERS: [&str; 16] = [
+    "$$lunasSetComponentElement",
+    "$$lunasUpdateComponent",
+    "$$lunasComponentReturn",
+    "$$lunasAfterMount",
+    "$$lunasReactive",
+    "$$lunasComputed",
+    "$$lunasEffect",
+    "$$lunasWatch",
+    "$$lunasOnMount",
+    "$$lunasOnUpdate",
+    "$$lunasOnDestroy",
+    "$$lunasRenderIfBlock",
+    "$$lunasCreateIfBlock",
+    "$$lunasEmit",
+    "$$lunasRefs",
+    "$$lunasExpose",
+];
+
+// Attributes of custom elements are case-insensitive, so `maxCount` is observed as `max-count`
+fn to_kebab_case(name: &str) -> String {
+    let mut kebab = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if !kebab.is_empty() {
+                kebab.push('-');
+            }
+            kebab.push(c.to_ascii_lowercase());
+        } else {
+            kebab.push(c);
+        }
+    }
+    kebab
+}
+
+fn gen_component_function(codes: Vec<String>, inputs: &[&PropsInput]) -> String {
     let arg_names_array = match inputs.len() == 0 {
         true => "".to_string(),
         false => {
             let arr = inputs
                 .iter()
-                .map(|i| format!("\"{}\"", i.variable_name.clone()))
+                .map(|i| string_literal(&i.variable_name))
                 .collect::<Vec<String>>();
             format!(", [{}]", arr.join(", "))
         }
@@ -241,13 +495,13 @@ fn gen_full_code(
         .collect::<Vec<String>>()
         .join("\n");
     format!(
-        r#"import {{ $$lunasAddEvListener, $$lunasEscapeHtml, $$lunasGetElmRefs, $$lunasInitComponent, $$lunasReplaceInnerHtml, $$lunasReplaceText, $$lunasReplaceAttr, $$lunasInsertEmpty, $$lunasInsertContent, $$createLunasElement, $$lunasCreateNonReactive }} from "{}";{}
-
-export default function(args = {{}}) {{
-    const {{ $$lunasSetComponentElement, $$lunasUpdateComponent, $$lunasComponentReturn, $$lunasAfterMount, $$lunasReactive, $$lunasRenderIfBlock, $$lunasCreateIfBlock }} = new $$lunasInitComponent(args{});
+        r#"function(args = {{}}) {{
+    const {{ {} }} = new $$lunasInitComponent(args{});
 {}
 }}"#,
-        runtime_path, imports_string, arg_names_array, code,
+        find_used_helpers(&code, &COMPONENT_HELPERS).join(", "),
+        arg_names_array,
+        code,
     )
 }
 
@@ -255,6 +509,7 @@ pub fn gen_ref_getter_from_needed_ids(
     needed_ids: &Vec<NeededIdName>,
     if_blk: &Option<&IfBlockInfo>,
     ctx: &Option<&Vec<String>>,
+    hydration: Option<&HydrationLocations>,
 ) -> String {
     let needed_ids_to_get_here = needed_ids
         .iter()
@@ -291,7 +546,7 @@ pub fn gen_ref_getter_from_needed_ids(
     ref_getter_str.push_str(
         needed_ids_to_get_here
             .iter()
-            .map(|id| format!("\"{}\"", id.id_name))
+            .map(|id| string_literal(&id.id_name))
             .collect::<Vec<String>>()
             .join(", ")
             .as_str(),
@@ -301,7 +556,24 @@ pub fn gen_ref_getter_from_needed_ids(
         .map(|id| id.to_delete)
         .collect::<Vec<bool>>();
     let delete_id_map = gen_binary_map_from_bool(delete_id_bool_map);
-    ref_getter_str.push_str(format!("], {map});", map = delete_id_map).as_str());
+    let element_paths = match hydration {
+        Some(hydration) => {
+            let node_ids = needed_ids_to_get_here
+                .iter()
+                .map(|id| &id.node_id)
+                .collect::<Vec<&String>>();
+            format!(", {}", hydration.element_paths(&node_ids))
+        }
+        None => "".to_string(),
+    };
+    ref_getter_str.push_str(
+        format!(
+            "], {map}{paths});",
+            map = delete_id_map,
+            paths = element_paths
+        )
+        .as_str(),
+    );
     ref_getter_str
 }
 
@@ -315,15 +587,60 @@ pub fn create_event_listener(
             continue;
         }
         result.push(format!(
-            "$$lunasAddEvListener($$lunas{}Ref, \"{}\", {});",
+            "$$lunasAddEvListener($$lunas{}Ref, {}, {});",
             action_and_target.target,
-            action_and_target.action_name,
+            string_literal(&action_and_target.action_name),
             action_and_target.action.to_string()
         ));
     }
     result
 }
 
+// Give: <input ref="nameInput">
+// Want: $$lunasRefs.nameInput = $$lunasXXXRef;
+pub fn gen_ref_assignments(
+    refs_and_targets: &[RefAndTarget],
+    current_ctx: &Vec<String>,
+) -> Vec<String> {
+    refs_and_targets
+        .iter()
+        .filter(|ref_and_target| ref_and_target.ctx == *current_ctx)
+        .map(|ref_and_target| {
+            format!(
+                "$$lunasRefs.{} = $$lunas{}Ref;",
+                ref_and_target.ref_name, ref_and_target.target
+            )
+        })
+        .collect()
+}
+
+// Refs inside an if block, including those in nested blocks, are cleared when it is removed
+fn collect_refs_in_if_blocks(
+    if_blocks_info: &mut [IfBlockInfo],
+    refs_and_targets: &[RefAndTarget],
+    custom_component_blocks_info: &[CustomComponentBlockInfo],
+) {
+    for if_block_info in if_blocks_info.iter_mut() {
+        if_block_info.refs = refs_and_targets
+            .iter()
+            .filter(|ref_and_target| ref_and_target.ctx == if_block_info.ctx_under_if)
+            .cloned()
+            .collect();
+        let is_inside = |ctx: &Vec<String>| ctx.contains(&if_block_info.target_if_blk_id);
+        if_block_info.ref_names = refs_and_targets
+            .iter()
+            .filter(|ref_and_target| is_inside(&ref_and_target.ctx))
+            .map(|ref_and_target| ref_and_target.ref_name.clone())
+            .chain(
+                custom_component_blocks_info
+                    .iter()
+                    .filter(|component| is_inside(&component.ctx))
+                    .filter_map(|component| component.ref_name.clone()),
+            )
+            .collect();
+    }
+}
+
 fn generate_if_block_ref_var_decl(
     if_blocks_info: &Vec<IfBlockInfo>,
     needed_id: &Vec<NeededIdName>,
@@ -409,17 +726,28 @@ fn gen_on_update_func(
             .map(|v| v.assignment)
             .collect::<Vec<u32>>();
 
+        // Conditions on values that never change are only checked on the first render
+        if dep_vars_assined_numbers.is_empty() {
+            continue;
+        }
         let combined_number = get_combined_binary_number(dep_vars_assined_numbers);
 
         replace_statements.push(format!(
-            "{}this.valUpdateMap & {} && ( {} ? {} : ({}, {}, {}) );",
+            "{}this.valUpdateMap & {} && ( {} ? {} : (this.blkRenderedMap & {6}) && ({}, {}, this.blkRenderedMap ^= {6}) );",
             if_blk_rendering_cond,
             combined_number,
             if_block_info.condition,
-            format!("$$lunasRenderIfBlock(\"{}\")", &if_block_info.if_blk_id),
-            format!("$$lunas{}Ref.remove()", &if_block_info.if_blk_id),
+            format!(
+                "$$lunasRenderIfBlock({})",
+                string_literal(&if_block_info.if_blk_id)
+            ),
+            format!(
+                "{}$$lunas{}Ref.remove()",
+                gen_ref_clearing(&if_block_info.ref_names),
+                &if_block_info.if_blk_id
+            ),
             format!("$$lunas{}Ref = null", &if_block_info.if_blk_id),
-            format!("this.blkRenderedMap ^= {}", index + 1),
+            1u64 << index,
         ));
     }
 
@@ -439,6 +767,11 @@ fn gen_on_update_func(
                         })
                         .map(|v| v.assignment)
                         .collect::<Vec<u32>>();
+                    // Bindings of values that never change are only rendered once
+                    let combined_number = get_combined_binary_number(dep_vars_assined_numbers);
+                    if combined_number == 0 {
+                        continue;
+                    }
 
                     let if_blk_rendering_cond = if elm_and_attr_relation.ctx.len() != 0 {
                         format!(
@@ -450,10 +783,10 @@ fn gen_on_update_func(
                     };
 
                     replace_statements.push(format!(
-                        "{}this.valUpdateMap & {:?} && $$lunasReplaceAttr(\"{}\", {}, $$lunas{}Ref);",
+                        "{}this.valUpdateMap & {:?} && $$lunasReplaceAttr({}, {}, $$lunas{}Ref);",
                         if_blk_rendering_cond,
-                        get_combined_binary_number(dep_vars_assined_numbers),
-                        c.attribute_key,
+                        combined_number,
+                        string_literal(&c.attribute_key),
                         c.content_of_attr,
                         elm_and_attr_relation.elm_id
                     ));
@@ -486,6 +819,9 @@ fn gen_on_update_func(
                 };
 
                 let combined_number = get_combined_binary_number(dep_vars_assined_numbers);
+                if combined_number == 0 {
+                    continue;
+                }
 
                 let to_update_cond = if under_if_blk {
                     format!(
@@ -534,6 +870,9 @@ fn gen_on_update_func(
                 };
 
                 let combined_number = get_combined_binary_number(dep_vars_assined_numbers);
+                if combined_number == 0 {
+                    continue;
+                }
 
                 let to_update_cond = if under_if_blk {
                     format!(
@@ -572,9 +911,19 @@ fn gen_on_update_func(
     result
 }
 
+// Give: ["nameInput"]
+// Want: $$lunasRefs.nameInput = null,
+pub fn gen_ref_clearing(ref_names: &[String]) -> String {
+    ref_names
+        .iter()
+        .map(|name| format!("$$lunasRefs.{} = null, ", name))
+        .collect()
+}
+
 pub fn gen_create_anchor_statements(
     text_node_renderer: &TextNodeRendererGroup,
     ctx_condition: &Vec<String>,
+    hydration: Option<&HydrationLocations>,
 ) -> Vec<String> {
     let mut create_anchor_statements = vec![];
     for render in &text_node_renderer.renderers {
@@ -593,12 +942,13 @@ pub fn gen_create_anchor_statements(
                     false => "const ",
                 };
                 let create_anchor_statement = format!(
-                    "{}$$lunas{}Text = $$lunasInsertContent(`{}`,$$lunas{}Ref,{});",
+                    "{}$$lunas{}Text = $$lunasInsertContent(`{}`,$$lunas{}Ref,{}{});",
                     &variable_declaration_word,
                     &txt_renderer.text_node_id,
                     &txt_renderer.content.trim(),
                     &txt_renderer.parent_id,
-                    anchor_id
+                    anchor_id,
+                    hydration_key_arg(hydration, &txt_renderer.element_location)
                 );
                 create_anchor_statements.push(create_anchor_statement);
             }
@@ -618,11 +968,12 @@ pub fn gen_create_anchor_statements(
                             false => "const ",
                         };
                         let create_anchor_statement = format!(
-                            "{}$$lunas{}Anchor = $$lunasInsertEmpty($$lunas{}Ref,{});",
+                            "{}$$lunas{}Anchor = $$lunasInsertEmpty($$lunas{}Ref,{}{});",
                             variable_declaration_word,
                             if_block.if_blk_id,
                             if_block.parent_id,
-                            anchor_id
+                            anchor_id,
+                            hydration_key_arg(hydration, &if_block.element_location)
                         );
                         create_anchor_statements.push(create_anchor_statement);
                     }
@@ -646,11 +997,12 @@ pub fn gen_create_anchor_statements(
                         false => "const ",
                     };
                     let create_anchor_statement = format!(
-                        "{}$$lunas{}Anchor = $$lunasInsertEmpty($$lunas{}Ref,{});",
+                        "{}$$lunas{}Anchor = $$lunasInsertEmpty($$lunas{}Ref,{}{});",
                         variable_declaration_word,
                         custom_component.custom_component_block_id,
                         custom_component.parent_id,
-                        anchor_id
+                        anchor_id,
+                        hydration_key_arg(hydration, &custom_component.element_location)
                     );
                     create_anchor_statements.push(create_anchor_statement);
                 }
@@ -665,6 +1017,7 @@ pub fn gen_render_custom_component_statements(
     custom_component_block_info: &Vec<CustomComponentBlockInfo>,
     ctx: &Vec<String>,
     variable_names: &Vec<String>,
+    hydration: Option<&HydrationLocations>,
 ) -> Vec<String> {
     let mut render_custom_statements = vec![];
 
@@ -675,16 +1028,18 @@ pub fn gen_render_custom_component_statements(
         if custom_component_block.ctx != *ctx {
             continue;
         }
+        let key_arg = hydration_key_arg(hydration, &custom_component_block.element_location);
         if custom_component_block.have_sibling_elm {
             match custom_component_block.distance_to_next_elm > 1 {
                 true => {
                     render_custom_statements.push(format!(
-                        "const $$lunas{}Comp = {}({}).insert($$lunas{}Ref, $$lunas{}Anchor);",
+                        "const $$lunas{}Comp = {}({}).insert($$lunas{}Ref, $$lunas{}Anchor{});",
                         custom_component_block.custom_component_block_id,
                         custom_component_block.component_name,
                         custom_component_block.args.to_object(variable_names),
                         custom_component_block.parent_id,
-                        custom_component_block.custom_component_block_id
+                        custom_component_block.custom_component_block_id,
+                        key_arg
                     ));
                 }
                 false => {
@@ -693,22 +1048,30 @@ pub fn gen_render_custom_component_statements(
                         None => "null".to_string(),
                     };
                     render_custom_statements.push(format!(
-                        "const $$lunas{}Comp = {}({}).insert($$lunas{}Ref, {});",
+                        "const $$lunas{}Comp = {}({}).insert($$lunas{}Ref, {}{});",
                         custom_component_block.custom_component_block_id,
                         custom_component_block.component_name,
                         custom_component_block.args.to_object(variable_names),
                         custom_component_block.parent_id,
-                        anchor_ref_name
+                        anchor_ref_name,
+                        key_arg
                     ));
                 }
             }
         } else {
             render_custom_statements.push(format!(
-                "const $$lunas{}Comp = {}({}).mount($$lunas{}Ref);",
+                "const $$lunas{}Comp = {}({}).mount($$lunas{}Ref{});",
                 custom_component_block.custom_component_block_id,
                 custom_component_block.component_name,
                 custom_component_block.args.to_object(variable_names),
-                custom_component_block.parent_id
+                custom_component_block.parent_id,
+                key_arg
+            ));
+        }
+        if let Some(ref_name) = &custom_component_block.ref_name {
+            render_custom_statements.push(format!(
+                "$$lunasRefs.{} = $$lunas{}Comp;",
+                ref_name, custom_component_block.custom_component_block_id
             ));
         }
     }
@@ -728,3 +1091,107 @@ fn get_combined_binary_number(numbers: Vec<u32>) -> u32 {
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use lunas_parser::{parse_lunas_file, DetailedMetaData};
+
+    use crate::{
+        lunas_compile_from_block_with_options,
+        test_utils::{compile, contains_code},
+        CompileOptions, CompileTarget,
+    };
+
+    #[test]
+    fn skips_updates_of_bindings_without_dependencies() {
+        let source = "html:\n  <div><p :title=\"label\">${label}</p><b><i>${label}</i> ${count}</b></div>\n\nscript:\n  const label = \"a\";\n  let count = 0;\n  const add = () => count++;\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(!js.contains("this.valUpdateMap & 0"));
+        assert!(js.contains("this.valUpdateMap & 1 && $$lunasReplaceText("));
+    }
+
+    #[test]
+    fn imports_the_generated_routes_module() {
+        let source = "@useAutoRouting\nhtml:\n  <div><RouterView /></div>\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.contains(
+            "import { routes as $$lunasGeneratedRoutes } from \"./generated-routes.js\";"
+        ));
+        let options = CompileOptions {
+            generated_routes_path: Some("../generated-routes.js".to_string()),
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains("from \"../generated-routes.js\";"));
+    }
+
+    #[test]
+    fn defines_custom_elements() {
+        let source = "@customElement my-counter\n@input maxCount: number = 3\n@event change\n\nhtml:\n  <div><button @click=\"inc\">${count}</button></div>\n\nscript:\n  let count = 0;\n  function inc() {\n    count++;\n    Lunas.emit(\"change\", count);\n  }\n\nstyle:\n  button { color: red; }\n";
+        let options = CompileOptions {
+            target: CompileTarget::CustomElement,
+            ..Default::default()
+        };
+        let blocks = parse_lunas_file(source).unwrap();
+        let (js, css) = lunas_compile_from_block_with_options(&blocks, &options).unwrap();
+        assert!(js.contains(
+            "export default $$lunasDefineCustomElement(\"my-counter\", function(args = {}) {"
+        ));
+        assert!(js.contains("$$lunasEmit(\"change\", count.v);"));
+        // Inputs are mapped to their attributes, and the style is kept in the shadow root
+        assert!(contains_code(
+            &js,
+            "}, { \"maxCount\": [\"max-count\", \"number\", ()=>(3)] }, \"button { color: red; }\");"
+        ));
+        assert_eq!(css, None);
+
+        // Types are emitted as string literals, whatever characters they hold
+        let mut blocks = parse_lunas_file(source).unwrap();
+        for meta_data in blocks.detailed_meta_data.iter_mut() {
+            if let DetailedMetaData::PropsInput(input) = meta_data {
+                input.type_of_value = "\"s\" | 'm'".to_string();
+            }
+        }
+        let (js, _) = lunas_compile_from_block_with_options(&blocks, &options).unwrap();
+        assert!(contains_code(
+            &js,
+            "{ \"maxCount\": [\"max-count\", \"\\\"s\\\" | 'm'\", ()=>(3)] }"
+        ));
+
+        let undeclared = source.replace("@event change\n", "");
+        assert_eq!(
+            compile(&undeclared, &options).unwrap_err(),
+            "event `change` is not declared with @event"
+        );
+    }
+
+    #[test]
+    fn exposes_template_refs() {
+        let source = "@use Child from './child.lunas'\nhtml:\n  <div><input ref=\"box\"><p :if=\"show\"><span ref=\"inner\">x</span><Child ref=\"kid\" title=\"a\" /></p></div>\n\nscript:\n  let show = true;\n  const hide = () => { show = false; };\n  Lunas.onMount(() => Lunas.refs.box.focus());\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.contains("$$lunasOnMount(()=>$$lunasRefs.box.focus());"));
+        assert!(js.contains("$$lunasRefs.box = $$lunas"));
+        assert!(js.contains("$$lunasRefs.inner = $$lunas"));
+        assert!(js.contains("$$lunasRefs.kid = $$lunas"));
+        // Refs inside an if block are cleared when it is removed
+        assert!(js.contains("($$lunasRefs.inner = null, $$lunasRefs.kid = null, $$lunas"));
+        assert!(!js.contains("\"ref\""));
+        assert!(!js.contains("ref=\""));
+        assert_eq!(
+            compile(
+                "html:\n  <div><input ref=\"my-box\"></div>\n",
+                &CompileOptions::default()
+            )
+            .unwrap_err(),
+            "ref name `my-box` is not a valid identifier"
+        );
+
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains("<input>"));
+        assert!(contains_code(&js, "${Child({ \"title\": \"a\" })}"));
+    }
+}
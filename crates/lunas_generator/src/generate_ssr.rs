@@ -0,0 +1,294 @@
+use lunas_html_parser::{Element, Node};
+use lunas_parser::{DetailedBlock, DetailedMetaData, PropsInput, UseComponentStatement};
+
+use crate::{
+    consts::{DEFAULT_RUNTIME_PATH, ROUTER_COMPONENTS, VOID_ELEMENTS},
+    generate_statements::utils::create_indent,
+    structs::compile_options::CompileOptions,
+    transformers::{
+        codegen::{escape_template_text, string_literal, text_to_template},
+        hydration::hydration_key,
+        imports::resolve_import_source,
+        intrinsics::IntrinsicContext,
+        js_utils::{extract_imports, extract_module_script},
+        module_format::{find_used_helpers, generate_module},
+    },
+};
+
+// Generates a module whose default export renders the component into an HTML string.
+// Children used with `@use` are expected to be compiled for the server as well.
+// Nodes that the client inserts after mounting are wrapped in `<!--[location-->...<!--]-->`
+// so that the `Hydrate` target can find them.
+pub fn generate_ssr_from_blocks(
+    blocks: &DetailedBlock,
+    options: &CompileOptions,
+) -> Result<(String, Option<String>), String> {
+    let use_component_statements = blocks
+        .detailed_meta_data
+        .iter()
+        .filter_map(|meta_data| match meta_data {
+            DetailedMetaData::UseComponentStatement(use_component) => Some(use_component),
+            _ => None,
+        })
+        .collect::<Vec<&UseComponentStatement>>();
+    let inputs = blocks
+        .detailed_meta_data
+        .iter()
+        .filter_map(|meta_data| match meta_data {
+            DetailedMetaData::PropsInput(input) => Some(input),
+            _ => None,
+        })
+        .collect::<Vec<&PropsInput>>();
+    let component_names = use_component_statements
+        .iter()
+        .map(|use_component| use_component.component_name.clone())
+        .collect::<Vec<String>>();
+
+    let runtime_path = match &options.runtime_path {
+        Some(runtime_path) => runtime_path.clone(),
+        None => DEFAULT_RUNTIME_PATH.to_string(),
+    };
+
+    let intrinsics = IntrinsicContext {
+        inputs: inputs.iter().map(|i| i.variable_name.clone()).collect(),
+        env: Some(&options.env),
+        script: blocks
+            .detailed_language_blocks
+            .js
+            .as_ref()
+            .map_or("", |js_block| &js_block.raw),
+        module_scope: false,
+    };
+    let (imports_in_module_script, module_script) = extract_module_script(blocks, options)?;
+    let (imports_in_script, script) = extract_imports(blocks, &intrinsics)?;
+    let mut imports = vec![];
+    for import in imports_in_module_script
+        .into_iter()
+        .chain(imports_in_script)
+    {
+        imports.push(match &options.import_resolver {
+            Some(resolver) => resolve_import_source(&import, resolver)?,
+            None => import,
+        });
+    }
+    for use_component in use_component_statements {
+        let component_path = match &options.import_resolver {
+            Some(resolver) => resolver(&use_component.component_path)?,
+            None => use_component.component_path.clone(),
+        };
+        imports.push(format!(
+            "import {} from {};",
+            use_component.component_name,
+            string_literal(&component_path)
+        ));
+    }
+
+    let dom = &blocks.detailed_language_blocks.dom;
+    let root = match dom.children.len() {
+        0 => Err("Root element has no child".to_string()),
+        1 => Ok(&dom.children[0]),
+        _ => Err("Root element has more than one child".to_string()),
+    }?;
+    let html = render_node(root, &[0], false, &component_names)?;
+
+    let mut codes = vec![];
+    if let Some(props_assignment) = generate_ssr_input_variable_decl(&inputs) {
+        codes.push(props_assignment);
+    }
+    if !script.trim().is_empty() {
+        codes.push(script);
+    }
+    codes.push(format!("return `{}`;", html));
+
+    let code = codes
+        .iter()
+        .map(|c| create_indent(c))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let render_function = format!("function(args = {{}}) {{\n{}\n}}", code);
+    let runtime_helpers = find_used_helpers(
+        &render_function,
+        &[
+            "$$lunasEscapeHtml",
+            "$$lunasRenderAttr",
+            "$$lunasComputed",
+            "$$lunasEffect",
+            "$$lunasWatch",
+            "$$lunasOnMount",
+            "$$lunasOnUpdate",
+            "$$lunasOnDestroy",
+            "$$lunasNextTick",
+            "$$lunasRefs",
+        ],
+    );
+    let full_js_code = generate_module(
+        &runtime_helpers,
+        &runtime_path,
+        &imports,
+        &[module_script],
+        &render_function,
+        options,
+    )?;
+
+    Ok((full_js_code, blocks.detailed_language_blocks.css.clone()))
+}
+
+// Inputs are passed as plain values on the server, so initial values become destructuring defaults
+fn generate_ssr_input_variable_decl(inputs: &[&PropsInput]) -> Option<String> {
+    if inputs.is_empty() {
+        return None;
+    }
+    let props = inputs
+        .iter()
+        .map(|input| match &input.initial_value {
+            Some(initial_value) => format!("{} = {}", input.variable_name, initial_value),
+            None => input.variable_name.clone(),
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    Some(format!("const {{ {} }} = args;", props))
+}
+
+fn render_node(
+    node: &Node,
+    location: &[usize],
+    has_siblings: bool,
+    component_names: &[String],
+) -> Result<String, String> {
+    match node {
+        // The client only creates text nodes by itself when they have siblings
+        Node::Text(text) => match has_siblings {
+            true => Ok(render_region(location, &render_text(text))),
+            false => Ok(render_text(text)),
+        },
+        Node::Element(element) => render_element(element, location, component_names),
+        // Keeps the indexes of the following siblings in line with the template
+        Node::Comment(_) => Ok("<!---->".to_string()),
+    }
+}
+
+fn render_region(location: &[usize], content: &str) -> String {
+    format!("<!--[{}-->{}<!--]-->", hydration_key(location), content)
+}
+
+// Give: Hello ${name}!
+// Want: Hello ${$$lunasEscapeHtml(name)}!
+fn render_text(text: &str) -> String {
+    text_to_template(text, |expression| {
+        format!("$$lunasEscapeHtml({})", expression)
+    })
+}
+
+fn render_element(
+    element: &Element,
+    location: &[usize],
+    component_names: &[String],
+) -> Result<String, String> {
+    let is_router_component = ROUTER_COMPONENTS.contains(&element.name.as_str());
+    let is_component = is_router_component || component_names.contains(&element.name);
+    let rendered = if is_router_component {
+        // Routes are resolved on the client
+        "".to_string()
+    } else if is_component {
+        render_component(element)
+    } else {
+        let mut html = format!("<{}{}>", element.name, render_attributes(element));
+        if !VOID_ELEMENTS.contains(&element.name.as_str()) {
+            let has_siblings = element.children.len() > 1;
+            for (index, child) in element.children.iter().enumerate() {
+                let mut child_location = location.to_vec();
+                child_location.push(index);
+                html.push_str(&render_node(
+                    child,
+                    &child_location,
+                    has_siblings,
+                    component_names,
+                )?);
+            }
+            html.push_str(&format!("</{}>", element.name));
+        }
+        html
+    };
+
+    let rendered = match element.attributes.get(":if") {
+        Some(Some(condition)) => format!("${{({}) ? `{}` : \"\"}}", condition, rendered),
+        Some(None) => Err("value of attribute :if is null".to_string())?,
+        None => rendered,
+    };
+    match is_component || element.attributes.contains_key(":if") {
+        true => Ok(render_region(location, &rendered)),
+        false => Ok(rendered),
+    }
+}
+
+fn sorted_attributes(element: &Element) -> Vec<(&String, &Option<String>)> {
+    let mut attributes = element
+        .attributes
+        .iter()
+        .filter(|(key, _)| !key.starts_with('@') && key.as_str() != ":if" && key.as_str() != "ref")
+        .collect::<Vec<_>>();
+    attributes.sort_by(|a, b| a.0.cmp(b.0));
+    attributes
+}
+
+fn render_attributes(element: &Element) -> String {
+    let mut rendered = String::new();
+    for (key, value) in sorted_attributes(element) {
+        let bound_name = key.strip_prefix("::").or_else(|| key.strip_prefix(':'));
+        match (bound_name, value) {
+            (Some(name), Some(value)) => rendered.push_str(&format!(
+                "${{$$lunasRenderAttr({}, {})}}",
+                string_literal(name),
+                value
+            )),
+            (Some(_), None) => {}
+            (None, Some(value)) => rendered.push_str(&format!(
+                " {}=\"{}\"",
+                key,
+                escape_template_text(&value.replace('"', "&quot;"))
+            )),
+            (None, None) => rendered.push_str(&format!(" {}", key)),
+        }
+    }
+    rendered
+}
+
+fn render_component(element: &Element) -> String {
+    let args = sorted_attributes(element)
+        .into_iter()
+        .map(|(key, value)| match (key.strip_prefix(':'), value) {
+            (Some(name), Some(value)) => format!("{}: {}", string_literal(name), value),
+            (_, None) => format!("{}: true", string_literal(key.trim_start_matches(':'))),
+            (None, Some(value)) => format!("{}: {}", string_literal(key), string_literal(value)),
+        })
+        .collect::<Vec<String>>();
+    format!("${{{}({{{}}})}}", element.name, args.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{compile, contains_code},
+        CompileOptions, CompileTarget,
+    };
+
+    #[test]
+    fn renders_markup_into_a_string() {
+        let source = "@use Child from './child.lunas'\n\nhtml:\n  <div><h1 :if=\"show\">${title}</h1><Child title=\"a\" /></div>\n\nscript:\n  let title = \"<x>\";\n  let show = true;\n";
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains("let title = \"<x>\";"));
+        // If blocks are rendered in place, and child components are called with their args
+        assert!(contains_code(
+            &js,
+            "`<div><!--[0.0-->${(show) ? `<h1>${$$lunasEscapeHtml(title)}</h1>` : \"\"}<!--]-->"
+        ));
+        assert!(contains_code(
+            &js,
+            "<!--[0.1-->${Child({ \"title\": \"a\" })}<!--]--></div>`;"
+        ));
+    }
+}
@@ -1,18 +1,23 @@
 use crate::{
     generate_js::{
-        create_event_listener, gen_create_anchor_statements, gen_ref_getter_from_needed_ids,
-        gen_render_custom_component_statements,
+        create_event_listener, gen_create_anchor_statements, gen_ref_assignments,
+        gen_ref_getter_from_needed_ids, gen_render_custom_component_statements,
     },
     orig_html_struct::structs::NodeContent,
     structs::transform_info::{
         ActionAndTarget, CustomComponentBlockInfo, IfBlockInfo, NeededIdName, TextNodeRendererGroup,
     },
-    transformers::html_utils::create_lunas_internal_component_statement,
+    transformers::{
+        codegen::string_literal,
+        html_utils::create_lunas_internal_component_statement,
+        hydration::{hydration_key_arg, HydrationLocations},
+    },
 };
 
 use super::utils::create_indent;
 
 // TODO: Many of the following functions are similar to top-level component creation functions, such as creating refs and rendering if statements. Consider refactoring them into a single function.
+#[allow(clippy::too_many_arguments)]
 pub fn gen_render_if_blk_func(
     if_block_info: &Vec<IfBlockInfo>,
     needed_ids: &Vec<NeededIdName>,
@@ -20,15 +25,15 @@ pub fn gen_render_if_blk_func(
     text_node_renderer: &TextNodeRendererGroup,
     custom_component_blocks_info: &Vec<CustomComponentBlockInfo>,
     variable_names: &Vec<String>,
-) -> Vec<String> {
+    hydration: Option<&HydrationLocations>,
+    templates: &mut Vec<String>,
+) -> Result<Vec<String>, String> {
     let mut render_if = vec![];
 
     for if_block in if_block_info.iter() {
         // create element
         let create_internal_element_statement = match &if_block.node.content {
-            NodeContent::Element(elm) => {
-                create_lunas_internal_component_statement(elm, "$$createLunasElement")
-            }
+            NodeContent::Element(elm) => create_lunas_internal_component_statement(elm, templates)?,
             _ => panic!(),
         };
 
@@ -38,6 +43,7 @@ pub fn gen_render_if_blk_func(
             needed_ids,
             &Some(if_block),
             &Some(&if_block.ctx_under_if),
+            hydration,
         );
         rendering_statement.push(ref_getter_str.as_str());
 
@@ -45,14 +51,18 @@ pub fn gen_render_if_blk_func(
         if ev_listener_code.len() != 0 {
             rendering_statement.extend(ev_listener_code.iter().map(|x| x.as_str()));
         }
+        let ref_assignments = gen_ref_assignments(&if_block.refs, &if_block.ctx_under_if);
+        rendering_statement.extend(ref_assignments.iter().map(|x| x.as_str()));
 
-        let gen_anchor = gen_create_anchor_statements(&text_node_renderer, &if_block.ctx_under_if);
+        let gen_anchor =
+            gen_create_anchor_statements(text_node_renderer, &if_block.ctx_under_if, hydration);
         rendering_statement.extend(gen_anchor.iter().map(|x| x.as_str()));
 
         let render_child_component = gen_render_custom_component_statements(
             &custom_component_blocks_info,
             &if_block.ctx_under_if,
             &variable_names,
+            hydration,
         );
         if render_child_component.len() != 0 {
             rendering_statement.extend(render_child_component.iter().map(|x| x.as_str()));
@@ -65,8 +75,9 @@ pub fn gen_render_if_blk_func(
             let mut child_block_rendering_exec = vec![];
             for child_if in children {
                 child_block_rendering_exec.push(format!(
-                    "\n{} && $$lunasRenderIfBlock(\"{}\");",
-                    child_if.condition, &child_if.if_blk_id
+                    "\n{} && $$lunasRenderIfBlock({});",
+                    child_if.condition,
+                    string_literal(&child_if.if_blk_id)
                 ));
             }
             child_block_rendering_exec
@@ -101,12 +112,13 @@ pub fn gen_render_if_blk_func(
             r#""{}",
 ()=>{},
 ()=>[{},{}],
-{},"#,
+{}{},"#,
             if_block.target_if_blk_id,
             create_internal_element_statement,
             name_of_parent_of_if_blk,
             name_of_anchor_of_if_blk,
             if_on_create,
+            hydration_key_arg(hydration, &if_block.element_location),
         );
 
         let create_if_func = format!(
@@ -119,10 +131,11 @@ pub fn gen_render_if_blk_func(
         render_if.push(create_if_func);
         if if_block.ctx_over_if.len() == 0 {
             render_if.push(format!(
-                "{} && $$lunasRenderIfBlock(\"{}\")",
-                if_block.condition, &if_block.if_blk_id
+                "{} && $$lunasRenderIfBlock({})",
+                if_block.condition,
+                string_literal(&if_block.if_blk_id)
             ));
         }
     }
-    render_if
+    Ok(render_if)
 }
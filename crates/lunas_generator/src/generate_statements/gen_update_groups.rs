@@ -0,0 +1,329 @@
+use crate::{
+    generate_js::gen_ref_clearing,
+    structs::{
+        transform_info::{IfBlockInfo, VariableNameAndAssignedNumber},
+        transform_targets::NodeAndReactiveInfo,
+    },
+    transformers::codegen::{split_template, string_literal},
+};
+
+use super::utils::create_indent;
+
+/// Statements run when any variable in `dep_bits` changes, under the same if-block context.
+struct UpdateGroup {
+    dep_bits: u32,
+    ctx_num: usize,
+    bindings: Vec<Binding>,
+}
+
+enum Binding {
+    Text {
+        content: String,
+        target: String,
+    },
+    Attr {
+        key: String,
+        value: String,
+        target: String,
+    },
+}
+
+// Give: `<p :title="count">${count}</p>` and `<b :if="show">${name}</b>`
+// Want: $$lunasUpdateComponent([[4, function () { show.v ? ... }], [1, function () { ... }], [2, function () { if (...) { ... } }]]);
+/// Generates the update function as closures keyed by the bits of the variables they depend on,
+/// so that a change only runs the statements depending on it.
+/// Bindings that depend on the same variables share the values of their repeated expressions.
+pub fn gen_grouped_update_func(
+    elm_and_variable_relations: &Vec<NodeAndReactiveInfo>,
+    variables: &[VariableNameAndAssignedNumber],
+    if_blocks_infos: &Vec<IfBlockInfo>,
+) -> Result<String, String> {
+    let mut closures = vec![];
+
+    // If blocks are toggled in order, so that outer blocks are rendered before their contents
+    let mut if_block_groups: Vec<(u32, Vec<String>)> = vec![];
+    for (index, if_block_info) in if_blocks_infos.iter().enumerate() {
+        let rendering_cond = match !if_block_info.ctx_over_if.is_empty() {
+            true => format!(
+                "(!((this.blkRenderedMap & {0}) ^ {0})) && ",
+                if_block_info.generate_ctx_num(if_blocks_infos)
+            ),
+            false => "".to_string(),
+        };
+        let statement = format!(
+            "{}({} ? $$lunasRenderIfBlock({}) : (this.blkRenderedMap & {3}) && ({5}$$lunas{4}Ref.remove(), $$lunas{4}Ref = null, this.blkRenderedMap ^= {3}));",
+            rendering_cond,
+            if_block_info.condition,
+            string_literal(&if_block_info.if_blk_id),
+            1u64 << index,
+            if_block_info.if_blk_id,
+            gen_ref_clearing(&if_block_info.ref_names),
+        );
+        let dep_bits = get_dep_bits(&if_block_info.condition_dep_vars, variables);
+        // Conditions on values that never change are only checked on the first render
+        if dep_bits == 0 {
+            continue;
+        }
+        match if_block_groups.last_mut() {
+            Some((bits, statements)) if *bits == dep_bits => statements.push(statement),
+            _ => if_block_groups.push((dep_bits, vec![statement])),
+        }
+    }
+    for (dep_bits, statements) in if_block_groups {
+        closures.push(gen_closure(dep_bits, &statements));
+    }
+
+    let mut groups: Vec<UpdateGroup> = vec![];
+    for relation in elm_and_variable_relations {
+        match relation {
+            NodeAndReactiveInfo::ElmAndReactiveAttributeRelation(elm_and_attr_relation) => {
+                for attr in &elm_and_attr_relation.reactive_attr {
+                    add_binding(
+                        &mut groups,
+                        get_dep_bits(&attr.variable_names, variables),
+                        elm_and_attr_relation.generate_ctx_num(if_blocks_infos),
+                        Binding::Attr {
+                            key: attr.attribute_key.clone(),
+                            value: attr.content_of_attr.clone(),
+                            target: format!("$$lunas{}Ref", elm_and_attr_relation.elm_id),
+                        },
+                    );
+                }
+            }
+            NodeAndReactiveInfo::ElmAndVariableRelation(elm_and_variable_relation) => add_binding(
+                &mut groups,
+                get_dep_bits(&elm_and_variable_relation.dep_vars, variables),
+                elm_and_variable_relation.generate_ctx_num(if_blocks_infos),
+                Binding::Text {
+                    content: elm_and_variable_relation
+                        .content_of_element
+                        .trim()
+                        .to_string(),
+                    target: format!("$$lunas{}Ref", elm_and_variable_relation.elm_id),
+                },
+            ),
+            NodeAndReactiveInfo::TextAndVariableContentRelation(txt_and_var_content) => {
+                add_binding(
+                    &mut groups,
+                    get_dep_bits(&txt_and_var_content.dep_vars, variables),
+                    txt_and_var_content.generate_ctx_num(if_blocks_infos),
+                    Binding::Text {
+                        content: txt_and_var_content.content_of_element.trim().to_string(),
+                        target: format!("$$lunas{}Text", txt_and_var_content.text_node_id),
+                    },
+                )
+            }
+        }
+    }
+    for group in groups {
+        let statements = gen_binding_statements(&group.bindings)?;
+        let statements = match group.ctx_num {
+            0 => statements,
+            // Blocks rendered in this update already show the current values
+            ctx_num => vec![format!(
+                "if (!((this.blkRenderedMap & {0}) ^ {0}) && (this.blkUpdateMap & {0}) ^ {0}) {{\n{1}\n}}",
+                ctx_num,
+                statements
+                    .iter()
+                    .map(|s| create_indent(s))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            )],
+        };
+        closures.push(gen_closure(group.dep_bits, &statements));
+    }
+
+    Ok(format!(
+        "$$lunasUpdateComponent([\n{}\n]);",
+        closures
+            .iter()
+            .map(|c| create_indent(c))
+            .collect::<Vec<String>>()
+            .join(",\n")
+    ))
+}
+
+fn get_dep_bits(dep_vars: &[String], variables: &[VariableNameAndAssignedNumber]) -> u32 {
+    variables
+        .iter()
+        .filter(|v| dep_vars.contains(&v.name))
+        .fold(0, |bits, v| bits | v.assignment)
+}
+
+fn add_binding(groups: &mut Vec<UpdateGroup>, dep_bits: u32, ctx_num: usize, binding: Binding) {
+    // A binding without dependencies keeps the value it was created with
+    if dep_bits == 0 {
+        return;
+    }
+    match groups
+        .iter_mut()
+        .find(|g| g.dep_bits == dep_bits && g.ctx_num == ctx_num)
+    {
+        Some(group) => group.bindings.push(binding),
+        None => groups.push(UpdateGroup {
+            dep_bits,
+            ctx_num,
+            bindings: vec![binding],
+        }),
+    }
+}
+
+fn gen_closure(dep_bits: u32, statements: &[String]) -> String {
+    format!(
+        "[{}, function () {{\n{}\n}}]",
+        dep_bits,
+        statements
+            .iter()
+            .map(|s| create_indent(s))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+}
+
+// Give: `${$$lunasEscapeHtml(count.v)}!` and `count.v` as the value of an attribute
+// Want: const $$lunasValue0 = count.v; with `${$$lunasEscapeHtml($$lunasValue0)}!` and `$$lunasValue0`
+fn gen_binding_statements(bindings: &[Binding]) -> Result<Vec<String>, String> {
+    // Text is escaped where it is inserted, so that it shares values with attributes
+    let split_bindings = bindings
+        .iter()
+        .map(|binding| match binding {
+            Binding::Text { content, .. } => {
+                let (strings, expressions) = split_template(content)?;
+                let expressions = expressions
+                    .iter()
+                    .map(|e| {
+                        match e
+                            .strip_prefix("$$lunasEscapeHtml(")
+                            .and_then(|e| e.strip_suffix(')'))
+                        {
+                            Some(value) => (value.to_string(), true),
+                            None => (e.clone(), false),
+                        }
+                    })
+                    .collect::<Vec<(String, bool)>>();
+                Ok((strings, expressions))
+            }
+            Binding::Attr { value, .. } => Ok((vec![], vec![(value.clone(), false)])),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut counts: Vec<(&str, usize)> = vec![];
+    for (_, expressions) in &split_bindings {
+        for (expression, _) in expressions {
+            match counts.iter_mut().find(|(e, _)| e == expression) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((expression, 1)),
+            }
+        }
+    }
+    let shared = counts
+        .iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(expression, _)| *expression)
+        .collect::<Vec<&str>>();
+    let value_of = |expression: &str| match shared.iter().position(|e| *e == expression) {
+        Some(index) => format!("$$lunasValue{}", index),
+        None => expression.to_string(),
+    };
+
+    let mut statements = shared
+        .iter()
+        .enumerate()
+        .map(|(index, expression)| format!("const $$lunasValue{} = {};", index, expression))
+        .collect::<Vec<String>>();
+    for (binding, (strings, expressions)) in bindings.iter().zip(&split_bindings) {
+        statements.push(match binding {
+            Binding::Text { target, .. } => {
+                let mut template = strings[0].clone();
+                for ((expression, escaped), s) in expressions.iter().zip(&strings[1..]) {
+                    template.push_str(&match escaped {
+                        true => format!("${{$$lunasEscapeHtml({})}}", value_of(expression)),
+                        false => format!("${{{}}}", value_of(expression)),
+                    });
+                    template.push_str(s);
+                }
+                format!("$$lunasReplaceText(`{}`, {});", template, target)
+            }
+            Binding::Attr { key, target, .. } => format!(
+                "$$lunasReplaceAttr({}, {}, {});",
+                string_literal(key),
+                value_of(&expressions[0].0),
+                target
+            ),
+        });
+    }
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gen_binding_statements, Binding};
+    use crate::{
+        test_utils::{compile, contains_code},
+        CompileOptions, UpdateDispatch,
+    };
+
+    #[test]
+    fn shares_repeated_expressions() {
+        let bindings = vec![
+            Binding::Text {
+                content: "${$$lunasEscapeHtml(count.v * 2)} and ${$$lunasEscapeHtml(name.v)}"
+                    .to_string(),
+                target: "$$lunasaRef".to_string(),
+            },
+            Binding::Attr {
+                key: "title".to_string(),
+                value: "count.v * 2".to_string(),
+                target: "$$lunasbRef".to_string(),
+            },
+        ];
+        assert_eq!(
+            gen_binding_statements(&bindings).unwrap(),
+            vec![
+                "const $$lunasValue0 = count.v * 2;",
+                "$$lunasReplaceText(`${$$lunasEscapeHtml($$lunasValue0)} and ${$$lunasEscapeHtml(name.v)}`, $$lunasaRef);",
+                "$$lunasReplaceAttr(\"title\", $$lunasValue0, $$lunasbRef);"
+            ]
+        );
+    }
+
+    #[test]
+    fn dispatches_updates_per_variable() {
+        let options = CompileOptions {
+            update_dispatch: UpdateDispatch::PerVariable,
+            ..Default::default()
+        };
+        let source = "html:\n  <div><p :title=\"count * 2\">${count * 2}</p><i>${name}</i><b :if=\"show\">${name}</b></div>\n\nscript:\n  let count = 0;\n  let name = \"a\";\n  let show = true;\n  const update = () => { count++; name += \"!\"; show = !show; };\n";
+        let js = compile(source, &options).unwrap();
+        assert!(contains_code(
+            &js,
+            "$$lunasUpdateComponent([[4, function() { (show.v ? $$lunasRenderIfBlock("
+        ));
+        // Bindings of the same variables share one closure and its repeated expressions
+        assert!(contains_code(&js, "[1, function() { const $$lunasValue0 = count.v * 2; $$lunasReplaceAttr(\"title\", $$lunasValue0, "));
+        assert!(contains_code(
+            &js,
+            "$$lunasReplaceText(`${$$lunasEscapeHtml($$lunasValue0)}`, "
+        ));
+        assert!(contains_code(
+            &js,
+            "[2, function() { $$lunasReplaceText(`${$$lunasEscapeHtml(name.v)}`, "
+        ));
+        // Bindings inside an if block only update while it is rendered
+        assert!(contains_code(&js, "[2, function() { if (!((this.blkRenderedMap & 1) ^ 1) && (this.blkUpdateMap & 1) ^ 1) {"));
+        assert!(!js.contains("this.valUpdateMap &"));
+    }
+
+    #[test]
+    fn skips_bindings_without_dependencies() {
+        let options = CompileOptions {
+            update_dispatch: UpdateDispatch::PerVariable,
+            ..Default::default()
+        };
+        let source = "html:\n  <div><p :title=\"label\">${label}</p><i>${count}</i></div>\n\nscript:\n  const label = \"a\";\n  let count = 0;\n  const add = () => count++;\n";
+        let js = compile(source, &options).unwrap();
+        assert!(!js.contains("[0,"));
+        assert!(!js.contains("$$lunasReplaceAttr"));
+        assert!(js.contains("$$lunasReplaceText(`${$$lunasEscapeHtml(count.v)}`"));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod gen_if_blk;
+pub mod gen_update_groups;
 pub mod utils;
@@ -0,0 +1,91 @@
+use lunas_parser::DetailedBlock;
+
+use crate::{
+    consts::DEFAULT_RUNTIME_PATH,
+    structs::compile_options::{CompileOptions, CompileTarget},
+    transformers::{
+        codegen::string_literal,
+        exports::{find_module_exports, split_exports, ExportedBinding},
+        imports::resolve_import_source,
+    },
+};
+
+// Generates the type declarations (`.d.ts`) of the module compiled from the component.
+// On the client, the bindings exported from `script:` are members of the component instance,
+// while those exported from `script module:` are exported from the module on every target.
+pub fn generate_types_from_blocks(
+    blocks: &DetailedBlock,
+    options: &CompileOptions,
+) -> Result<String, String> {
+    let exports = match &blocks.detailed_language_blocks.js {
+        Some(js_block) => split_exports(&js_block.ast, &js_block.raw)?.2,
+        None => vec![],
+    };
+    let (module_exports, reexports) = match &blocks.detailed_language_blocks.module_js {
+        Some(js_block) => {
+            let exports = find_module_exports(&js_block.ast, &js_block.raw)?;
+            (exports.bindings, exports.reexports)
+        }
+        None => (vec![], vec![]),
+    };
+    let runtime_path = match &options.runtime_path {
+        Some(runtime_path) => runtime_path.clone(),
+        None => DEFAULT_RUNTIME_PATH.to_string(),
+    };
+
+    let mut declarations = match options.target {
+        CompileTarget::Server => vec![
+            "declare const component: (args?: { [key: string]: any }) => string;".to_string(),
+        ],
+        CompileTarget::CustomElement => {
+            vec!["declare const component: CustomElementConstructor;".to_string()]
+        }
+        CompileTarget::Client | CompileTarget::Hydrate => vec![
+            format!(
+                "import type {{ LunasModuleExports }} from {};",
+                string_literal(&runtime_path)
+            ),
+            format!("export type Exports = {};", gen_exports_type(&exports)),
+            "declare const component: (args?: { [key: string]: any }) => LunasModuleExports & Exports;"
+                .to_string(),
+        ],
+    };
+    declarations.extend(
+        module_exports
+            .iter()
+            .map(|export| match export.is_function {
+                true => format!(
+                    "export declare function {}(...args: any[]): any;",
+                    export.name
+                ),
+                false => format!("export declare const {}: any;", export.name),
+            }),
+    );
+    for reexport in reexports {
+        declarations.push(match &options.import_resolver {
+            Some(resolver) => resolve_import_source(&reexport, resolver)?,
+            None => reexport,
+        });
+    }
+    Ok(format!(
+        "{}\nexport default component;\n",
+        declarations.join("\n")
+    ))
+}
+
+// Give: export function reset() {} export let count = 0;
+// Want: { reset: (...args: any[]) => any; readonly count: any; }
+fn gen_exports_type(exports: &[ExportedBinding]) -> String {
+    if exports.is_empty() {
+        return "{}".to_string();
+    }
+    let members = exports
+        .iter()
+        .map(|export| match export.is_function {
+            true => format!("    {}: (...args: any[]) => any;", export.name),
+            // Assigning to a value from outside doesn't change the binding in the component
+            false => format!("    readonly {}: any;", export.name),
+        })
+        .collect::<Vec<String>>();
+    format!("{{\n{}\n}}", members.join("\n"))
+}
@@ -1,11 +1,22 @@
 mod consts;
 mod generate_js;
+mod generate_ssr;
 mod generate_statements;
+mod generate_types;
 mod orig_html_struct;
 mod structs;
+#[cfg(test)]
+mod test_utils;
 mod transformers;
 use generate_js::generate_js_from_blocks;
+use generate_ssr::generate_ssr_from_blocks;
+use generate_types::generate_types_from_blocks;
 use lunas_parser::DetailedBlock;
+pub use consts::{GENERATED_ROUTES_FILE_NAME, ROUTER_COMPONENTS};
+pub use structs::compile_options::{
+    CompileOptions, CompileTarget, ImportResolver, ModuleFormat, UpdateDispatch,
+};
+use transformers::minify::minify_block;
 #[macro_use]
 extern crate lazy_static;
 
@@ -13,6 +24,37 @@ pub fn lunas_compile_from_block(
     b: &DetailedBlock,
     runtime_path: Option<String>,
 ) -> Result<(String, Option<String>), String> {
-    let compiled_code = generate_js_from_blocks(b, runtime_path);
-    compiled_code
+    let options = CompileOptions {
+        runtime_path,
+        ..Default::default()
+    };
+    lunas_compile_from_block_with_options(b, &options)
+}
+
+pub fn lunas_compile_from_block_with_options(
+    b: &DetailedBlock,
+    options: &CompileOptions,
+) -> Result<(String, Option<String>), String> {
+    let minified;
+    let b = match options.minify {
+        true => {
+            minified = minify_block(b);
+            &minified
+        }
+        false => b,
+    };
+    match options.target {
+        CompileTarget::Client | CompileTarget::Hydrate | CompileTarget::CustomElement => {
+            generate_js_from_blocks(b, options)
+        }
+        CompileTarget::Server => generate_ssr_from_blocks(b, options),
+    }
+}
+
+/// Generates the type declarations of the module that `options` compiles the component into.
+pub fn lunas_generate_types_from_block(
+    b: &DetailedBlock,
+    options: &CompileOptions,
+) -> Result<String, String> {
+    generate_types_from_blocks(b, options)
 }
@@ -1,7 +1,7 @@
 use lunas_html_parser::{Dom as RawDom, Element as RawElm, Node as RawNode};
 use std::collections::HashMap;
 
-use crate::transformers::utils::UUID_GENERATOR;
+use crate::{consts::VOID_ELEMENTS, transformers::utils::UUID_GENERATOR};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Node {
@@ -120,7 +120,7 @@ impl ToString for Node {
         match &self.content {
             NodeContent::Element(elm) => elm.to_string(),
             NodeContent::TextNode(text) => text.clone(),
-            NodeContent::Comment(comment) => comment.clone(),
+            NodeContent::Comment(comment) => format!("<!--{}-->", comment),
         }
     }
 }
@@ -134,13 +134,19 @@ impl ToString for Element {
 
         for (key, value) in attributes {
             if let Some(value) = value {
+                // Bound values are `${...}` expressions, while `${` in static values is escaped
+                let value = match value.starts_with("${") && value.ends_with('}') {
+                    true => value.clone(),
+                    false => value.replace('"', "&quot;"),
+                };
                 attribute_str.push_str(&format!(" {}=\"{}\"", key, value));
             } else {
                 attribute_str.push_str(&format!(" {}", key));
             }
         }
 
-        match self.children.len() == 0 {
+        // An empty element other than a void one is closed, since HTML ignores the `/` in `<div />`
+        match self.children.is_empty() && VOID_ELEMENTS.contains(&self.tag_name.as_str()) {
             true => {
                 format!("<{}{} />", self.tag_name, attribute_str)
             }
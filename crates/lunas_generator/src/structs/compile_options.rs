@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+/// Resolves an import specifier written in a component into the specifier to emit.
+pub type ImportResolver = Box<dyn Fn(&str) -> Result<String, String>>;
+
+/// What the compiled module does.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CompileTarget {
+    /// A component that renders itself into the DOM.
+    #[default]
+    Client,
+    /// A function that renders the component into an HTML string, for server-side rendering.
+    Server,
+    /// A `Client` component that can also attach to markup rendered by the `Server` target.
+    Hydrate,
+    /// A class extending `HTMLElement` that renders the component into a shadow root.
+    /// Requires `@customElement` and registers the component under that tag name.
+    CustomElement,
+}
+
+/// How the compiled module imports its dependencies and exposes the component.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ModuleFormat {
+    /// `import` declarations and `export default`.
+    #[default]
+    Esm,
+    /// `require` calls and `module.exports`.
+    CommonJs,
+    /// A function called with its dependencies read from globals, for `<script>` tags.
+    Iife,
+    /// Works as CommonJS, as an AMD module, or with globals like `Iife`.
+    Umd,
+}
+
+/// How the update function of a component finds the bindings to refresh after variables change.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum UpdateDispatch {
+    /// One function that checks every binding and if block against the changed variables.
+    #[default]
+    Linear,
+    /// A closure for each set of variables that bindings depend on, run only when one of them changes.
+    /// Repeated expressions among the bindings of a closure are evaluated once.
+    PerVariable,
+}
+
+#[derive(Default)]
+pub struct CompileOptions {
+    pub runtime_path: Option<String>,
+    pub target: CompileTarget,
+    /// Applied to `@use` paths and to `import` declarations in `script:` blocks.
+    /// Specifiers are emitted verbatim when this is `None`.
+    pub import_resolver: Option<ImportResolver>,
+    /// Module that `@useAutoRouting` imports its routes from.
+    /// Defaults to the `generated-routes.js` next to the component, where a pages directory
+    /// next to it has its routes module written.
+    pub generated_routes_path: Option<String>,
+    pub format: ModuleFormat,
+    /// Global variable that `Iife` and `Umd` builds assign the component to.
+    pub global_name: Option<String>,
+    /// Maps the specifier of an import to the global variable holding it in `Iife` and `Umd` builds.
+    /// The runtime is always read from the `Lunas` global.
+    pub globals: Option<ImportResolver>,
+    /// Source of the runtime as an ES module.
+    /// When set, the runtime helpers used by the component are copied into the output instead of being imported.
+    pub inlined_runtime: Option<String>,
+    /// Production build: collapses whitespace in the markup, drops HTML comments,
+    /// minifies the CSS, and runs the module through swc's minifier.
+    pub minify: bool,
+    pub update_dispatch: UpdateDispatch,
+    /// Values that `Lunas.env.<KEY>` is replaced with.
+    /// Reading a key that is missing is a compile error.
+    pub env: HashMap<String, String>,
+}
@@ -1,4 +1,5 @@
 // TODO: 1Struct 1ファイルにする
 
+pub mod compile_options;
 pub mod transform_info;
 pub mod transform_targets;
@@ -2,7 +2,10 @@ use std::collections::HashMap;
 
 use crate::{
     orig_html_struct::structs::Node,
-    transformers::utils::{append_v_to_vars_in_html, convert_non_reactive_to_obj},
+    transformers::{
+        codegen::string_literal,
+        utils::{append_v_to_vars_in_html, convert_non_reactive_to_obj},
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -45,6 +48,14 @@ pub struct ActionAndTarget {
     pub ctx: Vec<String>,
 }
 
+/// An element with a `ref` attribute, stored in `Lunas.refs` while it is rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefAndTarget {
+    pub ref_name: String,
+    pub target: String,
+    pub ctx: Vec<String>,
+}
+
 // FIXME: 命名
 #[derive(Debug)]
 pub struct NeededIdName {
@@ -81,17 +92,19 @@ impl ToString for EventTarget {
 }
 
 impl EventTarget {
-    pub fn new(content: String, variables: &Vec<String>) -> Self {
+    pub fn new(content: String, variables: &Vec<String>) -> Result<Self, String> {
         // FIXME: (P1) This is a hacky way to check if the content is a statement or a function
         if word_is_one_word(content.as_str()) {
-            EventTarget::RefToFunction(content)
+            Ok(EventTarget::RefToFunction(content))
         } else {
-            EventTarget::Statement(append_v_to_vars_in_html(content.as_str(), &variables).0)
+            Ok(EventTarget::Statement(
+                append_v_to_vars_in_html(content.as_str(), variables)?.0,
+            ))
         }
     }
 }
 
-fn word_is_one_word(word: &str) -> bool {
+pub fn word_is_one_word(word: &str) -> bool {
     word.chars()
         .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
 }
@@ -110,6 +123,10 @@ pub struct IfBlockInfo {
     pub ctx_over_if: Vec<String>,
     pub if_blk_id: String,
     pub element_location: Vec<usize>,
+    /// Elements with a `ref` attribute directly under the block, set when it is rendered.
+    pub refs: Vec<RefAndTarget>,
+    /// Refs of the elements and components inside the block, cleared when it is removed.
+    pub ref_names: Vec<String>,
 }
 
 impl IfBlockInfo {
@@ -155,6 +172,7 @@ pub struct CustomComponentBlockInfo {
     pub element_location: Vec<usize>,
     pub is_routing_component: bool,
     pub args: ComponentArgs,
+    pub ref_name: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -170,12 +188,12 @@ impl ComponentArg {
             // TODO: delete unwrap and add support for boolean attributes
             let value_converted_to_obj =
                 convert_non_reactive_to_obj(&self.value.clone().unwrap().as_str(), variable_names);
-            format!("\"{}\": {}", self.name, value_converted_to_obj)
+            format!("{}: {}", string_literal(&self.name), value_converted_to_obj)
         } else {
             format!(
-                "\"{}\": $$lunasCreateNonReactive(\"{}\")",
-                self.name,
-                self.value.clone().unwrap()
+                "{}: $$lunasCreateNonReactive({})",
+                string_literal(&self.name),
+                string_literal(&self.value.clone().unwrap())
             )
         }
     }
@@ -229,7 +247,7 @@ pub struct ManualRendererForTextNode {
 
 pub enum TextNodeRenderer {
     ManualRenderer(ManualRendererForTextNode),
-    IfBlockRenderer(IfBlockInfo),
+    IfBlockRenderer(Box<IfBlockInfo>),
     CustomComponentRenderer(CustomComponentBlockInfo),
 }
 
@@ -261,7 +279,7 @@ impl TextNodeRendererGroup {
     ) -> Self {
         let mut renderers: Vec<TextNodeRenderer> = vec![];
         for if_blk in if_blk {
-            renderers.push(TextNodeRenderer::IfBlockRenderer(if_blk.clone()));
+            renderers.push(TextNodeRenderer::IfBlockRenderer(Box::new(if_blk.clone())));
         }
         for txt_node_renderer in text_node_renderer {
             renderers.push(TextNodeRenderer::ManualRenderer(txt_node_renderer.clone()));
@@ -0,0 +1,15 @@
+use lunas_parser::parse_lunas_file;
+
+use crate::{lunas_compile_from_block_with_options, CompileOptions};
+
+// Compiles the source of a component into its JavaScript
+pub fn compile(source: &str, options: &CompileOptions) -> Result<String, String> {
+    let blocks = parse_lunas_file(source)?;
+    lunas_compile_from_block_with_options(&blocks, options).map(|(js, _)| js)
+}
+
+// Whether `js` contains `fragment`, ignoring whitespace so that the formatting of the output doesn't matter
+pub fn contains_code(js: &str, fragment: &str) -> bool {
+    let strip = |code: &str| code.split_whitespace().collect::<String>();
+    strip(js).contains(&strip(fragment))
+}
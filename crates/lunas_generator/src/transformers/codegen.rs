@@ -0,0 +1,277 @@
+use swc_common::{
+    comments::SingleThreadedComments, sync::Lrc, FileName, Mark, SourceMap, DUMMY_SP, GLOBALS,
+};
+use swc_ecma_ast::{EsVersion, Expr, Lit, Module, Program, Str};
+use swc_ecma_codegen::{text_writer::JsWriter, Config, Emitter, Node};
+use swc_ecma_minifier::{
+    optimize,
+    option::{terser::TerserCompressorOptions, ExtraOptions, MangleOptions, MinifyOptions},
+};
+use swc_ecma_parser::{lexer::Lexer, Parser, StringInput, Syntax};
+use swc_ecma_transforms_base::{fixer::fixer, resolver};
+use swc_ecma_visit::{VisitMut, VisitMutWith};
+
+/// A string literal with `value` escaped by swc's code generator.
+pub fn string_literal(value: &str) -> String {
+    let literal = Expr::Lit(Lit::Str(Str {
+        span: DUMMY_SP,
+        value: value.into(),
+        raw: None,
+    }));
+    let cm: Lrc<SourceMap> = Default::default();
+    let mut buf = vec![];
+    {
+        let mut emitter = Emitter {
+            cfg: Config::default(),
+            cm: cm.clone(),
+            comments: None,
+            wr: JsWriter::new(cm, "\n", &mut buf, None),
+        };
+        literal.emit_with(&mut emitter).unwrap();
+    }
+    String::from_utf8(buf).unwrap()
+}
+
+/// Escapes `text` so that a template literal evaluates to it.
+pub fn escape_template_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('`', "\\`")
+        .replace("${", "\\${")
+}
+
+// Give: a`b ${count} ${ c
+// Want: a\`b ${convert_expression("count")} \${ c
+/// Converts text with `${expression}` placeholders into the content of a template literal.
+/// A `${` without a closing `}` is kept as text.
+pub fn text_to_template(text: &str, mut convert_expression: impl FnMut(&str) -> String) -> String {
+    let mut template = String::new();
+    let mut last_end = 0;
+    while let Some(start) = text[last_end..].find("${") {
+        let start = start + last_end;
+        let end = match find_closing_brace(&text[start + 2..]) {
+            Some(end) => end + start + 2,
+            None => break,
+        };
+        template.push_str(&escape_template_text(&text[last_end..start]));
+        template.push_str(&format!(
+            "${{{}}}",
+            convert_expression(&text[start + 2..end])
+        ));
+        last_end = end + 1;
+    }
+    template.push_str(&escape_template_text(&text[last_end..]));
+    template
+}
+
+// Give: <p title="${title.v}">a \${ ${$$lunasEscapeHtml(count.v)}</p>
+// Want: [<p title=", ">a \${ , </p>], [title.v, $$lunasEscapeHtml(count.v)]
+/// Splits the content of a template literal into its static parts, still escaped, and the expressions between them.
+pub fn split_template(template: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut strings = vec![];
+    let mut expressions = vec![];
+    let mut current = String::new();
+    let mut chars = template.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                current.extend(chars.next().map(|(_, escaped)| escaped));
+            }
+            '$' if template[index..].starts_with("${") => {
+                let start = index + 2;
+                let end = match find_closing_brace(&template[start..]) {
+                    Some(end) => start + end,
+                    None => Err(format!("`${{` is not closed in `{}`", template))?,
+                };
+                strings.push(std::mem::take(&mut current));
+                expressions.push(template[start..end].to_string());
+                while chars.next().is_some_and(|(i, _)| i < end) {}
+            }
+            c => current.push(c),
+        }
+    }
+    strings.push(current);
+    Ok((strings, expressions))
+}
+
+// Braces in nested template literals and objects are balanced, and ones in quotes are skipped
+pub fn find_closing_brace(code: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in code.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return Some(index),
+            (None, '}') => depth -= 1,
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+/// Parses a generated module and prints it with swc, so that only valid JavaScript is emitted.
+/// With `minify`, the module is compressed and its local names are shortened by swc's minifier.
+/// Top-level names are kept, since the formats other than `Esm` expose the component through one.
+pub fn print_module(code: &str, minify: bool) -> Result<String, String> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let comments = SingleThreadedComments::default();
+    let fm = cm.new_source_file(FileName::Anon, code.to_string());
+    let lexer = Lexer::new(
+        Syntax::Es(Default::default()),
+        EsVersion::latest(),
+        StringInput::from(&*fm),
+        Some(&comments),
+    );
+    let mut parser = Parser::new_from(lexer);
+    let module = parser
+        .parse_module()
+        .map_err(|e| format!("generated code is not valid JavaScript: {:?}", e.kind()))?;
+    if let Some(e) = parser.take_errors().into_iter().next() {
+        return Err(format!(
+            "generated code is not valid JavaScript: {:?}",
+            e.kind()
+        ));
+    }
+
+    let mut buf = vec![];
+    GLOBALS.set(&Default::default(), || {
+        let module = match minify {
+            true => minify_module(module, cm.clone()),
+            false => module,
+        };
+        let mut emitter = Emitter {
+            cfg: Config {
+                minify,
+                ..Default::default()
+            },
+            cm: cm.clone(),
+            comments: match minify {
+                true => None,
+                false => Some(&comments),
+            },
+            wr: JsWriter::new(cm, "\n", &mut buf, None),
+        };
+        emitter.emit_module(&module).unwrap();
+    });
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+fn minify_module(mut module: Module, cm: Lrc<SourceMap>) -> Module {
+    let unresolved_mark = Mark::new();
+    let top_level_mark = Mark::new();
+    module.visit_mut_with(&mut resolver(unresolved_mark, top_level_mark, false));
+    module.visit_mut_with(&mut EscapedTemplatesToStrings);
+
+    let compress: TerserCompressorOptions = serde_json::from_str("{}").unwrap();
+    let mangle: MangleOptions = serde_json::from_str("{}").unwrap();
+    let options = MinifyOptions {
+        compress: Some(compress.into_config(cm.clone())),
+        mangle: Some(mangle),
+        ..Default::default()
+    };
+    let extra = ExtraOptions {
+        unresolved_mark,
+        top_level_mark,
+    };
+    let program = optimize(Program::Module(module), cm, None, None, &options, &extra);
+    let mut module = program.expect_module();
+    module.visit_mut_with(&mut fixer(None));
+    module
+}
+
+// The minifier turns template literals without expressions into strings by unescaping their raw text,
+// which reads the `n` of an escaped backslash like `\\n` as a newline,
+// so the ones with escapes are converted from their cooked text first
+struct EscapedTemplatesToStrings;
+
+impl VisitMut for EscapedTemplatesToStrings {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        expr.visit_mut_children_with(self);
+        let Expr::Tpl(tpl) = expr else {
+            return;
+        };
+        if let ([quasi], []) = (&tpl.quasis[..], &tpl.exprs[..]) {
+            if let Some(cooked) = quasi.cooked.as_ref().filter(|_| quasi.raw.contains('\\')) {
+                *expr = Expr::Lit(Lit::Str(Str {
+                    span: tpl.span,
+                    value: cooked.to_string().into(),
+                    raw: None,
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{print_module, split_template, string_literal, text_to_template};
+    use crate::{test_utils::compile, CompileOptions, CompileTarget};
+
+    #[test]
+    fn escapes_static_parts_of_templates() {
+        assert_eq!(
+            text_to_template("a`b\\c ${count} $${x} ${ d", |e| format!("f({})", e)),
+            "a\\`b\\\\c ${f(count)} $${f(x)} \\${ d"
+        );
+        assert_eq!(
+            text_to_template("${`a${b}`} ${ {x: \"}\"}.x }", |e| format!("f({})", e)),
+            "${f(`a${b}`)} ${f( {x: \"}\"}.x )}"
+        );
+        assert_eq!(
+            split_template("<p title=\"${title.v}\">a \\${ ${f({a: 1})}</p>").unwrap(),
+            (
+                vec![
+                    "<p title=\"".to_string(),
+                    "\">a \\${ ".to_string(),
+                    "</p>".to_string()
+                ],
+                vec!["title.v".to_string(), "f({a: 1})".to_string()]
+            )
+        );
+        assert_eq!(string_literal("title"), "\"title\"");
+        assert_eq!(string_literal("a\"b\\c\n"), "'a\"b\\\\c\\n'");
+    }
+
+    #[test]
+    fn prints_valid_modules_only() {
+        assert_eq!(
+            print_module("export default function(a) { return `x${a}` }", false).unwrap(),
+            "export default function(a) {\n    return `x${a}`;\n}\n"
+        );
+        assert!(print_module("export default `a`b`;", false).is_err());
+        assert_eq!(
+            print_module(
+                "const helper = 1;\nexport default function(args = {}) {\n    const $$lunasLongRef = helper + 1;\n    return () => $$lunasLongRef;\n}",
+                true
+            )
+            .unwrap(),
+            "let helper=1;export default function(e={}){return()=>2;}"
+        );
+        assert_eq!(
+            print_module("export default [`a\\`b \\\\n`, `c${d}`];", true).unwrap(),
+            "export default[\"a`b \\\\n\",`c${d}`];"
+        );
+    }
+
+    #[test]
+    fn escapes_static_text_and_attributes() {
+        let source = "html:\n  <div data-x='q\"r'><p title='s\"t'>a`b \\\\ $${ c</p></div>\n";
+        let markup =
+            "<div data-x=\"q&quot;r\"><p title=\"s&quot;t\">a\\`b \\\\\\\\ $\\${ c</p></div>";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.contains(&format!("`{}`", markup)));
+
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains(&format!("return `{}`;", markup)));
+    }
+}
@@ -0,0 +1,344 @@
+use serde_json::Value;
+
+use crate::structs::transform_info::{RemoveStatement, TransformInfo};
+
+use super::js_utils::{find_declared_names, find_pattern_names, get_span};
+
+/// Members of every component instance, which exported bindings would replace.
+const INSTANCE_MEMBERS: [&str; 5] = [
+    "mount",
+    "insert",
+    "hydrate",
+    "__unmount",
+    "__setEventTarget",
+];
+
+/// A binding exported from `script:`, which is part of the public API of the component instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedBinding {
+    /// The name the binding is exported as.
+    pub name: String,
+    /// The name of the binding in the script.
+    pub local: String,
+    pub is_function: bool,
+}
+
+/// The script with its exports unwrapped, the transforms removing them, and the exported bindings.
+type SplitExports = (Value, Vec<TransformInfo>, Vec<ExportedBinding>);
+
+// Give: export function reset() {} export let count = 0; export { count as total };
+// Want: function reset() {} let count = 0;
+/// Unwraps the `export`s of `script:`, since the script is inlined into the component function.
+/// The returned script is analyzed in place of the original one.
+pub fn split_exports(ast: &Value, raw_js: &str) -> Result<SplitExports, String> {
+    let (unwrapped, transforms, exports) = unwrap_exports(ast, raw_js, "script:")?;
+    if let Some(export) = exports
+        .iter()
+        .find(|export| INSTANCE_MEMBERS.contains(&export.name.as_str()))
+    {
+        return Err(format!(
+            "`{}` cannot be exported, since every component instance has a member of that name",
+            export.name
+        ));
+    }
+    Ok((unwrapped, transforms, exports))
+}
+
+/// The exports of `script module:`, which are exported from the component module as-is.
+pub struct ModuleExports {
+    pub bindings: Vec<ExportedBinding>,
+    /// `export ... from` statements, which are emitted with the imports so that their sources are resolved.
+    pub reexports: Vec<String>,
+    /// The transforms removing `reexports` from the script.
+    pub transforms: Vec<TransformInfo>,
+}
+
+// Give: export const cache = new Map(); export { format } from "./fmt.js";
+// Want: bindings: [cache], reexports: ["export { format } from \"./fmt.js\";"]
+/// Finds the exports of `script module:`.
+pub fn find_module_exports(ast: &Value, raw_js: &str) -> Result<ModuleExports, String> {
+    let mut body = vec![];
+    let mut reexports = vec![];
+    let mut transforms = vec![];
+    let mut names = vec![];
+    for item in ast["body"].as_array().into_iter().flatten() {
+        let is_reexport = match item["type"].as_str() {
+            Some("ExportNamedDeclaration") => !item["source"].is_null(),
+            Some("ExportAllDeclaration") => true,
+            _ => false,
+        };
+        if !is_reexport {
+            body.push(item.clone());
+            continue;
+        }
+        let (start_position, mut end_position) = get_span(item);
+        reexports.push(raw_js[start_position as usize..end_position as usize].to_string());
+        if raw_js[end_position as usize..].starts_with('\n') {
+            end_position += 1;
+        }
+        transforms.push(TransformInfo::RemoveStatement(RemoveStatement {
+            start_position,
+            end_position,
+        }));
+        names.extend(find_reexported_names(item));
+    }
+    let mut script = ast.clone();
+    script["body"] = Value::Array(body);
+    let bindings = unwrap_exports(&script, raw_js, "script module:")?.2;
+
+    names.extend(bindings.iter().map(|binding| binding.name.clone()));
+    for (index, name) in names.iter().enumerate() {
+        if name == "default" {
+            return Err(
+                "`default` cannot be exported from `script module:`, since the component is the default export"
+                    .to_string(),
+            );
+        }
+        if names[..index].contains(name) {
+            return Err(format!("`{}` is exported more than once", name));
+        }
+    }
+    Ok(ModuleExports {
+        bindings,
+        reexports,
+        transforms,
+    })
+}
+
+// Give: export { a, b as c } from "./x.js"; or export * as ns from "./y.js";
+// Want: ["a", "c"] or ["ns"]
+fn find_reexported_names(item: &Value) -> Vec<String> {
+    item["specifiers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|specifier| match specifier["type"].as_str() {
+            Some("ExportNamespaceSpecifier") => specifier["name"]["value"].as_str(),
+            _ => specifier["exported"]["value"]
+                .as_str()
+                .or(specifier["orig"]["value"].as_str()),
+        })
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn unwrap_exports(ast: &Value, raw_js: &str, block_name: &str) -> Result<SplitExports, String> {
+    let Some(Value::Array(body)) = ast.get("body") else {
+        return Ok((ast.clone(), vec![], vec![]));
+    };
+    let mut unwrapped_body = vec![];
+    let mut transforms = vec![];
+    let mut exported_names = vec![];
+    for item in body {
+        match item["type"].as_str() {
+            Some("ExportDeclaration") => {
+                let declaration = &item["declaration"];
+                transforms.push(TransformInfo::RemoveStatement(RemoveStatement {
+                    start_position: get_span(item).0,
+                    end_position: get_span(declaration).0,
+                }));
+                for local in find_top_level_names(declaration) {
+                    exported_names.push((local.clone(), local));
+                }
+                unwrapped_body.push(declaration.clone());
+            }
+            Some("ExportNamedDeclaration") if item["source"].is_null() => {
+                let (start_position, mut end_position) = get_span(item);
+                if raw_js[end_position as usize..].starts_with('\n') {
+                    end_position += 1;
+                }
+                transforms.push(TransformInfo::RemoveStatement(RemoveStatement {
+                    start_position,
+                    end_position,
+                }));
+                for specifier in item["specifiers"].as_array().into_iter().flatten() {
+                    let Some(local) = specifier["orig"]["value"].as_str() else {
+                        continue;
+                    };
+                    let name = specifier["exported"]["value"].as_str().unwrap_or(local);
+                    exported_names.push((name.to_string(), local.to_string()));
+                }
+            }
+            Some("ExportDefaultDeclaration" | "ExportDefaultExpression") => {
+                return Err(format!(
+                    "`export default` cannot be used in `{}`, since the component is the default export",
+                    block_name
+                ))
+            }
+            Some("ExportNamedDeclaration" | "ExportAllDeclaration") => {
+                return Err(format!(
+                    "`export ... from` cannot be used in `{}`; import the bindings and export them instead",
+                    block_name
+                ))
+            }
+            _ => unwrapped_body.push(item.clone()),
+        }
+    }
+
+    let declarations = unwrapped_body
+        .iter()
+        .flat_map(|item| {
+            let is_function = is_function_declaration(item);
+            find_top_level_names(item)
+                .into_iter()
+                .map(move |name| (name, is_function))
+        })
+        .collect::<Vec<(String, bool)>>();
+    let mut exports: Vec<ExportedBinding> = vec![];
+    for (name, local) in exported_names {
+        if exports.iter().any(|export| export.name == name) {
+            return Err(format!("`{}` is exported more than once", name));
+        }
+        let Some((_, is_function)) = declarations.iter().find(|(n, _)| *n == local) else {
+            return Err(format!(
+                "`{}` is exported, but it is not declared at the top level of `{}`",
+                local, block_name
+            ));
+        };
+        exports.push(ExportedBinding {
+            name,
+            local,
+            is_function: *is_function,
+        });
+    }
+
+    let mut unwrapped = ast.clone();
+    unwrapped["body"] = Value::Array(unwrapped_body);
+    Ok((unwrapped, transforms, exports))
+}
+
+// Give: function reset() {}, let [a, b] = pair; or import { c } from "./c.js";
+// Want: ["reset"], ["a", "b"] or ["c"]
+fn find_top_level_names(item: &Value) -> Vec<String> {
+    match item["type"].as_str() {
+        Some("VariableDeclaration") => find_declared_names(item),
+        Some("FunctionDeclaration" | "ClassDeclaration") => find_pattern_names(&item["identifier"]),
+        Some("ImportDeclaration") => item["specifiers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|specifier| find_pattern_names(&specifier["local"]))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// Give: function reset() {} or const reset = () => {};
+fn is_function_declaration(item: &Value) -> bool {
+    match item["type"].as_str() {
+        Some("FunctionDeclaration") => true,
+        Some("VariableDeclaration") => {
+            item["declarations"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .all(|declarator| {
+                    matches!(
+                        declarator["init"]["type"].as_str(),
+                        Some("ArrowFunctionExpression" | "FunctionExpression")
+                    )
+                })
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lunas_parser::parse_lunas_file;
+
+    use crate::{
+        lunas_generate_types_from_block,
+        test_utils::{compile, contains_code},
+        CompileOptions, CompileTarget, ModuleFormat,
+    };
+
+    const MODULE: &str = "html:\n  <div>hi</div>\n\nscript module:\n  export { a, b as c } from \"./x.js\"\n  export * from \"./y.js\"\n  export const z = 1\n";
+
+    fn types(source: &str, options: &CompileOptions) -> String {
+        let blocks = parse_lunas_file(source).unwrap();
+        lunas_generate_types_from_block(&blocks, options).unwrap()
+    }
+
+    #[test]
+    fn exposes_exported_bindings() {
+        let source = "html:\n  <div><p>${count}</p></div>\n\nscript:\n  export let count = 0;\n  export const step = 2;\n  export function increment() {\n    count += step;\n  }\n  const reset = () => { count = 0; };\n  export { reset as clear };\n";
+        let options = CompileOptions::default();
+        let js = compile(source, &options).unwrap();
+        assert!(contains_code(&js, "let count = $$lunasReactive(0); const step = 2; function increment() { count.v += step; }"));
+        // Values are read through getters, so that the instance sees their changes
+        assert!(contains_code(&js, "return $$lunasExpose({ get count () { return count.v; }, step, increment, clear: reset });"));
+        assert!(!js.contains("export {"));
+        assert!(contains_code(&types(source, &options), "export type Exports = { readonly count: any; readonly step: any; increment: (...args: any[]) => any; clear: (...args: any[]) => any; };"));
+        assert!(types(source, &options).contains("declare const component: (args?: { [key: string]: any }) => LunasModuleExports & Exports;"));
+
+        let message = |script: &str| {
+            let source = format!("html:\n  <div></div>\n\nscript:\n  {}\n", script);
+            compile(&source, &options).unwrap_err()
+        };
+        assert!(message("export default 1;")
+            .starts_with("`export default` cannot be used in `script:`"));
+        assert_eq!(
+            message("export function mount() {}"),
+            "`mount` cannot be exported, since every component instance has a member of that name"
+        );
+
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(contains_code(&js, "let count = 0; const step = 2;"));
+        assert!(!js.contains("export {"));
+        assert!(types(source, &options).contains("=> string;"));
+    }
+
+    #[test]
+    fn hoists_reexports_with_the_imports() {
+        for target in [CompileTarget::Client, CompileTarget::Server] {
+            let options = CompileOptions {
+                target,
+                import_resolver: Some(Box::new(|source: &str| Ok(source.replace("./", "./lib/")))),
+                ..Default::default()
+            };
+            let js = compile(MODULE, &options).unwrap();
+            let reexports = js
+                .find("export { a, b as c } from \"./lib/x.js\";")
+                .unwrap();
+            assert!(js[reexports..].starts_with(
+                "export { a, b as c } from \"./lib/x.js\";\nexport * from \"./lib/y.js\";\nexport const z = 1;"
+            ));
+        }
+    }
+
+    #[test]
+    fn declares_reexports_in_types() {
+        let types = types(MODULE, &CompileOptions::default());
+        assert!(types.contains("export declare const z: any;"));
+        assert!(types.contains("export { a, b as c } from \"./x.js\""));
+        assert!(types.contains("export * from \"./y.js\""));
+    }
+
+    #[test]
+    fn rejects_invalid_reexports() {
+        let source =
+            "html:\n  <div>hi</div>\n\nscript module:\n  export { default } from \"./x.js\"\n";
+        assert_eq!(
+            compile(source, &CompileOptions::default()).unwrap_err(),
+            "`default` cannot be exported from `script module:`, since the component is the default export"
+        );
+        let source = "html:\n  <div>hi</div>\n\nscript module:\n  export { a } from \"./x.js\"\n  export const a = 1\n";
+        assert_eq!(
+            compile(source, &CompileOptions::default()).unwrap_err(),
+            "`a` is exported more than once"
+        );
+        let options = CompileOptions {
+            format: ModuleFormat::CommonJs,
+            ..Default::default()
+        };
+        assert_eq!(
+            compile(MODULE, &options).unwrap_err(),
+            "`export` in `script module:` can only be used when the component is compiled into an ES module"
+        );
+    }
+}
@@ -11,8 +11,9 @@ use crate::{
     },
     structs::{
         transform_info::{
-            ActionAndTarget, ComponentArgs, CustomComponentBlockInfo, EventBindingStatement,
-            EventTarget, IfBlockInfo, ManualRendererForTextNode, NeededIdName,
+            word_is_one_word, ActionAndTarget, ComponentArgs, CustomComponentBlockInfo,
+            EventBindingStatement, EventTarget, IfBlockInfo, ManualRendererForTextNode,
+            NeededIdName, RefAndTarget,
         },
         transform_targets::{
             ElmAndReactiveAttributeRelation, ElmAndVariableContentRelation, NodeAndReactiveInfo,
@@ -21,7 +22,10 @@ use crate::{
     },
 };
 
-use super::utils::{append_v_to_vars_in_html, UUID_GENERATOR};
+use super::{
+    codegen::{escape_template_text, split_template, string_literal, text_to_template},
+    utils::{append_v_to_vars_in_html, UUID_GENERATOR},
+};
 
 // TODO:この関数の責務が多すぎるので、可能な限り分離させる
 // TODO:dep_vars の使い方を再考する
@@ -34,6 +38,7 @@ pub fn check_html_elms(
     needed_ids: &mut Vec<NeededIdName>,
     elm_and_var_relation: &mut Vec<NodeAndReactiveInfo>,
     actions_and_targets: &mut Vec<ActionAndTarget>,
+    refs_and_targets: &mut Vec<RefAndTarget>,
     parent_uuid: Option<&String>,
     html_manipulators: &mut Vec<HtmlManipulator>,
     if_blocks_info: &mut Vec<IfBlockInfo>,
@@ -49,6 +54,12 @@ pub fn check_html_elms(
         NodeContent::Element(element) => {
             let mut ctx_array = if_blk_ctx.clone();
             if !component_names.contains(&element.tag_name) {
+                // Static values are emitted into template literals, next to the `${...}` of bound ones
+                for (key, value) in element.attributes.iter_mut() {
+                    if let (false, Some(value)) = (key.starts_with(['@', ':']), value) {
+                        *value = escape_template_text(value);
+                    }
+                }
                 for (key, action_value) in &element.attributes.clone() {
                     // if attrs.name starts with "@"
                     if key.starts_with("@") {
@@ -57,7 +68,7 @@ pub fn check_html_elms(
                         if let Some(value) = &&action_value {
                             actions_and_targets.push(ActionAndTarget {
                                 action_name: action_name.to_string(),
-                                action: EventTarget::new(value.to_string(), varibale_names),
+                                action: EventTarget::new(value.to_string(), varibale_names)?,
                                 target: node_id.clone(),
                                 ctx: ctx_array.clone(),
                             })
@@ -160,10 +171,10 @@ pub fn check_html_elms(
                             Err(format!("value of attribute :{} is null", raw_attr_name))?;
                         }
 
-                        let mut raw_attr_value = raw_attr_value.unwrap();
+                        let raw_attr_value = raw_attr_value.unwrap();
 
                         let (raw_attr_value, used_vars) =
-                            append_v_to_vars_in_html(&mut raw_attr_value, varibale_names);
+                            append_v_to_vars_in_html(&raw_attr_value, varibale_names)?;
 
                         element.attributes.remove(key);
                         element.attributes.insert(
@@ -180,6 +191,16 @@ pub fn check_html_elms(
                         reactive_attr_info.reactive_attr.push(reactive_attr);
                     }
                 }
+                // Checked after the other attributes, so that a ref on an if block is set when it is rendered
+                if let Some(value) = element.attributes.remove("ref") {
+                    let ref_name = check_ref_name(&value)?;
+                    set_id_for_needed_elm(element, needed_ids, &node_id, &ctx_array);
+                    refs_and_targets.push(RefAndTarget {
+                        ref_name,
+                        target: node_id.clone(),
+                        ctx: ctx_array.clone(),
+                    });
+                }
 
                 // When the tag_name corresponds to the component_names
             } else {
@@ -227,6 +248,7 @@ pub fn check_html_elms(
                     needed_ids,
                     elm_and_var_relation,
                     actions_and_targets,
+                    refs_and_targets,
                     Some(&node.uuid),
                     html_manipulators,
                     if_blocks_info,
@@ -306,7 +328,7 @@ pub fn check_html_elms(
                             let (cond, dep_vars) = append_v_to_vars_in_html(
                                 remove_statement.condition.as_str(),
                                 &varibale_names,
-                            );
+                            )?;
                             if_blocks_info.push(IfBlockInfo {
                                 parent_id: node_id.clone(),
                                 target_if_blk_id: remove_statement.child_uuid.clone(),
@@ -320,6 +342,8 @@ pub fn check_html_elms(
                                 ctx_over_if: remove_statement.ctx_over_if.clone(),
                                 if_blk_id: remove_statement.block_id.clone(),
                                 element_location: remove_statement.elm_loc.clone(),
+                                refs: vec![],
+                                ref_names: vec![],
                             });
                         }
                         HtmlManipulation::RemoveChildForCustomComponent(remove_statement) => {
@@ -329,6 +353,12 @@ pub fn check_html_elms(
                                 &node_id,
                                 &remove_statement.ctx,
                             );
+                            // `ref` stores the instance of the component instead of being passed to it
+                            let mut attributes = remove_statement.attributes.clone();
+                            let ref_name = match attributes.remove("ref") {
+                                Some(value) => Some(check_ref_name(&value)?),
+                                None => None,
+                            };
                             let (_, _, distance, idx_of_ref) =
                                 element.remove_child(&remove_statement.child_uuid, component_names);
 
@@ -357,13 +387,14 @@ pub fn check_html_elms(
                                 have_sibling_elm: count_of_siblings > 1,
                                 target_anchor_id,
                                 component_name: remove_statement.component_name.clone(),
-                                args: ComponentArgs::new(&remove_statement.attributes),
+                                args: ComponentArgs::new(&attributes),
                                 ctx: remove_statement.ctx.clone(),
                                 custom_component_block_id: UUID_GENERATOR.lock().unwrap().gen(),
                                 element_location: remove_statement.elm_loc.clone(),
                                 is_routing_component: ROUTER_COMPONENTS
                                     .into_iter()
                                     .any(|x| x == remove_statement.component_name),
+                                ref_name,
                             });
                         }
                         HtmlManipulation::SetIdForReactiveContent(set_id) => {
@@ -434,7 +465,7 @@ pub fn check_html_elms(
             Ok(())
         }
         NodeContent::TextNode(text) => {
-            let (dep_vars, _) = replace_text_with_reactive_value(text, varibale_names);
+            let (dep_vars, _) = replace_text_with_reactive_value(text, varibale_names)?;
             if dep_vars.len() > 0 && count_of_siblings <= 1 {
                 html_manipulators.push(HtmlManipulator {
                     target_uuid: parent_uuid.unwrap().clone(),
@@ -461,7 +492,26 @@ pub fn check_html_elms(
             }
             Ok(())
         }
-        crate::orig_html_struct::structs::NodeContent::Comment(_) => Ok(()),
+        crate::orig_html_struct::structs::NodeContent::Comment(comment) => {
+            *comment = escape_template_text(comment);
+            Ok(())
+        }
+    }
+}
+
+// Give: <input ref="nameInput">
+// Want: "nameInput", which is used as `Lunas.refs.nameInput`
+fn check_ref_name(value: &Option<String>) -> Result<String, String> {
+    match value {
+        Some(name)
+            if !name.is_empty()
+                && word_is_one_word(name)
+                && !name.starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            Ok(name.clone())
+        }
+        Some(name) => Err(format!("ref name `{}` is not a valid identifier", name)),
+        None => Err("value of attribute ref is null".to_string()),
     }
 }
 
@@ -511,73 +561,80 @@ fn set_id_for_needed_elm(
 fn replace_text_with_reactive_value(
     code: &mut String,
     variables: &Vec<String>,
-) -> (Vec<String>, u32) {
+) -> Result<(Vec<String>, u32), String> {
     let mut count_of_bindings = 0;
-
-    let start_tag = "${";
-    let end_tag = "}";
-    let mut new_code = String::new();
     let mut depending_vars = vec![];
-    let mut last_end = 0;
-
-    while let Some(start) = code[last_end..].find(start_tag) {
+    let mut error = None;
+    *code = text_to_template(code, |in_bracket| {
         count_of_bindings += 1;
-        let start = start + last_end;
-        if let Some(end) = code[start..].find(end_tag) {
-            let end = end + start;
-            let pre_bracket = &code[last_end..start];
-            let in_bracket = &code[start + 2..end];
-            let _post_bracket = &code[end + 1..];
-
-            new_code.push_str(pre_bracket);
-            new_code.push_str(start_tag);
-            let (output, dep_vars) = append_v_to_vars_in_html(in_bracket, variables);
-            new_code.push_str(&escape_html(&output));
-            new_code.push_str(end_tag);
-
-            last_end = end + 1;
-
-            depending_vars.extend(dep_vars);
+        match append_v_to_vars_in_html(in_bracket, variables) {
+            Ok((output, dep_vars)) => {
+                depending_vars.extend(dep_vars);
+                escape_html(&output)
+            }
+            Err(message) => {
+                error.get_or_insert(message);
+                in_bracket.to_string()
+            }
         }
+    });
+    match error {
+        Some(message) => Err(message),
+        None => Ok((depending_vars, count_of_bindings)),
     }
-
-    new_code.push_str(&code[last_end..]);
-    *code = new_code;
-    (depending_vars, count_of_bindings)
 }
 
+// Give: <p class="a" title="${title.v}">${$$lunasEscapeHtml(count.v)}</p>
+// Want: $$lunasUseTemplate($$lunasTemplate0, [title.v, count.v])
+//   with `const $$lunasTemplate0 = $$lunasCreateTemplate([`<p class="a" title="`, `">`, `</p>`], "p");` in `templates`
+/// Hoists the markup of `elm` into a module-level template, which the runtime parses once and clones for each instance.
+/// Returns the expression creating the element with the dynamic parts filled in.
 pub fn create_lunas_internal_component_statement(
     elm: &Element,
-    generation_func_name: &str,
-) -> String {
-    let mut code = String::new();
-    code.push_str(format!("{}(`", generation_func_name).as_str());
-    for child in &elm.children {
-        code.push_str(&child.to_string());
-    }
-    code.push_str("`, \"");
-    code.push_str(&elm.tag_name);
-    code.push_str("\"");
-    let attrs = elm.attributes_without_meta();
-    if attrs.len() > 0 {
-        code.push_str(", {");
-        for (key, value) in attrs.iter() {
-            let js_value = match value {
-                Some(value) => format!("`{}`", value),
-                None => "null".to_string(),
-            };
-            code.push_str(&format!("\"{}\": {},", key, js_value));
-        }
-        code.push('}');
-    }
-    code.push_str(")");
-    code
+    templates: &mut Vec<String>,
+) -> Result<String, String> {
+    let mut elm = elm.clone();
+    elm.attributes = elm.attributes_without_meta();
+    let (strings, expressions) = split_template(&elm.to_string())?;
+    let template_name = format!("$$lunasTemplate{}", templates.len());
+    templates.push(format!(
+        "const {} = $$lunasCreateTemplate([{}], {});",
+        template_name,
+        strings
+            .iter()
+            .map(|s| format!("`{}`", s))
+            .collect::<Vec<String>>()
+            .join(", "),
+        string_literal(&elm.tag_name)
+    ));
+    // Text is filled into text nodes, so it is not escaped like in markup
+    let values = expressions
+        .iter()
+        .map(|expression| {
+            match expression
+                .strip_prefix("$$lunasEscapeHtml(")
+                .and_then(|e| e.strip_suffix(')'))
+            {
+                Some(text) => text,
+                None => expression,
+            }
+        })
+        .collect::<Vec<&str>>();
+    Ok(format!(
+        "$$lunasUseTemplate({}, [{}])",
+        template_name,
+        values.join(", ")
+    ))
 }
 
 // TODO: テストを別ファイルに移動する
 #[cfg(test)]
 mod tests {
     use super::replace_text_with_reactive_value;
+    use crate::{
+        test_utils::{compile, contains_code},
+        CompileOptions,
+    };
 
     #[test]
     fn exploration() {
@@ -586,7 +643,8 @@ mod tests {
         replace_text_with_reactive_value(
             &mut code,
             &vec!["count".to_string(), "count2".to_string()],
-        );
+        )
+        .unwrap();
         assert_eq!(code, "$$lunasEscapeHtml(count2.v+count.v)");
     }
 
@@ -597,7 +655,8 @@ mod tests {
         replace_text_with_reactive_value(
             &mut code,
             &vec!["count".to_string(), "count2".to_string()],
-        );
+        )
+        .unwrap();
         assert_eq!(code, "$$lunasEscapeHtml( count2.v + count.v )");
     }
 
@@ -605,12 +664,38 @@ mod tests {
     fn exploration3() {
         let code = "${interval==null?'start':'clear'}";
         let mut code = code.to_string();
-        replace_text_with_reactive_value(&mut code, &vec!["interval".to_string()]);
+        replace_text_with_reactive_value(&mut code, &vec!["interval".to_string()]).unwrap();
         assert_eq!(
             code,
             "${$$lunasEscapeHtml(interval.v == null ? 'start' : 'clear')}"
         );
     }
+
+    #[test]
+    fn hoists_markup_into_templates() {
+        let source = "html:\n  <ul><li :title=\"title\">${title}</li><li></li><b :if=\"show\">${title}!</b></ul>\n\nscript:\n  let title = \"a\";\n  let show = true;\n  const toggle = () => { show = !show; title += \"!\"; };\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        // Templates are created once per module, before the component function
+        let component = js.find("export default function").unwrap();
+        let list = js
+            .find("const $$lunasTemplate0 = $$lunasCreateTemplate([")
+            .unwrap();
+        let bold = js
+            .find("const $$lunasTemplate1 = $$lunasCreateTemplate([")
+            .unwrap();
+        assert!(list < component && bold < component);
+        assert!(contains_code(&js, "</li><li></li></ul>`], \"ul\");"));
+        assert!(contains_code(&js, "`!</b>`], \"b\");"));
+        // Each instance fills the holes of the template with its values
+        assert!(contains_code(
+            &js,
+            "$$lunasSetComponentElement($$lunasUseTemplate($$lunasTemplate0, [title.v, title.v]));"
+        ));
+        assert!(contains_code(
+            &js,
+            "()=>$$lunasUseTemplate($$lunasTemplate1, ["
+        ));
+    }
 }
 
 fn escape_html(s: &str) -> String {
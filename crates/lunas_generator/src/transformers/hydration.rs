@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use crate::orig_html_struct::structs::{Node, NodeContent};
+
+use super::codegen::string_literal;
+
+/// Locations of the elements in the original template, used to find them in server-rendered markup.
+/// A location is the list of child indexes from the component root, which itself is `[0]`.
+pub struct HydrationLocations {
+    elements: HashMap<String, (Vec<usize>, String)>,
+}
+
+impl HydrationLocations {
+    // Has to be created before `check_html_elms` removes if blocks, components and text nodes from the tree
+    pub fn new(root: &Node) -> Self {
+        let mut elements = HashMap::new();
+        collect_element_locations(root, &[0], &mut elements);
+        HydrationLocations { elements }
+    }
+
+    // Give: uuids of <p> at [0, 1, 0] and <em> at [0, 2]
+    // Want: [[[1, 0], "p"], [[2], "em"]]
+    pub fn element_paths(&self, node_ids: &[&String]) -> String {
+        let paths = node_ids
+            .iter()
+            .map(|node_id| {
+                let (location, tag_name) = &self.elements[*node_id];
+                let path = location[1..]
+                    .iter()
+                    .map(|index| index.to_string())
+                    .collect::<Vec<String>>();
+                format!("[[{}], {}]", path.join(", "), string_literal(tag_name))
+            })
+            .collect::<Vec<String>>();
+        format!("[{}]", paths.join(", "))
+    }
+}
+
+fn collect_element_locations(
+    node: &Node,
+    location: &[usize],
+    elements: &mut HashMap<String, (Vec<usize>, String)>,
+) {
+    if let NodeContent::Element(element) = &node.content {
+        elements.insert(
+            node.uuid.clone(),
+            (location.to_vec(), element.tag_name.clone()),
+        );
+        for (index, child) in element.children.iter().enumerate() {
+            let mut child_location = location.to_vec();
+            child_location.push(index);
+            collect_element_locations(child, &child_location, elements);
+        }
+    }
+}
+
+/// Key of the comment region that the server target renders around the node at `location`.
+pub fn hydration_key(location: &[usize]) -> String {
+    location
+        .iter()
+        .map(|index| index.to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+/// The trailing `, "<key>"` argument that hydrating runtime helpers receive, or nothing for the client target.
+pub fn hydration_key_arg(hydration: Option<&HydrationLocations>, location: &[usize]) -> String {
+    match hydration {
+        Some(_) => format!(", {}", string_literal(&hydration_key(location))),
+        None => "".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hydration_key, HydrationLocations};
+    use crate::{
+        orig_html_struct::structs::{Node, NodeContent},
+        test_utils::{compile, contains_code},
+        CompileOptions, CompileTarget,
+    };
+
+    #[test]
+    fn locates_elements_by_template_index() {
+        let dom =
+            lunas_html_parser::Dom::parse("<div><p>a<b>b</b></p>text<em>c</em></div>").unwrap();
+        let root = Node::new_from_dom(&dom).unwrap();
+        let locations = HydrationLocations::new(&root);
+        let NodeContent::Element(div) = &root.content else {
+            panic!()
+        };
+        let NodeContent::Element(p) = &div.children[0].content else {
+            panic!()
+        };
+        let ids = [&root.uuid, &p.children[1].uuid, &div.children[2].uuid];
+        assert_eq!(
+            locations.element_paths(&ids),
+            "[[[], \"div\"], [[0, 1], \"b\"], [[2], \"em\"]]"
+        );
+        assert_eq!(hydration_key(&[0, 2, 1]), "0.2.1");
+    }
+
+    #[test]
+    fn hydrates_server_rendered_markup() {
+        let source = "@use Child from './child.lunas'\n\nhtml:\n  <div><h1 :if=\"show\">${title}</h1><p>a ${title}<b @click=\"f\">b</b></p><Child title=\"a\" /></div>\n\nscript:\n  let title = \"x\";\n  let show = true;\n  const f = () => { title = \"y\"; show = !show; };\n";
+        let options = CompileOptions {
+            target: CompileTarget::Hydrate,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        // Elements are found by their path in the server-rendered markup
+        assert!(contains_code(&js, "[[1, 1], \"b\"]"));
+        assert!(contains_code(&js, "[[0], \"h1\"]"));
+        // Text, if blocks and child components are matched with the markers of their hydration keys
+        assert!(contains_code(
+            &js,
+            "$$lunasInsertContent(`a ${$$lunasEscapeHtml(title.v)}`,"
+        ));
+        assert!(js.contains(", \"0.1.0\");"));
+        assert!(js.contains("}, \"0.0\");"));
+        assert!(js.contains(").insert("));
+        assert!(js.contains(", null, \"0.2\");"));
+    }
+}
@@ -1,3 +1,7 @@
+use crate::structs::compile_options::ImportResolver;
+
+use super::{codegen::string_literal, utils_swc::parse_with_swc};
+
 pub fn generate_import_string(imports: &Vec<String>) -> String {
     match imports.len() == 0 {
         true => String::new(),
@@ -7,3 +11,25 @@ pub fn generate_import_string(imports: &Vec<String>) -> String {
             .collect::<String>(),
     }
 }
+
+/// Rewrites the module specifier of an `import` declaration with the resolver.
+pub fn resolve_import_source(import: &str, resolver: &ImportResolver) -> Result<String, String> {
+    let parsed = parse_with_swc(&import.to_string());
+    let parsed_json = serde_json::to_value(&parsed).unwrap();
+    let source = &parsed_json["body"][0]["source"];
+    let (specifier, start, end) = match (
+        source["value"].as_str(),
+        source["span"]["start"].as_u64(),
+        source["span"]["end"].as_u64(),
+    ) {
+        (Some(specifier), Some(start), Some(end)) => (specifier, start as usize, end as usize),
+        _ => return Err(format!("invalid import declaration: {}", import)),
+    };
+    let resolved = resolver(specifier)?;
+    Ok(format!(
+        "{}{}{}",
+        &import[..start - 1],
+        string_literal(&resolved),
+        &import[end - 1..]
+    ))
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::structs::transform_info::{ReplaceText, TransformInfo};
+
+use super::{codegen::string_literal, js_utils::get_span};
+
+/// What a `Lunas.<name>` expression is compiled to.
+enum Intrinsic {
+    /// Replaced with a runtime helper of the same purpose.
+    Helper(&'static str),
+    /// `Lunas.props.<input>` reads an input of the component.
+    Props,
+    /// `Lunas.env.<KEY>` is replaced with the value of `KEY` at compile time.
+    Env,
+}
+
+const INTRINSICS: [(&str, Intrinsic); 12] = [
+    ("router", Intrinsic::Helper("$$lunasRouter")),
+    ("emit", Intrinsic::Helper("$$lunasEmit")),
+    ("nextTick", Intrinsic::Helper("$$lunasNextTick")),
+    ("refs", Intrinsic::Helper("$$lunasRefs")),
+    ("computed", Intrinsic::Helper("$$lunasComputed")),
+    ("effect", Intrinsic::Helper("$$lunasEffect")),
+    ("watch", Intrinsic::Helper("$$lunasWatch")),
+    ("onMount", Intrinsic::Helper("$$lunasOnMount")),
+    ("onUpdate", Intrinsic::Helper("$$lunasOnUpdate")),
+    ("onDestroy", Intrinsic::Helper("$$lunasOnDestroy")),
+    ("props", Intrinsic::Props),
+    ("env", Intrinsic::Env),
+];
+
+/// The transforms replacing the intrinsics, and the variables they read.
+type Resolved = (Vec<TransformInfo>, Vec<String>);
+
+/// What the intrinsics of a script are resolved against.
+#[derive(Default)]
+pub struct IntrinsicContext<'a> {
+    /// Names of the inputs declared with `@input`.
+    pub inputs: Vec<String>,
+    pub env: Option<&'a HashMap<String, String>>,
+    /// Source of the script, to point at the intrinsics that cannot be resolved.
+    pub script: &'a str,
+    /// Whether the script is `script module:`, which runs outside of any component instance.
+    pub module_scope: bool,
+}
+
+// Give: Lunas.emit("change", count); Lunas.env.API_URL; Lunas.props.count
+// Want: $$lunasEmit("change", count); "https://..."; count.v
+/// Replaces the `Lunas.<name>` expressions in `json` and returns the variables they read.
+/// Markup expressions are resolved without a context, where `Lunas.env` is not available
+/// and the names read from `Lunas.props` are not checked.
+pub fn resolve_intrinsics(
+    json: &Value,
+    variables: &[String],
+    context: Option<&IntrinsicContext>,
+) -> Result<Resolved, String> {
+    match json {
+        Value::Object(obj) => {
+            if obj.get("type").and_then(Value::as_str) == Some("MemberExpression") {
+                if let Some(resolved) = resolve_member(json, variables, context)? {
+                    return Ok(resolved);
+                }
+            }
+            let mut transforms = vec![];
+            let mut dep_vars = vec![];
+            for value in obj.values() {
+                let (child_transforms, child_dep_vars) =
+                    resolve_intrinsics(value, variables, context)?;
+                transforms.extend(child_transforms);
+                dep_vars.extend(child_dep_vars);
+            }
+            Ok((transforms, dep_vars))
+        }
+        Value::Array(arr) => {
+            let mut transforms = vec![];
+            let mut dep_vars = vec![];
+            for value in arr {
+                let (child_transforms, child_dep_vars) =
+                    resolve_intrinsics(value, variables, context)?;
+                transforms.extend(child_transforms);
+                dep_vars.extend(child_dep_vars);
+            }
+            Ok((transforms, dep_vars))
+        }
+        _ => Ok((vec![], vec![])),
+    }
+}
+
+// Give: Lunas.props.count, Lunas.props or Lunas.emit
+// Want: count.v, ({ count: count.v }) or $$lunasEmit
+fn resolve_member(
+    member: &Value,
+    variables: &[String],
+    context: Option<&IntrinsicContext>,
+) -> Result<Option<Resolved>, String> {
+    let (start_position, end_position) = get_span(member);
+    let replace = |string: String, dep_vars: Vec<String>| {
+        Ok(Some((
+            vec![TransformInfo::ReplaceText(ReplaceText {
+                start_position,
+                end_position,
+                string,
+            })],
+            dep_vars,
+        )))
+    };
+    let reference = |name: &str| match variables.iter().any(|v| v == name) {
+        true => format!("{}.v", name),
+        false => name.to_string(),
+    };
+
+    // `Lunas.props.<input>` and `Lunas.env.<KEY>` are resolved as a whole
+    if let Some(name) = find_intrinsic(&member["object"]) {
+        if let Some(key) = find_property_name(member) {
+            match find_table_entry(name)? {
+                Intrinsic::Props => {
+                    check_instance_scope(name, context)?;
+                    if let Some(context) = context {
+                        if !context.inputs.iter().any(|input| input == key) {
+                            return Err(format!(
+                                "`Lunas.{}.{}` is not an input of this component",
+                                name, key
+                            ));
+                        }
+                    }
+                    let dep_vars = match variables.iter().any(|v| v == key) {
+                        true => vec![key.to_string()],
+                        false => vec![],
+                    };
+                    return replace(reference(key), dep_vars);
+                }
+                Intrinsic::Env => {
+                    let Some((context, env)) =
+                        context.and_then(|context| Some((context, context.env?)))
+                    else {
+                        return Err(env_outside_script_error());
+                    };
+                    let Some(value) = env.get(key) else {
+                        let line = context.script[..start_position as usize]
+                            .matches('\n')
+                            .count()
+                            + 1;
+                        return Err(format!(
+                            "`Lunas.{}.{}` is not set in `env` (line {} of `{}`)",
+                            name,
+                            key,
+                            line,
+                            match context.module_scope {
+                                true => "script module:",
+                                false => "script:",
+                            }
+                        ));
+                    };
+                    return replace(string_literal(value), vec![]);
+                }
+                Intrinsic::Helper(_) => {}
+            }
+        }
+    }
+
+    let Some(name) = find_intrinsic(member) else {
+        return Ok(None);
+    };
+    match find_table_entry(name)? {
+        Intrinsic::Helper(helper) => {
+            check_instance_scope(name, context)?;
+            replace(helper.to_string(), vec![])
+        }
+        Intrinsic::Props => match context {
+            Some(context) if context.module_scope => Err(instance_scope_error(name)),
+            Some(context) => {
+                let fields = context
+                    .inputs
+                    .iter()
+                    .map(|input| format!("{}: {}", input, reference(input)))
+                    .collect::<Vec<String>>();
+                let dep_vars = context
+                    .inputs
+                    .iter()
+                    .filter(|input| variables.contains(input))
+                    .cloned()
+                    .collect();
+                replace(format!("({{ {} }})", fields.join(", ")), dep_vars)
+            }
+            None => Err(format!(
+                "`Lunas.{}` can only be used in `script:`; read its inputs by name instead",
+                name
+            )),
+        },
+        Intrinsic::Env => match context.and_then(|context| context.env) {
+            Some(_) => Err(
+                "`Lunas.env` has to be followed by the name of a variable, as in `Lunas.env.API_URL`"
+                    .to_string(),
+            ),
+            None => Err(env_outside_script_error()),
+        },
+    }
+}
+
+fn check_instance_scope(name: &str, context: Option<&IntrinsicContext>) -> Result<(), String> {
+    match context.is_some_and(|context| context.module_scope) {
+        true => Err(instance_scope_error(name)),
+        false => Ok(()),
+    }
+}
+
+fn instance_scope_error(name: &str) -> String {
+    format!(
+        "`Lunas.{}` cannot be used in `script module:`, since it belongs to a component instance",
+        name
+    )
+}
+
+fn env_outside_script_error() -> String {
+    "`Lunas.env` can only be used in `script:`; assign the value to a variable to use it in markup"
+        .to_string()
+}
+
+// Give: Lunas.emit
+// Want: "emit"
+fn find_intrinsic(node: &Value) -> Option<&str> {
+    match node["type"] == "MemberExpression"
+        && node["object"]["type"] == "Identifier"
+        && node["object"]["value"] == "Lunas"
+    {
+        true => find_property_name(node),
+        false => None,
+    }
+}
+
+// `obj[key]` is not resolved, since its key is only known at runtime
+fn find_property_name(member: &Value) -> Option<&str> {
+    match member["property"]["type"] == "Identifier" {
+        true => member["property"]["value"].as_str(),
+        false => None,
+    }
+}
+
+fn find_table_entry(name: &str) -> Result<&'static Intrinsic, String> {
+    match INTRINSICS.iter().find(|(key, _)| *key == name) {
+        Some((_, entry)) => Ok(entry),
+        None => Err(format!(
+            "`Lunas.{}` is unknown; expected one of {}",
+            name,
+            INTRINSICS
+                .iter()
+                .map(|(key, _)| format!("`{}`", key))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        test_utils::{compile, contains_code},
+        CompileOptions, CompileTarget,
+    };
+
+    #[test]
+    fn resolves_intrinsics() {
+        let source = "@input start:number = 1\nhtml:\n  <div><p>${Lunas.props.start}</p><button @click=\"Lunas.emit('save', count)\">save</button></div>\n\nscript:\n  let count = Lunas.props.start;\n  const all = Lunas.props;\n  const url = Lunas.env.API_URL;\n  const save = async () => { count++; await Lunas.nextTick(); };\n";
+        let options = CompileOptions {
+            env: HashMap::from([("API_URL".to_string(), "https://example.com".to_string())]),
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains("let count = $$lunasReactive(start.v);"));
+        assert!(contains_code(&js, "const all = ({ start: start.v });"));
+        assert!(js.contains("const url = \"https://example.com\";"));
+        assert!(js.contains("await $$lunasNextTick();"));
+        assert!(js.contains("()=>$$lunasEmit('save', count.v)"));
+        assert!(js.contains("$$lunasReplaceText(`${$$lunasEscapeHtml(start.v)}`"));
+    }
+
+    #[test]
+    fn rejects_unknown_intrinsics() {
+        let options = CompileOptions::default();
+        let message = |script: &str| {
+            let source = format!("html:\n  <div></div>\n\nscript:\n  {}\n", script);
+            compile(&source, &options).unwrap_err()
+        };
+        assert!(message("Lunas.fetch(\"/api\");")
+            .starts_with("`Lunas.fetch` is unknown; expected one of `router`, "));
+        assert_eq!(
+            message("const a = Lunas.props.missing;"),
+            "`Lunas.props.missing` is not an input of this component"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_intrinsics_in_text() {
+        let source = "html:\n  <div><p>${Lunas.fetch()}</p></div>\n";
+        for target in [CompileTarget::Client, CompileTarget::Server] {
+            let options = CompileOptions {
+                target,
+                ..Default::default()
+            };
+            let message = compile(source, &options).unwrap_err();
+            assert!(message.starts_with("`Lunas.fetch` is unknown; expected one of "));
+        }
+    }
+
+    #[test]
+    fn rejects_missing_env_keys() {
+        let options = CompileOptions {
+            env: HashMap::from([("API_URL".to_string(), "https://example.com".to_string())]),
+            ..Default::default()
+        };
+        let source = "html:\n  <div></div>\n\nscript:\n  const url = Lunas.env.API_URL;\n  const mode = Lunas.env.MODE;\n";
+        assert_eq!(
+            compile(source, &options).unwrap_err(),
+            "`Lunas.env.MODE` is not set in `env` (line 2 of `script:`)"
+        );
+    }
+
+    #[test]
+    fn rejects_env_in_markup() {
+        let options = CompileOptions {
+            env: HashMap::from([("X".to_string(), "1".to_string())]),
+            ..Default::default()
+        };
+        for source in [
+            "html:\n  <div><i>${Lunas.env.X}</i></div>\n",
+            "html:\n  <div><i :title=\"Lunas.env.X\"></i></div>\n",
+        ] {
+            assert_eq!(
+                compile(source, &options).unwrap_err(),
+                "`Lunas.env` can only be used in `script:`; assign the value to a variable to use it in markup"
+            );
+        }
+    }
+
+    #[test]
+    fn resolves_env_in_tracked_functions() {
+        let options = CompileOptions {
+            env: HashMap::from([("RATE".to_string(), "2".to_string())]),
+            ..Default::default()
+        };
+        let source = "html:\n  <div>${total}</div>\n\nscript:\n  let count = 1;\n  const total = Lunas.computed(() => count * Lunas.env.RATE);\n  const add = () => count++;\n";
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains("$$lunasComputed(()=>count.v * \"2\", 1)"));
+    }
+
+    #[test]
+    fn registers_lifecycle_hooks() {
+        let source = "html:\n  <div><p>${time}</p></div>\n\nscript:\n  let time = 0;\n  Lunas.onMount(() => {\n    const id = setInterval(() => time++, 1000);\n    return () => clearInterval(id);\n  });\n  Lunas.onUpdate(() => console.log(time));\n  Lunas.onDestroy(() => console.log(\"bye\"));\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        // The hooks belong to the component instance
+        assert!(js.contains("$$lunasOnMount, $$lunasOnUpdate, $$lunasOnDestroy } = "));
+        assert!(contains_code(
+            &js,
+            "$$lunasOnMount(()=>{ const id = setInterval(()=>time.v++, 1000);"
+        ));
+        assert!(js.contains("$$lunasOnUpdate(()=>console.log(time.v));"));
+        assert!(js.contains("$$lunasOnDestroy(()=>console.log(\"bye\"));"));
+
+        // On the server, they are imported no-ops
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains("$$lunasOnMount, $$lunasOnUpdate, $$lunasOnDestroy } from "));
+    }
+}
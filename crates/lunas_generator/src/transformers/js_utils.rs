@@ -1,117 +1,675 @@
 use std::vec;
 
+use lunas_html_parser::Node;
 use lunas_parser::DetailedBlock;
 use serde_json::{Map, Value};
 
-use crate::structs::transform_info::{
-    AddStringToPosition, RemoveStatement, ReplaceText, TransformInfo, VariableNameAndAssignedNumber,
+use crate::structs::{
+    compile_options::{CompileOptions, ModuleFormat},
+    transform_info::{
+        word_is_one_word, AddStringToPosition, RemoveStatement, TransformInfo,
+        VariableNameAndAssignedNumber,
+    },
 };
 
-use super::utils::add_or_remove_strings_to_script;
+use super::{
+    codegen::text_to_template,
+    exports::{find_module_exports, split_exports, ExportedBinding},
+    intrinsics::{resolve_intrinsics, IntrinsicContext},
+    utils::add_or_remove_strings_to_script,
+    utils_swc::parse_with_swc,
+};
 
 pub fn analyze_js(
     blocks: &DetailedBlock,
     initial_num: u32,
     variables: &mut Vec<VariableNameAndAssignedNumber>,
-) -> (Vec<String>, Vec<String>, String) {
+    exports: &mut Vec<ExportedBinding>,
+    intrinsics: &IntrinsicContext,
+) -> Result<(Vec<String>, Vec<String>, String), String> {
+    check_markup_intrinsics(&blocks.detailed_language_blocks.dom.children)?;
     if let Some(js_block) = &blocks.detailed_language_blocks.js {
-        let mut positions = vec![];
+        let (ast, mut positions, exported) = split_exports(&js_block.ast, &js_block.raw)?;
+        exports.extend(exported);
         let mut imports = vec![];
+        // Bindings that are never written keep their values, so they don't need to be reactive
+        let mut written_variables = find_written_variables(&ast);
+        written_variables.extend(find_variables_written_by_markup(
+            &blocks.detailed_language_blocks.dom.children,
+        ));
         // find all variable declarations
-        let str_positions = find_variable_declarations(&js_block.ast, initial_num, variables);
-        // add all variable declarations to positions to add custom variable declaration function
-        positions.extend(str_positions);
+        let str_positions =
+            find_variable_declarations(&ast, initial_num, &written_variables, variables);
         let variable_names = variables.iter().map(|v| v.name.clone()).collect();
-        let (position_result, import_result, _) = search_json(
-            &js_block.ast,
-            &js_block.raw,
-            &variable_names,
-            Some(&imports),
-            None,
-        );
+        let (position_result, import_result, _) =
+            search_json(&ast, &js_block.raw, &variable_names, Some(&imports));
         positions.extend(position_result);
         imports.extend(import_result);
+        positions.extend(resolve_intrinsics(&ast, &variable_names, Some(intrinsics))?.0);
+        // Give: const total = Lunas.computed(() => price * count);
+        // Want: const total = $$lunasComputed(() => price.v * count.v, 3);
+        for call in find_tracked_calls(&ast) {
+            let Some(arguments) = call["arguments"].as_array().filter(|a| !a.is_empty()) else {
+                continue;
+            };
+            let tracked_function = &arguments[0]["expression"];
+            let (_, _, mut dep_vars) =
+                search_json(tracked_function, &js_block.raw, &variable_names, None);
+            dep_vars
+                .extend(resolve_intrinsics(tracked_function, &variable_names, Some(intrinsics))?.1);
+            // An effect is not run again by its own writes, so that it doesn't trigger itself
+            let written_vars = match find_tracked_call(call) {
+                Some("effect") => find_written_variables(tracked_function),
+                _ => vec![],
+            };
+            let dep_bits = variables
+                .iter()
+                .filter(|v| dep_vars.contains(&v.name) && !written_vars.contains(&v.name))
+                .fold(0, |bits, v| bits | v.assignment);
+            positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+                position: get_span(&arguments[arguments.len() - 1]["expression"]).1,
+                string: format!(", {}", dep_bits),
+            }));
+        }
+        // add all variable declarations to positions to add custom variable declaration function
+        // after `.v`, so that `let b = a` becomes `$$lunasReactive(a.v)`
+        positions.extend(str_positions);
         let output = add_or_remove_strings_to_script(positions, &js_block.raw);
-        (variable_names, imports, output)
+        Ok((variable_names, imports, output))
     } else {
         let variable_names = variables
             .iter()
             .map(|v| v.name.clone())
             .collect::<Vec<String>>();
-        (variable_names, vec![], "".to_string())
+        Ok((variable_names, vec![], "".to_string()))
+    }
+}
+
+// Removes the import declarations and `export`s from the script without making its variables reactive
+pub fn extract_imports(
+    blocks: &DetailedBlock,
+    intrinsics: &IntrinsicContext,
+) -> Result<(Vec<String>, String), String> {
+    check_markup_intrinsics(&blocks.detailed_language_blocks.dom.children)?;
+    match &blocks.detailed_language_blocks.js {
+        Some(js_block) => {
+            let (ast, mut positions, _) = split_exports(&js_block.ast, &js_block.raw)?;
+            let (position_result, imports, _) = search_json(&ast, &js_block.raw, &vec![], None);
+            positions.extend(position_result);
+            positions.extend(resolve_intrinsics(&ast, &[], Some(intrinsics))?.0);
+            let output = add_or_remove_strings_to_script(positions, &js_block.raw);
+            Ok((imports, output))
+        }
+        None => Ok((vec![], "".to_string())),
+    }
+}
+
+// Give: script module: import { format } from "./fmt.js"; export const cache = new Map();
+// Want: (["import { format } from \"./fmt.js\";"], "export const cache = new Map();")
+/// Removes the import declarations and `export ... from` statements from `script module:`,
+/// which is placed in the component module as-is, so its bindings are shared by the instances and not reactive.
+pub fn extract_module_script(
+    blocks: &DetailedBlock,
+    options: &CompileOptions,
+) -> Result<(Vec<String>, String), String> {
+    let Some(js_block) = &blocks.detailed_language_blocks.module_js else {
+        return Ok((vec![], "".to_string()));
+    };
+    let exports = find_module_exports(&js_block.ast, &js_block.raw)?;
+    let has_exports = !exports.bindings.is_empty() || !exports.reexports.is_empty();
+    if has_exports && options.format != ModuleFormat::Esm {
+        return Err(
+            "`export` in `script module:` can only be used when the component is compiled into an ES module"
+                .to_string(),
+        );
     }
+    let intrinsics = IntrinsicContext {
+        env: Some(&options.env),
+        script: &js_block.raw,
+        module_scope: true,
+        ..Default::default()
+    };
+    let (mut positions, mut imports, _) = search_json(&js_block.ast, &js_block.raw, &vec![], None);
+    positions.extend(resolve_intrinsics(&js_block.ast, &[], Some(&intrinsics))?.0);
+    positions.extend(exports.transforms);
+    imports.extend(exports.reexports);
+    let output = add_or_remove_strings_to_script(positions, &js_block.raw);
+    Ok((imports, output.trim().to_string()))
 }
 
 // Finds all variable declarations in a javascript file and returns a vector of VariableNameAndAssignedNumber structs
+// Declarations of bindings that are not in `written_variables` are left as plain values
 fn find_variable_declarations(
     json: &Value,
     initial_num: u32,
+    written_variables: &[String],
     variables: &mut Vec<VariableNameAndAssignedNumber>,
 ) -> vec::Vec<TransformInfo> {
-    if let Some(Value::Array(body)) = json.get("body") {
-        let mut str_positions = vec![];
-        let mut num_generator = power_of_two_generator(initial_num);
-        for body_item in body {
-            if Some(&Value::String("VariableDeclaration".to_string())) == body_item.get("type") {
-                if let Some(Value::Array(declarations)) = body_item.get("declarations") {
-                    for declaration in declarations {
-                        let name = if let Some(Value::Object(id)) = declaration.get("id") {
-                            if let Some(Value::String(name)) = id.get("value") {
-                                Some(name.to_string())
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        };
-                        // get span
-                        let start_and_end =
-                            if let Some(Value::Object(init)) = declaration.get("init") {
-                                if let Some(Value::Object(span)) = init.get("span") {
-                                    if let Some(Value::Number(end)) = span.get("end") {
-                                        if let Some(Value::Number(start)) = span.get("start") {
-                                            Some((start, end))
-                                        } else {
-                                            None
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            };
-                        if let Some(name) = name {
-                            if let Some((start, end)) = start_and_end {
-                                let variable_num = num_generator();
-                                variables.push(VariableNameAndAssignedNumber {
-                                    name,
-                                    assignment: variable_num,
-                                });
-                                str_positions.push(TransformInfo::AddStringToPosition(
-                                    AddStringToPosition {
-                                        position: (start.as_u64().unwrap() - 1) as u32,
-                                        string: "$$lunasReactive(".to_string(),
-                                    },
-                                ));
-                                str_positions.push(TransformInfo::AddStringToPosition(
-                                    AddStringToPosition {
-                                        position: (end.as_u64().unwrap() - 1) as u32,
-                                        string: format!(")"),
-                                    },
-                                ));
-                            }
+    let Some(Value::Array(body)) = json.get("body") else {
+        return vec![];
+    };
+    let mut str_positions = vec![];
+    let mut num_generator = power_of_two_generator(initial_num);
+    let top_level_declarators = body
+        .iter()
+        .filter(|body_item| body_item["type"] == "VariableDeclaration")
+        .flat_map(|body_item| body_item["declarations"].as_array().into_iter().flatten())
+        .collect::<Vec<&Value>>();
+    let top_level_names = top_level_declarators
+        .iter()
+        .flat_map(|declarator| find_pattern_names(&declarator["id"]))
+        .collect::<Vec<String>>();
+
+    // `var`s in nested blocks belong to the component, so they are declared before the script
+    // and the original declarations become assignments
+    let nested_vars = body
+        .iter()
+        .filter(|body_item| body_item["type"] != "VariableDeclaration")
+        .flat_map(|body_item| find_var_declarations(body_item, false))
+        .filter(|(declaration, _)| {
+            find_declared_names(declaration)
+                .iter()
+                .any(|name| written_variables.contains(name))
+        })
+        .collect::<Vec<(&Value, bool)>>();
+    let mut hoisted_names: Vec<String> = vec![];
+    for (declaration, _) in &nested_vars {
+        for name in find_declared_names(declaration) {
+            if !top_level_names.contains(&name) && !hoisted_names.contains(&name) {
+                hoisted_names.push(name);
+            }
+        }
+    }
+    if !hoisted_names.is_empty() {
+        let mut hoisted_declarations = String::new();
+        for name in hoisted_names {
+            if written_variables.contains(&name) {
+                hoisted_declarations
+                    .push_str(&format!("var {} = $$lunasReactive(undefined);\n", name));
+                variables.push(VariableNameAndAssignedNumber {
+                    name,
+                    assignment: num_generator(),
+                });
+            } else {
+                hoisted_declarations.push_str(&format!("var {};\n", name));
+            }
+        }
+        str_positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+            position: 0,
+            string: hoisted_declarations,
+        }));
+    }
+    for (declaration, in_loop_head) in nested_vars {
+        str_positions.extend(convert_var_to_assignment(
+            declaration,
+            in_loop_head,
+            written_variables,
+        ));
+    }
+
+    for declarator in top_level_declarators {
+        let id = &declarator["id"];
+        let init = declarator.get("init").filter(|init| !init.is_null());
+        match (id["value"].as_str(), init) {
+            // Computed values get their own bit, which is set when they are computed again
+            (Some(name), Some(init)) if find_tracked_call(init) == Some("computed") => {
+                variables.push(VariableNameAndAssignedNumber {
+                    name: name.to_string(),
+                    assignment: num_generator(),
+                });
+            }
+            (Some(name), _) if !written_variables.iter().any(|v| v == name) => {}
+            (Some(name), Some(init)) => {
+                variables.push(VariableNameAndAssignedNumber {
+                    name: name.to_string(),
+                    assignment: num_generator(),
+                });
+                let (start, end) = get_span(init);
+                str_positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: start,
+                    string: "$$lunasReactive(".to_string(),
+                }));
+                str_positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: end,
+                    string: ")".to_string(),
+                }));
+            }
+            (Some(name), None) => {
+                variables.push(VariableNameAndAssignedNumber {
+                    name: name.to_string(),
+                    assignment: num_generator(),
+                });
+                str_positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: get_span(id).1,
+                    string: " = $$lunasReactive(undefined)".to_string(),
+                }));
+            }
+            // Give: let { a, b: [c] = [] } = obj;
+            // Want: let [a, c] = (({ a, b: [c] = [] }) => [$$lunasReactive(a), $$lunasReactive(c)])(obj);
+            (None, Some(init)) => {
+                let names = find_pattern_names(id);
+                if !names.iter().any(|name| written_variables.contains(name)) {
+                    continue;
+                }
+                let mut values = vec![];
+                for name in &names {
+                    if written_variables.contains(name) {
+                        values.push(format!("$$lunasReactive({})", name));
+                        variables.push(VariableNameAndAssignedNumber {
+                            name: name.clone(),
+                            assignment: num_generator(),
+                        });
+                    } else {
+                        values.push(name.clone());
+                    }
+                }
+                let (pattern_start, pattern_end) = get_span(id);
+                let (init_start, init_end) = get_span(init);
+                str_positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: pattern_start,
+                    string: format!("[{}] = ((", names.join(", ")),
+                }));
+                str_positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: pattern_end,
+                    string: format!(") => [{}])(", values.join(", ")),
+                }));
+                str_positions.push(TransformInfo::RemoveStatement(RemoveStatement {
+                    start_position: pattern_end,
+                    end_position: init_start,
+                }));
+                str_positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: init_end,
+                    string: ")".to_string(),
+                }));
+            }
+            (None, None) => {}
+        }
+    }
+    str_positions
+}
+
+// The name of the `Lunas` function that `expr` calls, if its dependencies are tracked
+fn find_tracked_call(expr: &Value) -> Option<&str> {
+    let callee = &expr["callee"];
+    match expr["type"] == "CallExpression"
+        && callee["type"] == "MemberExpression"
+        && callee["object"]["value"] == "Lunas"
+    {
+        true => callee["property"]["value"]
+            .as_str()
+            .filter(|name| ["computed", "effect", "watch"].contains(name)),
+        false => None,
+    }
+}
+
+// The calls to `Lunas.computed`, `Lunas.effect` and `Lunas.watch` at the top level of the script
+fn find_tracked_calls(json: &Value) -> Vec<&Value> {
+    json["body"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|body_item| match body_item["type"].as_str() {
+            Some("VariableDeclaration") => body_item["declarations"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|declarator| &declarator["init"])
+                .collect(),
+            Some("ExpressionStatement") => vec![&body_item["expression"]],
+            _ => vec![],
+        })
+        .filter(|expr| find_tracked_call(expr).is_some())
+        .collect()
+}
+
+// Give: var a = 1, { b } = c; with a and b reactive
+// Want: (a.v = 1, { b: b.v } = c);
+/// Turns a `var` declaration whose bindings are declared before the script into an assignment.
+fn convert_var_to_assignment(
+    declaration: &Value,
+    in_loop_head: bool,
+    written_variables: &[String],
+) -> Vec<TransformInfo> {
+    let declarators = declaration["declarations"].as_array().unwrap();
+    let (first_start, _) = get_span(&declarators[0]);
+    let (_, last_end) = get_span(&declarators[declarators.len() - 1]);
+    let mut positions = vec![TransformInfo::RemoveStatement(RemoveStatement {
+        start_position: get_span(declaration).0,
+        end_position: first_start,
+    })];
+    // A statement starting with `{` would be a block
+    let needs_parens = !in_loop_head && declarators[0]["id"]["type"] == "ObjectPattern";
+    if needs_parens {
+        positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+            position: first_start,
+            string: "(".to_string(),
+        }));
+    }
+    for declarator in declarators {
+        positions.extend(find_binding_references(
+            &declarator["id"],
+            written_variables,
+        ));
+    }
+    if needs_parens {
+        positions.push(TransformInfo::AddStringToPosition(AddStringToPosition {
+            position: last_end,
+            string: ")".to_string(),
+        }));
+    }
+    positions
+}
+
+// Give: [a, { b, c: d }] with a, b and d reactive
+// Want: [a.v, { b: b.v, c: d.v }]
+fn find_binding_references(pattern: &Value, written_variables: &[String]) -> Vec<TransformInfo> {
+    let reference = |identifier: &Value, prefix: &str| {
+        let name = identifier["value"].as_str().unwrap_or_default();
+        match written_variables.iter().any(|v| v == name) {
+            true => vec![TransformInfo::AddStringToPosition(AddStringToPosition {
+                position: get_span(identifier).1,
+                string: format!("{}.v", prefix),
+            })],
+            false => vec![],
+        }
+    };
+    match pattern["type"].as_str() {
+        Some("Identifier") => reference(pattern, ""),
+        Some("AssignmentPattern") => find_binding_references(&pattern["left"], written_variables),
+        Some("RestElement") => find_binding_references(&pattern["argument"], written_variables),
+        Some("ArrayPattern") => pattern["elements"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|element| find_binding_references(element, written_variables))
+            .collect(),
+        Some("ObjectPattern") => pattern["properties"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|property| match property["type"].as_str() {
+                Some("AssignmentPatternProperty") => {
+                    let key = &property["key"];
+                    reference(
+                        key,
+                        &format!(": {}", key["value"].as_str().unwrap_or_default()),
+                    )
+                }
+                Some("KeyValuePatternProperty") => {
+                    find_binding_references(&property["value"], written_variables)
+                }
+                _ => find_binding_references(property, written_variables),
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// Give: if (a) { var b = 1; } for (var c of d) {} const f = () => { var e; };
+// Want: the declarations of b and c, with whether they are the head of a loop
+/// `var` declarations that belong to the function or component containing `json`.
+fn find_var_declarations(json: &Value, in_loop_head: bool) -> Vec<(&Value, bool)> {
+    match json {
+        // Functions have their own `var`s
+        Value::Object(obj) if obj.contains_key("params") => vec![],
+        Value::Object(obj)
+            if obj.get("type") == Some(&Value::String("VariableDeclaration".into())) =>
+        {
+            match obj["kind"] == "var" {
+                true => vec![(json, in_loop_head)],
+                false => vec![],
+            }
+        }
+        Value::Object(obj) => {
+            let is_loop = matches!(
+                obj.get("type").and_then(Value::as_str),
+                Some("ForStatement" | "ForInStatement" | "ForOfStatement")
+            );
+            obj.iter()
+                .flat_map(|(key, value)| {
+                    find_var_declarations(value, is_loop && (key == "init" || key == "left"))
+                })
+                .collect()
+        }
+        Value::Array(arr) => arr
+            .iter()
+            .flat_map(|value| find_var_declarations(value, false))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+pub fn find_declared_names(declaration: &Value) -> Vec<String> {
+    declaration["declarations"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|declarator| find_pattern_names(&declarator["id"]))
+        .collect()
+}
+
+// Give: (a, { b }) => { var c; let d; function e() {} }
+// Want: ["a", "b", "c"] for the function, and ["d", "e"] for its body
+/// Names declared in the scope that `node` opens, which hide the component's bindings inside it.
+fn find_scope_declarations(node: &Map<String, Value>) -> Vec<String> {
+    let mut names = vec![];
+    let declared_by = |declaration: &Value| match declaration["kind"].as_str() {
+        Some("let" | "const") => find_declared_names(declaration),
+        _ => vec![],
+    };
+    if let (Some(Value::Array(params)), Some(body)) = (node.get("params"), node.get("body")) {
+        for param in params {
+            names.extend(find_pattern_names(param.get("pat").unwrap_or(param)));
+        }
+        names.extend(
+            find_var_declarations(body, false)
+                .into_iter()
+                .flat_map(|(declaration, _)| find_declared_names(declaration)),
+        );
+    }
+    match node.get("type").and_then(Value::as_str) {
+        Some("FunctionExpression" | "ClassExpression") => {
+            names.extend(find_pattern_names(&node["identifier"]))
+        }
+        Some("BlockStatement") => {
+            for stmt in node["stmts"].as_array().into_iter().flatten() {
+                match stmt["type"].as_str() {
+                    Some("VariableDeclaration") => names.extend(declared_by(stmt)),
+                    Some("FunctionDeclaration" | "ClassDeclaration") => {
+                        names.extend(find_pattern_names(&stmt["identifier"]))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Some("ForStatement") => names.extend(declared_by(&node["init"])),
+        Some("ForInStatement" | "ForOfStatement") => names.extend(declared_by(&node["left"])),
+        Some("CatchClause") => names.extend(find_pattern_names(&node["param"])),
+        _ => {}
+    }
+    names
+}
+
+// The positions of the node in the script, which swc counts from 1
+pub fn get_span(node: &Value) -> (u32, u32) {
+    let span = &node["span"];
+    (
+        span["start"].as_u64().unwrap() as u32 - 1,
+        span["end"].as_u64().unwrap() as u32 - 1,
+    )
+}
+
+/// Methods that change the array they are called on.
+const MUTATING_METHODS: [&str; 9] = [
+    "push",
+    "pop",
+    "shift",
+    "unshift",
+    "splice",
+    "sort",
+    "reverse",
+    "fill",
+    "copyWithin",
+];
+
+/// Methods that change the map or set they are called on.
+/// Objects like `classList` or `URLSearchParams` share these names, so they only count
+/// when they are called on a binding directly.
+const COLLECTION_MUTATING_METHODS: [&str; 4] = ["set", "add", "delete", "clear"];
+
+// Give: count++; [a, { b }] = pair; user.name = "x"; items.push(1); const c = count;
+// Want: ["count", "a", "b", "user", "items"]
+/// Names of the bindings that are reassigned, or whose objects are changed, anywhere in `json`.
+/// A write to a property counts as a write to the binding holding the object.
+/// Writes to names declared in nested scopes are left out.
+pub fn find_written_variables(json: &Value) -> Vec<String> {
+    match json {
+        Value::Object(obj) => {
+            let mut names = vec![];
+            if let Some(target) = find_write_target(obj) {
+                names.extend(find_pattern_names(target));
+            }
+            if let Some("ForInStatement" | "ForOfStatement") =
+                obj.get("type").and_then(Value::as_str)
+            {
+                let left = &obj["left"];
+                match left["type"] == "VariableDeclaration" {
+                    true => names.extend(find_declared_names(left)),
+                    false => names.extend(find_pattern_names(left)),
+                }
+            }
+            for value in obj.values() {
+                names.extend(find_written_variables(value));
+            }
+            // Writes to names declared in a nested scope don't change the component's bindings
+            let shadowed = find_scope_declarations(obj);
+            names.retain(|name| !shadowed.contains(name));
+            names
+        }
+        Value::Array(arr) => arr.iter().flat_map(find_written_variables).collect(),
+        _ => vec![],
+    }
+}
+
+// Give: user.name = "a" or items.push(1) or tags.add("a")
+// Want: user.name or items or tags
+/// The expression that `node` writes to, if it is an assignment or changes an object in place.
+fn find_write_target(node: &Map<String, Value>) -> Option<&Value> {
+    match node.get("type").and_then(Value::as_str) {
+        Some("AssignmentExpression") => Some(&node["left"]),
+        Some("UpdateExpression") => Some(&node["argument"]),
+        Some("UnaryExpression") if node["operator"] == "delete" => Some(&node["argument"]),
+        Some("CallExpression") => {
+            let callee = &node["callee"];
+            if callee["type"] != "MemberExpression" {
+                return None;
+            }
+            let method = callee["property"]["value"].as_str().unwrap_or_default();
+            if callee["object"]["value"] == "Object" && method == "assign" {
+                Some(&node["arguments"][0]["expression"])
+            } else if MUTATING_METHODS.contains(&method)
+                || (COLLECTION_MUTATING_METHODS.contains(&method)
+                    && callee["object"]["type"] == "Identifier")
+            {
+                Some(&callee["object"])
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+// Give: [a, { b, c: d, ...e }] or user.profile.name
+// Want: ["a", "b", "d", "e"] or ["user"]
+pub fn find_pattern_names(pattern: &Value) -> Vec<String> {
+    match pattern["type"].as_str() {
+        Some("Identifier") => pattern["value"]
+            .as_str()
+            .map(str::to_string)
+            .into_iter()
+            .collect(),
+        Some("MemberExpression") => find_pattern_names(&pattern["object"]),
+        Some("ParenthesisExpression") => find_pattern_names(&pattern["expression"]),
+        Some("AssignmentPattern") => find_pattern_names(&pattern["left"]),
+        Some("RestElement") => find_pattern_names(&pattern["argument"]),
+        Some("ArrayPattern") => pattern["elements"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(find_pattern_names)
+            .collect(),
+        Some("ObjectPattern") => pattern["properties"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|property| match property["type"].as_str() {
+                Some("AssignmentPatternProperty") => find_pattern_names(&property["key"]),
+                Some("KeyValuePatternProperty") => find_pattern_names(&property["value"]),
+                _ => find_pattern_names(property),
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// Give: <input ::value="name"><button @click="count++">
+// Want: ["name", "count"]
+fn find_variables_written_by_markup(nodes: &[Node]) -> Vec<String> {
+    let mut names = vec![];
+    for node in nodes {
+        if let Node::Element(element) = node {
+            for (key, value) in &element.attributes {
+                match (key, value) {
+                    (key, Some(value)) if key.starts_with("::") => names.push(value.clone()),
+                    (key, Some(value)) if key.starts_with('@') && !word_is_one_word(value) => {
+                        let statement = serde_json::to_value(parse_with_swc(value)).unwrap();
+                        names.extend(find_written_variables(&statement));
+                    }
+                    _ => {}
+                }
+            }
+            names.extend(find_variables_written_by_markup(&element.children));
+        }
+    }
+    names
+}
+
+// Markup expressions are resolved while the markup is transformed, so they are checked beforehand
+fn check_markup_intrinsics(nodes: &[Node]) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::Element(element) => {
+                for (key, value) in &element.attributes {
+                    match (key, value) {
+                        (key, Some(value))
+                            if (key.starts_with(':') || key.starts_with('@'))
+                                && value.contains("Lunas.") =>
+                        {
+                            let expression = serde_json::to_value(parse_with_swc(value)).unwrap();
+                            resolve_intrinsics(&expression, &[], None)?;
                         }
+                        _ => {}
                     }
                 }
+                check_markup_intrinsics(&element.children)?;
             }
+            // Give: <p>${Lunas.fetch()}</p>
+            Node::Text(text) if text.contains("Lunas.") => {
+                let mut expressions = vec![];
+                text_to_template(text, |expression| {
+                    expressions.push(expression.to_string());
+                    String::new()
+                });
+                for expression in expressions {
+                    let expression = serde_json::to_value(parse_with_swc(&expression)).unwrap();
+                    resolve_intrinsics(&expression, &[], None)?;
+                }
+            }
+            _ => {}
         }
-        str_positions
-    } else {
-        vec![]
     }
+    Ok(())
 }
 
 fn power_of_two_generator(init: u32) -> impl FnMut() -> u32 {
@@ -123,6 +681,8 @@ fn power_of_two_generator(init: u32) -> impl FnMut() -> u32 {
     }
 }
 
+type SearchResult = (vec::Vec<TransformInfo>, vec::Vec<String>, vec::Vec<String>);
+
 // TODO: (P5) Use mutable references for the arguments instead of returning them
 pub fn search_json(
     json: &Value,
@@ -130,39 +690,17 @@ pub fn search_json(
     variables: &Vec<String>,
     // FIXME: imports are unused
     imports: Option<&Vec<String>>,
-    parent: Option<&Map<String, Value>>,
-) -> (vec::Vec<TransformInfo>, vec::Vec<String>, vec::Vec<String>) {
+) -> SearchResult {
     if let Value::Object(obj) = json {
-        if obj.contains_key("type") && obj["type"] == Value::String("Identifier".into()) {
-            if parent.is_some()
-                && parent.unwrap().get("type")
-                    != Some(&Value::String("VariableDeclarator".to_string()))
-            {
-                if let Some(Value::String(variable_name)) = obj.get("value") {
-                    if variables.iter().any(|e| e == variable_name) {
-                        if let Some(Value::Object(span)) = obj.get("span") {
-                            if let Some(Value::Number(end)) = span.get("end") {
-                                return (
-                                    vec![TransformInfo::AddStringToPosition(AddStringToPosition {
-                                        position: (end.as_u64().unwrap() - 1) as u32,
-                                        string: ".v".to_string(),
-                                    })],
-                                    vec![],
-                                    vec![variable_name.clone()],
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-
-            return (vec![], vec![], vec![]);
+        let node_type = obj.get("type").and_then(Value::as_str);
+        if node_type == Some("Identifier") {
+            return reference_variable(obj, variables, "");
         } else if obj.contains_key("type")
             && obj["type"] == Value::String("ImportDeclaration".into())
         {
             let trim_end = obj["span"]["end"].as_u64().unwrap() as u32;
-            let mut remove_end = trim_end;
-            if raw_js.chars().nth(trim_end as usize).unwrap() == '\n' {
+            let mut remove_end = trim_end.min(raw_js.len() as u32);
+            if raw_js.chars().nth(trim_end as usize) == Some('\n') {
                 remove_end += 1;
             }
 
@@ -178,71 +716,455 @@ pub fn search_json(
                     .collect()],
                 vec![],
             );
-        } else if obj.contains_key("type")
-            && obj["type"] == Value::String("MemberExpression".into())
-        {
-            if let Some(object) = obj.get("object") {
-                if let Some(property) = obj.get("property") {
-                    let is_target_property = if let Value::Object(property) = property {
-                        if let Some(Value::String(property_value)) = property.get("value") {
-                            property_value == "router"
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    };
-                    let is_target_object = if let Value::Object(object) = object {
-                        if let Some(Value::String(object_value)) = object.get("value") {
-                            object_value == "Lunas"
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    };
-                    if is_target_object && is_target_property {
-                        if let Some(Value::Object(span)) = obj.get("span") {
-                            let start = span["start"].as_u64().unwrap() as u32;
-                            let end = span["end"].as_u64().unwrap() as u32;
-                            return (
-                                vec![TransformInfo::ReplaceText(ReplaceText {
-                                    start_position: start - 1,
-                                    end_position: end - 1,
-                                    string: "$$lunasRouter".to_string(),
-                                })],
-                                vec![],
-                                vec![],
-                            );
+        }
+        // Names declared in a nested scope hide the component's variables inside it
+        let shadowed = find_scope_declarations(obj);
+        let scoped_variables;
+        let variables = if shadowed.iter().any(|name| variables.contains(name)) {
+            scoped_variables = variables
+                .iter()
+                .filter(|v| !shadowed.contains(v))
+                .cloned()
+                .collect::<Vec<String>>();
+            &scoped_variables
+        } else {
+            variables
+        };
+        // Give: items.push(1) or user.name = "a"
+        // Want: items.touch(items.v.push(1)) or user.touch(user.v.name = "a")
+        // Changes inside an object don't go through its setter, so they are reported after they are made
+        let touched = find_write_target(obj)
+            .filter(|target| {
+                node_type == Some("CallExpression") || target["type"] == "MemberExpression"
+            })
+            .and_then(|target| find_pattern_names(target).into_iter().next())
+            .filter(|name| variables.contains(name));
+        let mut results = vec![];
+        if let Some(name) = &touched {
+            results.push((
+                vec![TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: get_span(json).0,
+                    string: format!("{}.touch(", name),
+                })],
+                vec![],
+                vec![],
+            ));
+        }
+        for (key, value) in obj {
+            results.push(match (node_type, key.as_str()) {
+                (Some("VariableDeclarator"), "id") | (Some("CatchClause"), "param") => {
+                    search_pattern(value, raw_js, variables, imports)
+                }
+                (_, "params") if obj.contains_key("body") => {
+                    merge_results(value.as_array().into_iter().flatten().map(|param| {
+                        search_pattern(
+                            param.get("pat").unwrap_or(param),
+                            raw_js,
+                            variables,
+                            imports,
+                        )
+                    }))
+                }
+                // Give: { count } or ({ count } = obj)
+                // Want: { count: count.v } or ({ count: count.v } = obj)
+                (Some("ObjectExpression"), "properties") => {
+                    merge_results(value.as_array().into_iter().flatten().map(|property| {
+                        match property["type"] == "Identifier" {
+                            true => shorthand_reference(property, variables),
+                            false => search_json(property, raw_js, variables, imports),
                         }
-                    }
+                    }))
                 }
-            }
+                (Some("AssignmentPatternProperty"), "key") => shorthand_reference(value, variables),
+                _ if is_name_position(node_type, key, value) => continue,
+                _ => search_json(value, raw_js, variables, imports),
+            });
         }
-        let mut trans_tmp = vec![];
-        let mut import_tmp = vec![];
-        let mut dep_vars_tmp = vec![];
-        for (_key, value) in obj {
-            let (trans_res, import_res, dep_vars) =
-                search_json(value, raw_js, variables, imports, Some(&obj));
-            trans_tmp.extend(trans_res);
-            import_tmp.extend(import_res);
-            dep_vars_tmp.extend(dep_vars);
+        if touched.is_some() {
+            results.push((
+                vec![TransformInfo::AddStringToPosition(AddStringToPosition {
+                    position: get_span(json).1,
+                    string: ")".to_string(),
+                })],
+                vec![],
+                vec![],
+            ));
         }
-        return (trans_tmp, import_tmp, dep_vars_tmp);
+        return merge_results(results);
     } else if let Value::Array(arr) = json {
-        let mut trans_tmp = vec![];
-        let mut import_tmp = vec![];
-        let mut dep_vars_tmp = vec![];
-        for child_value in arr {
-            // TODO: Pass parent to search_json
-            let (trans_res, import_res, dep_vars) =
-                search_json(child_value, raw_js, variables, imports, None);
-            trans_tmp.extend(trans_res);
-            import_tmp.extend(import_res);
-            dep_vars_tmp.extend(dep_vars);
-        }
-        return (trans_tmp, import_tmp, dep_vars_tmp);
+        return merge_results(
+            arr.iter()
+                .map(|child_value| search_json(child_value, raw_js, variables, imports)),
+        );
     }
     return (vec![], vec![], vec![]);
 }
+
+fn merge_results(results: impl IntoIterator<Item = SearchResult>) -> SearchResult {
+    let mut merged: SearchResult = (vec![], vec![], vec![]);
+    for (trans_res, import_res, dep_vars) in results {
+        merged.0.extend(trans_res);
+        merged.1.extend(import_res);
+        merged.2.extend(dep_vars);
+    }
+    merged
+}
+
+fn reference_variable(
+    identifier: &Map<String, Value>,
+    variables: &[String],
+    prefix: &str,
+) -> SearchResult {
+    if let Some(Value::String(variable_name)) = identifier.get("value") {
+        if variables.iter().any(|e| e == variable_name) {
+            if let Some(Value::Object(span)) = identifier.get("span") {
+                if let Some(Value::Number(end)) = span.get("end") {
+                    return (
+                        vec![TransformInfo::AddStringToPosition(AddStringToPosition {
+                            position: (end.as_u64().unwrap() - 1) as u32,
+                            string: format!("{}.v", prefix),
+                        })],
+                        vec![],
+                        vec![variable_name.clone()],
+                    );
+                }
+            }
+        }
+    }
+    (vec![], vec![], vec![])
+}
+
+fn shorthand_reference(identifier: &Value, variables: &[String]) -> SearchResult {
+    match identifier {
+        Value::Object(obj) => {
+            let prefix = format!(": {}", obj["value"].as_str().unwrap_or_default());
+            reference_variable(obj, variables, &prefix)
+        }
+        _ => (vec![], vec![], vec![]),
+    }
+}
+
+// Searches the expressions in a pattern that declares names, such as its default values
+fn search_pattern(
+    pattern: &Value,
+    raw_js: &String,
+    variables: &Vec<String>,
+    imports: Option<&Vec<String>>,
+) -> SearchResult {
+    search_pattern_after(pattern, raw_js, variables, imports, &mut vec![])
+}
+
+// Give: { a, b = a } or [x, y = x]
+// Want: { a, b = a } or [x, y = x] (the defaults read the names bound before them, not the variables)
+/// `bound` collects the names declared by the pattern so far, which hide the variables in later defaults.
+fn search_pattern_after(
+    pattern: &Value,
+    raw_js: &String,
+    variables: &Vec<String>,
+    imports: Option<&Vec<String>>,
+    bound: &mut Vec<String>,
+) -> SearchResult {
+    match pattern["type"].as_str() {
+        Some("Identifier") => {
+            bound.extend(find_pattern_names(pattern));
+            (vec![], vec![], vec![])
+        }
+        // The default is evaluated before the name on the left is bound
+        Some("AssignmentPattern") => {
+            let default = search_json(
+                &pattern["right"],
+                raw_js,
+                &unbound(variables, bound),
+                imports,
+            );
+            merge_results([
+                default,
+                search_pattern_after(&pattern["left"], raw_js, variables, imports, bound),
+            ])
+        }
+        Some("RestElement") => {
+            search_pattern_after(&pattern["argument"], raw_js, variables, imports, bound)
+        }
+        Some("ArrayPattern") => {
+            let mut results = vec![];
+            for element in pattern["elements"].as_array().into_iter().flatten() {
+                results.push(search_pattern_after(
+                    element, raw_js, variables, imports, bound,
+                ));
+            }
+            merge_results(results)
+        }
+        Some("ObjectPattern") => {
+            let mut results = vec![];
+            for property in pattern["properties"].as_array().into_iter().flatten() {
+                let unbound_variables = unbound(variables, bound);
+                results.push(match property["type"].as_str() {
+                    Some("AssignmentPatternProperty") => {
+                        let default =
+                            search_json(&property["value"], raw_js, &unbound_variables, imports);
+                        bound.extend(find_pattern_names(&property["key"]));
+                        default
+                    }
+                    Some("KeyValuePatternProperty") => merge_results([
+                        match property["key"]["type"] == "Computed" {
+                            true => {
+                                search_json(&property["key"], raw_js, &unbound_variables, imports)
+                            }
+                            false => (vec![], vec![], vec![]),
+                        },
+                        search_pattern_after(&property["value"], raw_js, variables, imports, bound),
+                    ]),
+                    _ => search_pattern_after(property, raw_js, variables, imports, bound),
+                });
+            }
+            merge_results(results)
+        }
+        _ => search_json(pattern, raw_js, &unbound(variables, bound), imports),
+    }
+}
+
+fn unbound(variables: &[String], bound: &[String]) -> Vec<String> {
+    variables
+        .iter()
+        .filter(|v| !bound.contains(v))
+        .cloned()
+        .collect()
+}
+
+// Property names, labels and the names of functions and classes are not references to variables
+fn is_name_position(node_type: Option<&str>, key: &str, value: &Value) -> bool {
+    match key {
+        "property" => {
+            matches!(node_type, Some("MemberExpression" | "SuperPropExpression"))
+                && value["type"] != "Computed"
+        }
+        "key" => value["type"] != "Computed",
+        "label" => true,
+        "identifier" => matches!(
+            node_type,
+            Some(
+                "FunctionDeclaration"
+                    | "FunctionExpression"
+                    | "ClassDeclaration"
+                    | "ClassExpression"
+            )
+        ),
+        _ => false,
+    }
+}
+
+// Give: Lunas.emit("change", value); Lunas.emit(name)
+// Want: ["change"]
+// Event names that are not string literals are not checked
+pub fn find_emitted_events(json: &Value) -> Vec<String> {
+    match json {
+        Value::Object(obj) => {
+            let mut events = vec![];
+            if obj.get("type") == Some(&Value::String("CallExpression".into())) {
+                let callee = &obj["callee"];
+                if callee["type"] == "MemberExpression"
+                    && callee["object"]["value"] == "Lunas"
+                    && callee["property"]["value"] == "emit"
+                {
+                    let event_name = &obj["arguments"][0]["expression"];
+                    if let (Some("StringLiteral"), Some(name)) =
+                        (event_name["type"].as_str(), event_name["value"].as_str())
+                    {
+                        events.push(name.to_string());
+                    }
+                }
+            }
+            for value in obj.values() {
+                events.extend(find_emitted_events(value));
+            }
+            events
+        }
+        Value::Array(arr) => arr.iter().flat_map(find_emitted_events).collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lunas_parser::parse_lunas_file;
+
+    use crate::{
+        lunas_generate_types_from_block,
+        test_utils::{compile, contains_code},
+        CompileOptions, CompileTarget, ModuleFormat,
+    };
+
+    fn compile_script(script: &str) -> String {
+        let source = format!(
+            "html:\n  <div>${{a}} ${{b}}</div>\n\nscript:\n  let count = 0;\n{}\n  const f = () => {{ a++; b++; count++; }};\n",
+            script
+        );
+        compile(&source, &CompileOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn object_defaults_read_earlier_names_of_the_pattern() {
+        let js = compile_script("  let obj = {};\n  let { a, b = a + count } = obj;");
+        assert!(js.contains("({ a, b = a + count.v })=>["));
+    }
+
+    #[test]
+    fn array_defaults_read_earlier_names_of_the_pattern() {
+        let js = compile_script("  let pair = [];\n  let [a, b = a + count] = pair;");
+        assert!(js.contains("([a, b = a + count.v])=>["));
+    }
+
+    #[test]
+    fn makes_every_binding_form_reactive() {
+        let source = "html:\n  <p>${selected} ${a} ${b} ${x} ${i}</p>\n\nscript:\n  let selected;\n  let { a, b = 2, ...rest } = obj;\n  let [x, y = a] = pair;\n  for (var i = 0; i < 2; i++) {}\n  const pick = (a) => {\n    selected = { a, b };\n    ({ a, b } = obj);\n    [x] = [a];\n    function local() { let b = 1; b++; }\n  };\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(contains_code(
+            &js,
+            "var i = $$lunasReactive(undefined); let selected = $$lunasReactive(undefined);"
+        ));
+        // `a` is only written through the parameter of `pick`
+        assert!(contains_code(
+            &js,
+            "let [a, b, rest] = (({ a, b = 2, ...rest })=>[a, $$lunasReactive(b), rest])(obj);"
+        ));
+        assert!(contains_code(
+            &js,
+            "let [x, y] = (([x, y = a])=>[$$lunasReactive(x), y])(pair);"
+        ));
+        assert!(js.contains("for(i.v = 0; i.v < 2; i.v++){}"));
+        // Parameters and local declarations hide the component's bindings
+        assert!(contains_code(&js, "selected.v = { a, b: b.v };"));
+        assert!(js.contains("({ a, b: b.v } = obj);"));
+        assert!(contains_code(&js, "[x.v] = [a];"));
+        assert!(contains_code(&js, "let b = 1; b++;"));
+    }
+
+    #[test]
+    fn keeps_unwritten_bindings_plain() {
+        let source = "html:\n  <div><h1>${title}</h1><p>${count} ${items.length}</p><button @click=\"count++\">+</button><button @click=\"add\">add</button></div>\n\nscript:\n  const title = \"Todo\";\n  let count = 0;\n  let unused = 1;\n  const items = [];\n  const add = () => items.push(count);\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const title = \"Todo\";"));
+        assert!(js.contains("let count = $$lunasReactive(0);"));
+        assert!(js.contains("let unused = 1;"));
+        assert!(js.contains("const items = $$lunasReactive([]);"));
+        assert!(js.contains("const add = ()=>items.touch(items.v.push(count.v));"));
+        assert!(js.contains("\"click\", add);"));
+        // Text depending only on constants is rendered once
+        assert!(contains_code(&js, "`<div><h1>`, `</h1><p id="));
+        assert!(contains_code(&js, "this.valUpdateMap & 3 && $$lunasReplaceText(`${$$lunasEscapeHtml(count.v)} ${$$lunasEscapeHtml(items.v.length)}`"));
+        assert!(!js.contains("title.v"));
+    }
+
+    #[test]
+    fn reports_changes_inside_objects() {
+        let source = "html:\n  <div><p>${items.length} ${user.name}</p><button @click=\"items.push(1)\">+</button></div>\n\nscript:\n  const items = [];\n  const user = { name: \"a\" };\n  const rename = (name) => {\n    user.name = name;\n    items[0] = name;\n  };\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        // Mutations through a binding mark it as changed, although it is never reassigned
+        assert!(js.contains("user.touch(user.v.name = name);"));
+        assert!(js.contains("items.touch(items.v[0] = name);"));
+        assert!(js.contains("\"click\", ()=>items.touch(items.v.push(1)));"));
+        assert!(js.contains("this.valUpdateMap & 3 && $$lunasReplaceText("));
+    }
+
+    #[test]
+    fn tracks_dependencies_of_computed_values() {
+        let source = "html:\n  <div><p>${label}</p><b :if=\"expensive\">!</b><button @click=\"count++\">+</button></div>\n\nscript:\n  let count = 1;\n  const price = 3;\n  const total = Lunas.computed(() => price * count);\n  const label = Lunas.computed(() => `total: ${total}`);\n  const expensive = Lunas.computed(() => total > 10);\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.contains("$$lunasReactive, $$lunasComputed, "));
+        // Computed values depend on the variables they read, and are bindings of their own
+        assert!(js.contains("const total = $$lunasComputed(()=>price * count.v, 1);"));
+        assert!(js.contains("const label = $$lunasComputed(()=>`total: ${total.v}`, 2);"));
+        assert!(js.contains("const expensive = $$lunasComputed(()=>total.v > 10, 2);"));
+        assert!(js.contains("this.valUpdateMap & 8 && (expensive.v ? $$lunasRenderIfBlock("));
+        assert!(js.contains(
+            "this.valUpdateMap & 4 && $$lunasReplaceText(`${$$lunasEscapeHtml(label.v)}`"
+        ));
+
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(js.contains("$$lunasComputed }"));
+        assert!(js.contains("const total = $$lunasComputed(()=>price * count);"));
+    }
+
+    #[test]
+    fn registers_effects_with_their_dependencies() {
+        let source = "html:\n  <div><p>${count}</p><button @click=\"count++\">+</button></div>\n\nscript:\n  let count = 0;\n  let doubled = 0;\n  let label = \"\";\n  Lunas.effect(() => {\n    doubled = count * 2;\n    return () => console.log(label);\n  });\n  const stop = Lunas.watch(() => count > 1, (next) => { label = `${next}`; });\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.contains("$$lunasReactive, $$lunasEffect, $$lunasWatch } = "));
+        // The effect writes `doubled`, so only `count` and `label` run it again
+        assert!(contains_code(
+            &js,
+            "return ()=>console.log(label.v); }, 5);"
+        ));
+        assert!(contains_code(
+            &js,
+            "const stop = $$lunasWatch(()=>count.v > 1, (next)=>{ label.v = `${next}`; }, 1);"
+        ));
+    }
+
+    #[test]
+    fn hoists_module_script() {
+        let source = "html:\n  <div><p>${format(count)} ${label}</p></div>\n\nscript module:\n  import { format } from \"./fmt.js\";\n  let created = 0;\n  export const label = Lunas.env.LABEL;\n  export function instances() {\n    return created;\n  }\n\nscript:\n  let count = 0;\n  created++;\n  const increment = () => count++;\n";
+        let options = CompileOptions {
+            env: HashMap::from([("LABEL".to_string(), "items".to_string())]),
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        // The module script runs once, before the component function, and keeps its exports
+        assert!(contains_code(&js, "import { format } from \"./fmt.js\"; let created = 0; export const label = \"items\"; export function instances() { return created; } const $$lunasTemplate0"));
+        // Its bindings are shared by the instances and read without `.v`
+        assert!(contains_code(
+            &js,
+            "let count = $$lunasReactive(0); created++;"
+        ));
+        assert!(contains_code(&js, "format(count.v), label"));
+        let blocks = parse_lunas_file(source).unwrap();
+        assert!(contains_code(
+            &lunas_generate_types_from_block(&blocks, &options).unwrap(),
+            "export declare const label: any; export declare function instances(...args: any[]): any; export default component;"
+        ));
+        let emit = "html:\n  <div></div>\n\nscript module:\n  Lunas.emit(\"change\");\n";
+        assert_eq!(
+            compile(emit, &options).unwrap_err(),
+            "`Lunas.emit` cannot be used in `script module:`, since it belongs to a component instance"
+        );
+
+        assert_eq!(
+            compile(source, &CompileOptions::default()).unwrap_err(),
+            "`Lunas.env.LABEL` is not set in `env` (line 3 of `script module:`)"
+        );
+
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..options
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(contains_code(&js, "export const label = \"items\"; export function instances() { return created; } export default function(args = {}) { let count = 0; created++;"));
+
+        let options = CompileOptions {
+            format: ModuleFormat::CommonJs,
+            ..Default::default()
+        };
+        assert!(compile(source, &options)
+            .unwrap_err()
+            .starts_with("`export` in `script module:` can only be used"));
+    }
+
+    #[test]
+    fn counts_collection_methods_on_bindings_only() {
+        let source = "html:\n  <div>${el.id} ${params} ${tags.size}</div>\n\nscript:\n  const el = document.createElement(\"div\");\n  const params = new URLSearchParams();\n  const tags = new Set();\n  const update = () => {\n    el.classList.add(\"x\");\n    params.set(\"a\", \"b\");\n    tags.add(\"a\");\n  };\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const el = document.createElement(\"div\");"));
+        assert!(js.contains("el.classList.add(\"x\");"));
+        assert!(js.contains("const tags = $$lunasReactive(new Set());"));
+        assert!(js.contains("tags.touch(tags.v.add(\"a\"));"));
+        // `params.set` is called on the binding itself, `el.classList.add` on one of its properties
+        assert!(js.contains("params.touch(params.v.set(\"a\", \"b\"));"));
+        assert!(!js.contains("el.v"));
+    }
+}
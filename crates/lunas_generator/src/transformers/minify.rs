@@ -0,0 +1,155 @@
+use lunas_html_parser::Node;
+use lunas_parser::DetailedBlock;
+
+use super::codegen::find_closing_brace;
+
+/// Elements whose text is rendered as written.
+const WHITESPACE_SENSITIVE_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+/// A copy of `block` with the whitespace of its markup collapsed, its HTML comments dropped and its CSS minified.
+/// Every target is generated from the same copy, so hydration locations still line up.
+pub fn minify_block(block: &DetailedBlock) -> DetailedBlock {
+    let mut block = block.clone();
+    minify_nodes(&mut block.detailed_language_blocks.dom.children);
+    if let Some(css) = &block.detailed_language_blocks.css {
+        block.detailed_language_blocks.css = Some(minify_css(css));
+    }
+    block
+}
+
+fn minify_nodes(nodes: &mut Vec<Node>) {
+    nodes.retain(|node| !matches!(node, Node::Comment(_)));
+    // Text around a dropped comment becomes a single text node, as the browser would parse it
+    let mut merged: Vec<Node> = vec![];
+    for node in nodes.drain(..) {
+        match (merged.last_mut(), node) {
+            (Some(Node::Text(previous)), Node::Text(text)) => previous.push_str(&text),
+            (_, node) => merged.push(node),
+        }
+    }
+    *nodes = merged;
+
+    for node in nodes.iter_mut() {
+        match node {
+            Node::Text(text) => *text = collapse_whitespace(text),
+            Node::Element(element) => {
+                if !WHITESPACE_SENSITIVE_ELEMENTS.contains(&element.name.as_str()) {
+                    minify_nodes(&mut element.children);
+                }
+            }
+            Node::Comment(_) => {}
+        }
+    }
+}
+
+// Give: "\n    Count: ${ count  +  1 }\n  "
+// Want: " Count: ${ count  +  1 } "
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::new();
+    let mut last_end = 0;
+    while let Some(start) = text[last_end..].find("${") {
+        let start = start + last_end;
+        let end = match find_closing_brace(&text[start + 2..]) {
+            Some(end) => end + start + 2,
+            None => break,
+        };
+        collapsed.push_str(&collapse_static_whitespace(&text[last_end..start]));
+        collapsed.push_str(&text[start..end + 1]);
+        last_end = end + 1;
+    }
+    collapsed.push_str(&collapse_static_whitespace(&text[last_end..]));
+    collapsed
+}
+
+fn collapse_static_whitespace(text: &str) -> String {
+    let mut collapsed = String::new();
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        match c.is_ascii_whitespace() {
+            true if in_whitespace => {}
+            true => collapsed.push(' '),
+            false => collapsed.push(c),
+        }
+        in_whitespace = c.is_ascii_whitespace();
+    }
+    collapsed
+}
+
+/// Removes comments and the whitespace that does not separate tokens.
+/// Strings are kept as written.
+pub fn minify_css(css: &str) -> String {
+    let mut tokens = String::new();
+    let mut chars = css.chars().peekable();
+    let mut pending_space = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                pending_space = true;
+            }
+            c if c.is_whitespace() => pending_space = true,
+            '"' | '\'' => {
+                push_css_token(&mut tokens, c, &mut pending_space);
+                let mut escaped = false;
+                for s in chars.by_ref() {
+                    tokens.push(s);
+                    match s {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        s if s == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            '}' if tokens.ends_with(';') => {
+                tokens.pop();
+                tokens.push('}');
+                pending_space = false;
+            }
+            c => push_css_token(&mut tokens, c, &mut pending_space),
+        }
+    }
+    tokens
+}
+
+// `+`, `-` and `~` keep their spaces, which are significant in `calc()` and selectors
+fn push_css_token(tokens: &mut String, c: char, pending_space: &mut bool) {
+    let separates = |c: char| matches!(c, '{' | '}' | ';' | ':' | ',' | '>');
+    let after_separator = tokens.chars().last().is_none_or(separates);
+    // `a :hover` selects differently from `a:hover`
+    if *pending_space && !after_separator && (!separates(c) || c == ':') {
+        tokens.push(' ');
+    }
+    *pending_space = false;
+    tokens.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collapse_whitespace, minify_css};
+
+    #[test]
+    fn collapses_static_whitespace_only() {
+        assert_eq!(
+            collapse_whitespace("\n    Count: ${ count  +  \"a  b\" }\n  "),
+            " Count: ${ count  +  \"a  b\" } "
+        );
+    }
+
+    #[test]
+    fn minifies_css() {
+        assert_eq!(
+            minify_css(
+                "/* header */\nh1 > a:hover,\np  .title {\n  color: red;\n  width: calc(100% - 2px);\n  content: \"a  b\";\n}\n"
+            ),
+            "h1>a:hover,p .title{color:red;width:calc(100% - 2px);content:\"a  b\"}"
+        );
+    }
+}
@@ -1,7 +1,13 @@
+pub mod codegen;
+pub mod exports;
 pub mod html_utils;
+pub mod hydration;
 pub mod imports;
 pub mod js_utils;
 pub mod utils;
 pub mod utils_swc;
 pub mod router;
 pub mod inputs;
+pub mod intrinsics;
+pub mod minify;
+pub mod module_format;
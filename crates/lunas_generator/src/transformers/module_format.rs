@@ -0,0 +1,523 @@
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::{
+    consts::ROUTER_RUNTIME_PATH,
+    structs::compile_options::{CompileOptions, ModuleFormat},
+};
+
+use super::{
+    codegen::{print_module, string_literal},
+    imports::generate_import_string,
+    utils_swc::parse_with_swc,
+};
+
+/// Global that `Iife` and `Umd` builds read the runtime from.
+const RUNTIME_GLOBAL: &str = "Lunas";
+
+/// Wraps the expression exported by a component into a module of `options.format`.
+///
+/// `runtime_helpers` are imported from `runtime_path`, or copied from `options.inlined_runtime`
+/// when it is set. `imports` are `import` declarations, which become `require` calls or
+/// parameters of the wrapping function in the formats other than `Esm`.
+/// `declarations` are placed at the top level of the module, before the component.
+/// The module is printed by swc, which fails when the generated code is not valid JavaScript.
+pub fn generate_module(
+    runtime_helpers: &[&str],
+    runtime_path: &str,
+    imports: &[String],
+    declarations: &[String],
+    exported: &str,
+    options: &CompileOptions,
+) -> Result<String, String> {
+    let code = assemble_module(
+        runtime_helpers,
+        runtime_path,
+        imports,
+        declarations,
+        exported,
+        options,
+    )?;
+    print_module(&code, options.minify)
+}
+
+fn assemble_module(
+    runtime_helpers: &[&str],
+    runtime_path: &str,
+    imports: &[String],
+    declarations: &[String],
+    exported: &str,
+    options: &CompileOptions,
+) -> Result<String, String> {
+    let inlined_runtime = match &options.inlined_runtime {
+        Some(source) => Some(inline_runtime_helpers(source, runtime_helpers)?),
+        None => None,
+    };
+
+    let imports_runtime = inlined_runtime.is_none() && !runtime_helpers.is_empty();
+    let module_code =
+        join_sections(&[inlined_runtime.unwrap_or_default(), declarations.join("\n")]);
+
+    if options.format == ModuleFormat::Esm {
+        let mut import_lines = match imports_runtime {
+            false => String::new(),
+            true => format!(
+                "import {{ {} }} from {};",
+                runtime_helpers.join(", "),
+                string_literal(runtime_path)
+            ),
+        };
+        import_lines.push_str(&generate_import_string(&imports.to_vec()));
+        let sections = [
+            import_lines.trim_start().to_string(),
+            module_code,
+            format!("export default {}", exported),
+        ];
+        return Ok(join_sections(&sections));
+    }
+
+    let mut dependencies = vec![];
+    if imports_runtime {
+        dependencies.push(Dependency {
+            source: runtime_path.to_string(),
+            bindings: runtime_helpers
+                .iter()
+                .map(|helper| Binding::Named(helper.to_string(), helper.to_string()))
+                .collect(),
+        });
+    }
+    for import in imports {
+        dependencies.push(Dependency::from_import(import)?);
+    }
+    let exported = exported.trim_end().trim_end_matches(';');
+
+    match options.format {
+        ModuleFormat::Esm => unreachable!(),
+        ModuleFormat::CommonJs => {
+            let requires = dependencies
+                .iter()
+                .enumerate()
+                .map(|(index, dependency)| dependency.gen_require(index))
+                .collect::<Vec<String>>()
+                .join("\n");
+            let sections = [
+                "\"use strict\";".to_string(),
+                requires,
+                module_code,
+                format!("module.exports = {};", exported),
+            ];
+            Ok(join_sections(&sections))
+        }
+        ModuleFormat::Iife => {
+            let globals = dependencies
+                .iter()
+                .map(|dependency| dependency.global_name(runtime_path, options))
+                .collect::<Result<Vec<String>, String>>()?;
+            let assignment = match &options.global_name {
+                Some(name) => format!("var {} = ", name),
+                None => String::new(),
+            };
+            Ok(format!(
+                "{}(function ({}) {{\n{}\n}})({});",
+                assignment,
+                gen_dependency_params(&dependencies),
+                gen_factory_body(&dependencies, module_code, exported),
+                globals.join(", ")
+            ))
+        }
+        ModuleFormat::Umd => {
+            let sources = dependencies
+                .iter()
+                .map(|dependency| string_literal(&dependency.source))
+                .collect::<Vec<String>>();
+            let requires = sources
+                .iter()
+                .map(|source| format!("require({})", source))
+                .collect::<Vec<String>>();
+            let globals = dependencies
+                .iter()
+                .map(|dependency| dependency.global_name(runtime_path, options))
+                .collect::<Result<Vec<String>, String>>()?;
+            let global_assignment = match &options.global_name {
+                Some(name) => format!("root.{} = ", name),
+                None => String::new(),
+            };
+            Ok(format!(
+                r#"(function (root, factory) {{
+    if (typeof define === "function" && define.amd) define([{}], factory);
+    else if (typeof module === "object" && module.exports) module.exports = factory({});
+    else {}factory({});
+}})(typeof self !== "undefined" ? self : this, function ({}) {{
+{}
+}});"#,
+                sources.join(", "),
+                requires.join(", "),
+                global_assignment,
+                globals.join(", "),
+                gen_dependency_params(&dependencies),
+                gen_factory_body(&dependencies, module_code, exported),
+            ))
+        }
+    }
+}
+
+/// Returns the `helpers` that `code` refers to, in the order of `helpers`.
+/// Helpers are only named by the generated code, so finding the identifier is enough.
+pub fn find_used_helpers<'a>(code: &str, helpers: &[&'a str]) -> Vec<&'a str> {
+    let is_identifier_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    helpers
+        .iter()
+        .filter(|helper| {
+            code.match_indices(*helper).any(|(start, _)| {
+                let before = code[..start].chars().next_back();
+                let after = code[start + helper.len()..].chars().next();
+                !before.is_some_and(is_identifier_char) && !after.is_some_and(is_identifier_char)
+            })
+        })
+        .copied()
+        .collect()
+}
+
+fn join_sections(sections: &[String]) -> String {
+    sections
+        .iter()
+        .filter(|section| !section.is_empty())
+        .cloned()
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn gen_dependency_params(dependencies: &[Dependency]) -> String {
+    (0..dependencies.len())
+        .map(dependency_param)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn dependency_param(index: usize) -> String {
+    format!("$$lunasDep{}", index)
+}
+
+fn gen_factory_body(dependencies: &[Dependency], module_code: String, exported: &str) -> String {
+    let bindings = dependencies
+        .iter()
+        .enumerate()
+        .flat_map(|(index, dependency)| dependency.gen_bindings(&dependency_param(index)))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let sections = [
+        "\"use strict\";".to_string(),
+        bindings,
+        module_code,
+        format!("return {};", exported),
+    ];
+    join_sections(&sections)
+}
+
+enum Binding {
+    Default(String),
+    /// Imported name and local name
+    Named(String, String),
+    Namespace(String),
+}
+
+/// A module that the compiled component imports from.
+struct Dependency {
+    source: String,
+    bindings: Vec<Binding>,
+}
+
+impl Dependency {
+    fn from_import(import: &str) -> Result<Self, String> {
+        let parsed = parse_with_swc(&import.to_string());
+        let parsed_json = serde_json::to_value(&parsed).unwrap();
+        let declaration = &parsed_json["body"][0];
+        let source = match (
+            declaration["type"].as_str(),
+            declaration["source"]["value"].as_str(),
+        ) {
+            (Some("ImportDeclaration"), Some(source)) => source.to_string(),
+            _ => return Err(format!("invalid import declaration: {}", import)),
+        };
+        let mut bindings = vec![];
+        if let Some(Value::Array(specifiers)) = declaration.get("specifiers") {
+            for specifier in specifiers {
+                let local = specifier["local"]["value"].as_str().unwrap_or_default();
+                bindings.push(match specifier["type"].as_str() {
+                    Some("ImportDefaultSpecifier") => Binding::Default(local.to_string()),
+                    Some("ImportNamespaceSpecifier") => Binding::Namespace(local.to_string()),
+                    _ => match specifier["imported"]["value"].as_str() {
+                        Some(imported) => Binding::Named(imported.to_string(), local.to_string()),
+                        None => Binding::Named(local.to_string(), local.to_string()),
+                    },
+                });
+            }
+        }
+        Ok(Dependency { source, bindings })
+    }
+
+    // Give: import Child, { a, b as c } from "./child";
+    // Want: const $$lunasDep1 = require("./child");
+    //       const Child = $$lunasDep1 && $$lunasDep1.__esModule ? $$lunasDep1.default : $$lunasDep1;
+    //       const { a, b: c } = $$lunasDep1;
+    fn gen_require(&self, index: usize) -> String {
+        let require = format!("require({})", string_literal(&self.source));
+        let only_named = self
+            .bindings
+            .iter()
+            .all(|binding| matches!(binding, Binding::Named(_, _)));
+        match (self.bindings.is_empty(), only_named) {
+            (true, _) => format!("{};", require),
+            (false, true) => self.gen_bindings(&require).join("\n"),
+            (false, false) => {
+                let param = dependency_param(index);
+                let mut lines = vec![format!("const {} = {};", param, require)];
+                lines.extend(self.gen_bindings(&param));
+                lines.join("\n")
+            }
+        }
+    }
+
+    fn gen_bindings(&self, module: &str) -> Vec<String> {
+        let mut lines = vec![];
+        let mut named = vec![];
+        for binding in &self.bindings {
+            match binding {
+                // Modules that are not transpiled from ES modules export their default value directly
+                Binding::Default(local) => lines.push(format!(
+                    "const {} = {} && {}.__esModule ? {}.default : {};",
+                    local, module, module, module, module
+                )),
+                Binding::Namespace(local) => lines.push(format!("const {} = {};", local, module)),
+                Binding::Named(imported, local) if imported == local => named.push(local.clone()),
+                Binding::Named(imported, local) => named.push(format!("{}: {}", imported, local)),
+            }
+        }
+        if !named.is_empty() {
+            lines.push(format!("const {{ {} }} = {};", named.join(", "), module));
+        }
+        lines
+    }
+
+    fn global_name(&self, runtime_path: &str, options: &CompileOptions) -> Result<String, String> {
+        if self.source == runtime_path || self.source == ROUTER_RUNTIME_PATH {
+            return Ok(RUNTIME_GLOBAL.to_string());
+        }
+        match &options.globals {
+            Some(globals) => globals(&self.source),
+            None => Err(format!(
+                "`{}` has no global name; set `globals` to import it in an IIFE or UMD build",
+                self.source
+            )),
+        }
+    }
+}
+
+/// Copies the declarations of `helpers` and everything they depend on out of the runtime source.
+fn inline_runtime_helpers(source: &str, helpers: &[&str]) -> Result<String, String> {
+    let parsed = parse_with_swc(&source.to_string());
+    let parsed_json = serde_json::to_value(&parsed).unwrap();
+    let mut declarations = vec![];
+    if let Some(Value::Array(body)) = parsed_json.get("body") {
+        for item in body {
+            // `export function f() {}` is copied as `function f() {}`
+            let declaration = match item["type"].as_str() {
+                Some("ExportDeclaration") => &item["declaration"],
+                _ => item,
+            };
+            let names = declared_names(declaration);
+            if names.is_empty() {
+                continue;
+            }
+            let (start, end) = match (
+                declaration["span"]["start"].as_u64(),
+                declaration["span"]["end"].as_u64(),
+            ) {
+                (Some(start), Some(end)) => (start as usize - 1, end as usize - 1),
+                _ => continue,
+            };
+            let mut references = HashSet::new();
+            collect_identifiers(declaration, &mut references);
+            declarations.push((names, &source[start..end], references));
+        }
+    }
+
+    let mut needed = vec![false; declarations.len()];
+    let mut queue = helpers
+        .iter()
+        .map(|helper| helper.to_string())
+        .collect::<Vec<String>>();
+    let mut seen = HashSet::new();
+    while let Some(name) = queue.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let found = declarations
+            .iter()
+            .position(|(names, _, _)| names.contains(&name));
+        match found {
+            Some(index) => {
+                needed[index] = true;
+                queue.extend(declarations[index].2.iter().cloned());
+            }
+            None if helpers.contains(&name.as_str()) => {
+                return Err(format!(
+                    "runtime helper `{}` is not found in the inlined runtime",
+                    name
+                ))
+            }
+            // Globals and local variables
+            None => {}
+        }
+    }
+
+    Ok(declarations
+        .iter()
+        .zip(needed)
+        .filter(|(_, needed)| *needed)
+        .map(|((_, code, _), _)| code.to_string())
+        .collect::<Vec<String>>()
+        .join("\n\n"))
+}
+
+fn declared_names(declaration: &Value) -> Vec<String> {
+    match declaration["type"].as_str() {
+        Some("FunctionDeclaration") | Some("ClassDeclaration") => declaration["identifier"]
+            ["value"]
+            .as_str()
+            .map(|name| vec![name.to_string()])
+            .unwrap_or_default(),
+        Some("VariableDeclaration") => match &declaration["declarations"] {
+            Value::Array(declarators) => declarators
+                .iter()
+                .filter_map(|declarator| declarator["id"]["value"].as_str())
+                .map(|name| name.to_string())
+                .collect(),
+            _ => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+// Property names are collected as well, which may copy a few unused declarations but never misses one
+fn collect_identifiers(json: &Value, identifiers: &mut HashSet<String>) {
+    match json {
+        Value::Object(obj) => {
+            if obj.get("type") == Some(&Value::String("Identifier".to_string())) {
+                if let Some(Value::String(name)) = obj.get("value") {
+                    identifiers.insert(name.clone());
+                }
+            }
+            for value in obj.values() {
+                collect_identifiers(value, identifiers);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_identifiers(value, identifiers);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_used_helpers, inline_runtime_helpers};
+    use crate::{
+        test_utils::{compile, contains_code},
+        CompileOptions, CompileTarget, ModuleFormat,
+    };
+
+    const APP: &str =
+        "@use Child from './child.lunas'\n\nhtml:\n  <div><Child title=\"a\" /></div>\n";
+
+    #[test]
+    fn finds_helpers_by_identifier() {
+        let code = "$$lunasReplaceText(a, b); $$lunasInsertContentX(); $$lunasGetElmRefs";
+        assert_eq!(
+            find_used_helpers(
+                code,
+                &[
+                    "$$lunasGetElmRefs",
+                    "$$lunasInsertContent",
+                    "$$lunasReplaceText"
+                ]
+            ),
+            vec!["$$lunasGetElmRefs", "$$lunasReplaceText"]
+        );
+    }
+
+    #[test]
+    fn inlines_helpers_with_their_dependencies() {
+        let runtime = r#"const warn = (message) => console.warn(message);
+const unused = 1;
+export function $$lunasA(x) { return helper(x); }
+function helper(x) { warn(x); return x; }
+export const $$lunasB = function () {};"#;
+        assert_eq!(
+            inline_runtime_helpers(runtime, &["$$lunasA"]).unwrap(),
+            "const warn = (message) => console.warn(message);\n\nfunction $$lunasA(x) { return helper(x); }\n\nfunction helper(x) { warn(x); return x; }"
+        );
+        assert!(inline_runtime_helpers(runtime, &["$$lunasC"]).is_err());
+    }
+
+    #[test]
+    fn imports_only_used_helpers() {
+        let source = "html:\n  <div>static</div>\n";
+        let js = compile(source, &CompileOptions::default()).unwrap();
+        assert!(js.starts_with("import { $$lunasGetElmRefs, $$lunasInitComponent, $$lunasCreateTemplate, $$lunasUseTemplate } from \"lunas/dist/runtime\";\n"));
+        assert!(contains_code(&js, "const { $$lunasSetComponentElement, $$lunasUpdateComponent, $$lunasComponentReturn, $$lunasAfterMount } = new $$lunasInitComponent(args);"));
+
+        let options = CompileOptions {
+            target: CompileTarget::Server,
+            ..Default::default()
+        };
+        let js = compile(source, &options).unwrap();
+        assert!(!js.contains("import"));
+        assert!(contains_code(
+            &js,
+            "export default function(args = {}) { return `<div>static</div>`; }"
+        ));
+    }
+
+    #[test]
+    fn requires_dependencies_in_common_js() {
+        let options = CompileOptions {
+            format: ModuleFormat::CommonJs,
+            ..Default::default()
+        };
+        let js = compile(APP, &options).unwrap();
+        assert!(js.starts_with("\"use strict\";\n"));
+        assert!(contains_code(&js, "} = require(\"lunas/dist/runtime\");"));
+        // Default imports also accept modules compiled from ES modules
+        assert!(contains_code(
+            &js,
+            "const $$lunasDep1 = require(\"./child.lunas\"); const Child = $$lunasDep1 && $$lunasDep1.__esModule ? $$lunasDep1.default : $$lunasDep1;"
+        ));
+        assert!(contains_code(&js, "module.exports = function(args = {}) {"));
+        assert!(contains_code(&js, "return $$lunasComponentReturn; };"));
+    }
+
+    #[test]
+    fn reads_dependencies_from_globals_in_iife() {
+        let options = CompileOptions {
+            format: ModuleFormat::Iife,
+            ..Default::default()
+        };
+        let js = compile("html:\n  <div>${title}</div>\n", &options).unwrap();
+        assert!(contains_code(
+            &js,
+            "(function($$lunasDep0) { \"use strict\"; const {"
+        ));
+        assert!(contains_code(&js, "} = $$lunasDep0;"));
+        assert!(js.trim_end().ends_with("})(Lunas);"));
+
+        // Only the runtime has a known global
+        assert_eq!(
+            compile(APP, &options).unwrap_err(),
+            "`./child.lunas` has no global name; set `globals` to import it in an IIFE or UMD build"
+        );
+    }
+}
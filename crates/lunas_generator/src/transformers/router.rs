@@ -1,17 +1,133 @@
+use lunas_html_parser::{Dom, Node};
+use lunas_parser::{RouteDeclaration, RouteTarget};
+
 use crate::{consts::ROUTER_VIEW, structs::transform_info::CustomComponentBlockInfo};
 
+use super::codegen::string_literal;
+
+/// Generates the route table of a component using `@useRouting` from its `@route` declarations.
+/// Routes with a `parent` are nested in the `children` of the route with that name.
+pub fn generate_route_table(
+    routes: &[&RouteDeclaration],
+    component_names: &[String],
+) -> Result<String, String> {
+    let mut names: Vec<&String> = vec![];
+    for route in routes {
+        if let Some(parent) = &route.parent {
+            if !names.contains(&parent) {
+                return Err(format!(
+                    "parent route `{}` must be declared before its children",
+                    parent
+                ));
+            }
+        }
+        if let Some(name) = &route.name {
+            if names.contains(&name) {
+                return Err(format!("route name `{}` is already used", name));
+            }
+            names.push(name);
+        }
+        if let RouteTarget::Component(component) = &route.target {
+            if !component_names.contains(component) {
+                return Err(format!(
+                    "route component `{}` is not imported with @use",
+                    component
+                ));
+            }
+        }
+    }
+    Ok(format!(
+        "const $$lunasGeneratedRoutes = [\n{}\n];",
+        generate_route_entries(routes, None, 1)
+    ))
+}
+
+fn generate_route_entries(
+    routes: &[&RouteDeclaration],
+    parent: Option<&String>,
+    depth: usize,
+) -> String {
+    let indent = "  ".repeat(depth);
+    routes
+        .iter()
+        .filter(|route| route.parent.as_ref() == parent)
+        .map(|route| {
+            let mut fields = vec![format!("path: {}", string_literal(&route.path))];
+            if let Some(name) = &route.name {
+                fields.push(format!("name: {}", string_literal(name)));
+            }
+            match &route.target {
+                RouteTarget::Component(component) => fields.push(format!(
+                    "component: async () => ({{ default: {} }})",
+                    component
+                )),
+                RouteTarget::Redirect(to) => {
+                    fields.push(format!("redirect: {}", string_literal(to)))
+                }
+            }
+            let children = match &route.name {
+                Some(name) => generate_route_entries(routes, Some(name), depth + 1),
+                None => String::new(),
+            };
+            match children.is_empty() {
+                true => format!("{}{{ {} }},", indent, fields.join(", ")),
+                false => format!(
+                    "{}{{ {}, children: [\n{}\n{}] }},",
+                    indent,
+                    fields.join(", "),
+                    children,
+                    indent
+                ),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether the template contains a `RouterView`, i.e. the component is a layout
+/// that renders nested routes when it is not the component that owns the routes.
+pub fn has_router_view(dom: &Dom) -> bool {
+    fn visit(node: &Node) -> bool {
+        match node {
+            Node::Element(elm) => elm.name == ROUTER_VIEW || elm.children.iter().any(visit),
+            _ => false,
+        }
+    }
+    dom.children.iter().any(visit)
+}
+
+/// Registers the `RouterView` of a layout component, which renders the next level of the matched routes.
+pub fn generate_router_view_registration_code(
+    custom_component_blocks_info: &[CustomComponentBlockInfo],
+) -> Result<String, String> {
+    Ok(format!(
+        "$$lunasRouter.registerView({});",
+        generate_router_view_target(custom_component_blocks_info)?
+    ))
+}
+
 pub fn generate_router_initialization_code(
-    custom_component_blocks_info: Vec<CustomComponentBlockInfo>,
+    custom_component_blocks_info: &[CustomComponentBlockInfo],
+) -> Result<String, String> {
+    Ok(format!(
+        "$$lunasRouter.initialize($$lunasGeneratedRoutes, {});",
+        generate_router_view_target(custom_component_blocks_info)?
+    ))
+}
+
+/// Arguments telling the router where the `RouterView` renders: parent, anchor and whether it has siblings.
+fn generate_router_view_target(
+    custom_component_blocks_info: &[CustomComponentBlockInfo],
 ) -> Result<String, String> {
     match custom_component_blocks_info
-        .into_iter()
+        .iter()
         .find(|cc| cc.component_name == ROUTER_VIEW)
     {
         Some(router_component) => Ok(if router_component.have_sibling_elm {
             match router_component.distance_to_next_elm > 1 {
                 true => {
                     format!(
-                        "$$lunasRouter.initialize($$lunasGeneratedRoutes, $$lunas{}Ref, $$lunas{}Anchor, true);",
+                        "$$lunas{}Ref, $$lunas{}Anchor, true",
                         router_component.parent_id, router_component.custom_component_block_id
                     )
                 }
@@ -21,17 +137,42 @@ pub fn generate_router_initialization_code(
                         None => "null".to_string(),
                     };
                     format!(
-                        "$$lunasRouter.initialize($$lunasGeneratedRoutes, $$lunas{}Ref, {}, true);",
+                        "$$lunas{}Ref, {}, true",
                         router_component.parent_id, anchor_ref_name
                     )
                 }
             }
         } else {
-            format!(
-                "$$lunasRouter.initialize($$lunasGeneratedRoutes, $$lunas{}Ref, null, false);",
-                router_component.parent_id,
-            )
+            format!("$$lunas{}Ref, null, false", router_component.parent_id)
         }),
         None => Err("RouterView component not found".to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{compile, contains_code},
+        CompileOptions,
+    };
+
+    const APP: &str = "@useRouting\n@use Home from './pages/Home.lunas'\n@use UserPage from './pages/UserPage.lunas'\n@route '/' Home\n@route(name: user) '/users/:id' UserPage\n@route '/old' redirect '/'\n\nhtml:\n  <div><RouterView /></div>\n";
+
+    #[test]
+    fn generates_route_table_from_declarations() {
+        let js = compile(APP, &CompileOptions::default()).unwrap();
+        // Route components are loaded lazily, and redirects have no component
+        assert!(contains_code(
+            &js,
+            "{ path: \"/users/:id\", name: \"user\", component: async ()=>({ default: UserPage }) },"
+        ));
+        assert!(contains_code(&js, "{ path: \"/old\", redirect: \"/\" }"));
+        assert!(js.contains("$$lunasRouter.initialize($$lunasGeneratedRoutes, "));
+
+        let missing = APP.replace("@use UserPage from './pages/UserPage.lunas'\n", "");
+        assert_eq!(
+            compile(&missing, &CompileOptions::default()).unwrap_err(),
+            "route component `UserPage` is not imported with @use"
+        );
+    }
+}
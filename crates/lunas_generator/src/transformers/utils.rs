@@ -9,18 +9,11 @@ pub fn add_or_remove_strings_to_script(
     script: &String,
 ) -> String {
     let mut transformers = position_and_strs.clone();
-    transformers.sort_by(|a, b| {
-        let a = match a {
-            TransformInfo::AddStringToPosition(a) => a.position,
-            TransformInfo::RemoveStatement(a) => a.start_position,
-            TransformInfo::ReplaceText(a) => a.start_position,
-        };
-        let b = match b {
-            TransformInfo::AddStringToPosition(b) => b.position,
-            TransformInfo::RemoveStatement(b) => b.start_position,
-            TransformInfo::ReplaceText(b) => b.start_position,
-        };
-        a.cmp(&b)
+    // Strings added where a removal starts are kept, so they go first
+    transformers.sort_by_key(|transform| match transform {
+        TransformInfo::AddStringToPosition(add) => (add.position, 0),
+        TransformInfo::RemoveStatement(remove) => (remove.start_position, 1),
+        TransformInfo::ReplaceText(replace) => (replace.start_position, 1),
     });
     let mut result = String::new();
     let mut last_position = 0;
@@ -48,7 +41,7 @@ pub fn add_or_remove_strings_to_script(
 
 use rand::seq::SliceRandom;
 
-use super::{js_utils::search_json, utils_swc::parse_with_swc};
+use super::{intrinsics::resolve_intrinsics, js_utils::search_json, utils_swc::parse_with_swc};
 
 lazy_static! {
     pub static ref UUID_GENERATOR: Mutex<UuidGenerator> = Mutex::new(UuidGenerator::new());
@@ -99,17 +92,23 @@ fn is_testgen() -> bool {
     }
 }
 
-pub fn append_v_to_vars_in_html(input: &str, variables: &Vec<String>) -> (String, Vec<String>) {
+pub fn append_v_to_vars_in_html(
+    input: &str,
+    variables: &Vec<String>,
+) -> Result<(String, Vec<String>), String> {
     let parsed = parse_with_swc(&input.to_string());
 
     let parsed_json = serde_json::to_value(&parsed).unwrap();
 
-    let (positions, _, depending_vars) =
-        search_json(&parsed_json, &input.to_string(), &variables, None, None);
+    let (mut positions, _, mut depending_vars) =
+        search_json(&parsed_json, &input.to_string(), variables, None);
+    let (intrinsic_positions, intrinsic_vars) = resolve_intrinsics(&parsed_json, variables, None)?;
+    positions.extend(intrinsic_positions);
+    depending_vars.extend(intrinsic_vars);
 
     let modified_string = add_or_remove_strings_to_script(positions, &input.to_string());
 
-    (modified_string, depending_vars)
+    Ok((modified_string, depending_vars))
 }
 
 pub fn convert_non_reactive_to_obj(input: &str, variables: &Vec<String>) -> String {
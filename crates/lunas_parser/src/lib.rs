@@ -7,7 +7,9 @@ mod swc_parser;
 use parse2::parse2;
 use parser1::parse1;
 pub use structs::detailed_blocks::DetailedBlock;
-pub use structs::detailed_meta_data::{DetailedMetaData, PropsInput, UseComponentStatement};
+pub use structs::detailed_meta_data::{
+    DetailedMetaData, PropsInput, RouteDeclaration, RouteTarget, UseComponentStatement,
+};
 
 pub fn parse_lunas_file(input: &str) -> Result<DetailedBlock, String> {
     let parsed_items = match parse1(input) {
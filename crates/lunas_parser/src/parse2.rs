@@ -40,8 +40,8 @@ fn parse_language_blocks<'a>(blks: Vec<LanguageBlock>) -> Result<DetailedLanguag
     let mut hm = HashMap::new();
     for block in &blks {
         let language_name: &str = &block.language_name.as_str();
-        // if language_name is not one of 'html', 'style', 'script'
-        if language_name != "html" && language_name != "style" && language_name != "script" {
+        // if language_name is not one of 'html', 'style', 'script', 'script module'
+        if !["html", "style", "script", "script module"].contains(&language_name) {
             return Err("Invalid language name");
         }
         if hm.contains_key(language_name) {
@@ -60,19 +60,8 @@ fn parse_language_blocks<'a>(blks: Vec<LanguageBlock>) -> Result<DetailedLanguag
     match parsed_html_dom_result {
         Ok(parsed_html) => {
             let css = hm.get("style");
-            let js = hm.get("script");
-            let parsed_js = match js {
-                Some(js) => {
-                    // TODO: TS to JS
-                    let parsed = parse_with_swc(js.into());
-                    let parsed_json = serde_json::to_value(&parsed).unwrap();
-                    Some(JsBlock {
-                        ast: parsed_json,
-                        raw: js.trim().into(),
-                    })
-                }
-                None => None,
-            };
+            let parsed_js = hm.get("script").map(|js| parse_js_block(js));
+            let parsed_module_js = hm.get("script module").map(|js| parse_js_block(js));
             let str_css = match css {
                 Some(css) => Some(css.to_string()),
                 None => None,
@@ -81,8 +70,19 @@ fn parse_language_blocks<'a>(blks: Vec<LanguageBlock>) -> Result<DetailedLanguag
                 dom: parsed_html,
                 css: str_css,
                 js: parsed_js,
+                module_js: parsed_module_js,
             })
         }
         Err(_) => return Err("Invalid html block"),
     }
 }
+
+fn parse_js_block(js: &str) -> JsBlock {
+    // TODO: TS to JS
+    let parsed = parse_with_swc(&js.to_string());
+    let parsed_json = serde_json::to_value(&parsed).unwrap();
+    JsBlock {
+        ast: parsed_json,
+        raw: js.trim().into(),
+    }
+}
@@ -3,8 +3,9 @@ use crate::structs::blocks::{LanguageBlock, ParsedItem};
 extern crate nom;
 
 use nom::bytes::complete::take_while;
-use nom::character::complete::line_ending;
+use nom::character::complete::{alphanumeric1, line_ending, space1};
 use nom::combinator::{not, opt, peek};
+use nom::sequence::preceded;
 use nom::multi::many_till;
 
 use nom::{
@@ -14,6 +15,13 @@ use nom::{
 pub fn parse_language_block(input: &str) -> IResult<&str, ParsedItem> {
     let (input, _) = empty_lines(input)?;
     let (input, language_name) = parse_language_name(input)?;
+    // Give: script module:
+    // Want: "script module"
+    let (input, modifier) = opt(preceded(space1, alphanumeric1))(input)?;
+    let language_name = match modifier {
+        Some(modifier) => format!("{} {}", language_name, modifier),
+        None => language_name,
+    };
     let (input, _) = tuple((multispace0, tag(":")))(input)?;
     let (input, _) = tag("\n")(input)?;
 
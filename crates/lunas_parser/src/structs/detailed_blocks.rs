@@ -1,7 +1,7 @@
 use super::detailed_language_blocks::DetailedLanguageBlocks;
 use super::detailed_meta_data::DetailedMetaData;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DetailedBlock {
     pub detailed_meta_data: Vec<DetailedMetaData>,
     pub detailed_language_blocks: DetailedLanguageBlocks,
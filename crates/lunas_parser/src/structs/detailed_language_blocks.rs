@@ -1,14 +1,16 @@
 use lunas_html_parser::Dom;
 use serde_json::Value;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DetailedLanguageBlocks {
     pub dom: Dom,
     pub css: Option<String>,
     pub js: Option<JsBlock>,
+    /// `script module:`, which runs once when the module of the component is loaded.
+    pub module_js: Option<JsBlock>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct JsBlock {
     pub ast: Value,
     pub raw: String,
@@ -2,21 +2,26 @@ use nom::{
     branch::permutation,
     bytes::complete::{is_not, tag, take_while1},
     character::complete::{alphanumeric0, alphanumeric1, space0},
-    combinator::{all_consuming, opt},
+    combinator::{all_consuming, map, opt, recognize},
     IResult,
 };
 
 use super::blocks::MetaData;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DetailedMetaData {
     PropsInput(PropsInput),
     UseComponentStatement(UseComponentStatement),
     UseAutoRoutingStatement,
     UseRoutingStatement,
+    RouteDeclaration(RouteDeclaration),
+    /// Tag name that the custom element target registers the component as.
+    CustomElementDeclaration(String),
+    /// Name of an event dispatched with `Lunas.emit`.
+    EventDeclaration(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PropsInput {
     pub variable_name: String,
     pub type_of_value: String,
@@ -24,12 +29,28 @@ pub struct PropsInput {
     pub is_nullable: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UseComponentStatement {
     pub component_name: String,
     pub component_path: String,
 }
 
+/// A route declared with `@route` in a component using `@useRouting`.
+#[derive(Debug, Clone)]
+pub struct RouteDeclaration {
+    pub path: String,
+    pub name: Option<String>,
+    /// Name of the route this route is nested in.
+    pub parent: Option<String>,
+    pub target: RouteTarget,
+}
+
+#[derive(Debug, Clone)]
+pub enum RouteTarget {
+    Component(String),
+    Redirect(String),
+}
+
 impl<'a> DetailedMetaData {
     pub fn from_simple_meta_data(simple_meta_data: MetaData) -> Result<Self, &'a str> {
         if simple_meta_data.kind == "input" {
@@ -60,6 +81,36 @@ impl<'a> DetailedMetaData {
             Ok(Self::UseAutoRoutingStatement)
         } else if simple_meta_data.kind == "useRouting" {
             Ok(Self::UseRoutingStatement)
+        } else if simple_meta_data.kind == "route" {
+            let (_, (path, target)) = parse_route_declaration(&simple_meta_data.content)
+                .map_err(|_| "error parsing route declaration")?;
+            let param = |key: &str| {
+                simple_meta_data
+                    .params
+                    .get(key)
+                    .map(|value| value.trim_matches(|c| c == '\'' || c == '"').to_string())
+            };
+            let parent = param("parent");
+            // Paths of nested routes are relative to their parent route
+            match (&parent, path.starts_with('/')) {
+                (None, false) => return Err("route path must start with `/`"),
+                (Some(_), true) => return Err("path of a nested route must not start with `/`"),
+                _ => {}
+            }
+            Ok(Self::RouteDeclaration(RouteDeclaration {
+                path: path.to_string(),
+                name: param("name"),
+                parent,
+                target,
+            }))
+        } else if simple_meta_data.kind == "customElement" {
+            parse_custom_element_name(simple_meta_data.content.trim())
+                .map(|(_, name)| Self::CustomElementDeclaration(name.to_string()))
+                .map_err(|_| "custom element name must be lowercase and contain `-`")
+        } else if simple_meta_data.kind == "event" {
+            parse_event_name(simple_meta_data.content.trim())
+                .map(|(_, name)| Self::EventDeclaration(name.to_string()))
+                .map_err(|_| "error parsing event declaration")
         } else {
             Err("unknown kind of meta data")
         }
@@ -123,3 +174,45 @@ fn parse_component_use_statement(input: &str) -> IResult<&str, (&str, &str, &str
         preceded(multispace1, parse_string),
     )))(input)
 }
+
+// @route '/users/:id' UserPage
+// @route '/home' redirect '/'
+
+fn parse_route_declaration(input: &str) -> IResult<&str, (&str, RouteTarget)> {
+    all_consuming(tuple((
+        parse_string,
+        preceded(
+            multispace1,
+            alt((
+                map(
+                    preceded(tuple((tag("redirect"), multispace1)), parse_string),
+                    |path: &str| RouteTarget::Redirect(path.to_string()),
+                ),
+                map(take_while1(is_alphanumeric_underscore), |name: &str| {
+                    RouteTarget::Component(name.to_string())
+                }),
+            )),
+        ),
+    )))(input)
+}
+
+// @customElement my-widget
+
+fn parse_custom_element_name(input: &str) -> IResult<&str, &str> {
+    all_consuming(recognize(tuple((
+        take_while1(|c: char| c.is_ascii_lowercase()),
+        take_while(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+        char('-'),
+        take_while(|c: char| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_' || c == '.'
+        }),
+    ))))(input)
+}
+
+// @event change
+
+fn parse_event_name(input: &str) -> IResult<&str, &str> {
+    all_consuming(take_while1(|c: char| {
+        is_alphanumeric_underscore(c) || c == '-' || c == ':'
+    }))(input)
+}
@@ -3,9 +3,15 @@ export type ComponentDeclaration = (args?: {
 }) => LunasModuleExports;
 
 export type LunasModuleExports = {
-  mount: (elm: HTMLElement) => LunasComponentState;
-  insert: (elm: HTMLElement, anchor: HTMLElement | null) => LunasComponentState;
+  mount: (elm: HTMLElement, hydrationKey?: string) => LunasModuleExports;
+  insert: (
+    elm: HTMLElement,
+    anchor: Node | null,
+    hydrationKey?: string
+  ) => LunasModuleExports;
+  hydrate: (elm: HTMLElement) => LunasModuleExports;
   __unmount: () => void;
+  __setEventTarget: (target: EventTarget) => void;
 };
 
 export type LunasComponentState = {
@@ -17,10 +23,24 @@ export type LunasComponentState = {
   currentVarBit: number;
   currentIfBlkBit: number;
   ifBlkRenderers: { [key: string]: () => void };
+  unclaimedIfBlks: { [key: string]: () => void };
   isMounted: boolean;
   componentElm: HTMLElement;
+  eventTarget: EventTarget | null;
   compSymbol: symbol;
   resetDependecies: (() => void)[];
+  // Derived values with the bits of the variables they are computed from
+  computeds: [number, computedObj<any>][];
+  // Side effects with the bits of the variables they read, run after the DOM is updated
+  effects: LunasEffect[];
+  // Callbacks registered with `Lunas.onMount`, `Lunas.onUpdate` and `Lunas.onDestroy`
+  mountHooks: (() => void | (() => void))[];
+  updateHooks: (() => void)[];
+  destroyHooks: (() => void)[];
+  // Functions returned by the mount hooks, called when the component is unmounted
+  mountCleanups: (() => void)[];
+  // Read by scripts as `Lunas.refs`
+  refs: { [name: string]: any };
   // componentElmentSetter: (innerHtml: string, topElmTag: string,topElmAttr: {[key: string]: string}) => void
   __lunas_update: () => void;
   __lunas_after_mount: () => void;
@@ -34,16 +54,45 @@ export type LunasComponentState = {
   // __lunas_init_component: () => void;
 };
 
-type LunasInternalElement = {
-  innerHtml: string;
+type LunasEffect = {
+  depBits: number;
+  // Called when the component is mounted
+  start: () => void;
+  run: () => void;
+  cleanup: () => void;
+};
+
+// Created from a template, or from the markup of components compiled before templates were introduced
+type LunasInternalElement =
+  | {
+      innerHtml: string;
+      topElmTag: string;
+      topElmAttr: { [key: string]: string };
+      template?: undefined;
+    }
+  | { template: LunasTemplate; values: any[]; topElmTag: string };
+
+// Markup of a block with its dynamic parts left out, parsed when the block is first created
+export type LunasTemplate = {
+  strings: string[];
   topElmTag: string;
-  topElmAttr: { [key: string]: string };
+  compiled: CompiledTemplate | null;
+};
+
+type CompiledTemplate = { element: HTMLElement; parts: TemplatePart[] };
+
+// A text node or an attribute whose content is `strings` interleaved with values
+type TemplatePart = {
+  path: number[];
+  attr: string | null;
+  strings: string[];
+  valueIndexes: number[];
 };
 
 class valueObj<T> {
   dependencies: { [key: symbol]: [LunasComponentState, number] } = {};
   constructor(
-    private _v: T,
+    protected _v: T,
     componentObj?: LunasComponentState,
     componentSymbol?: symbol,
     symbolIndex: number = 0
@@ -56,8 +105,27 @@ class valueObj<T> {
   set v(v: T) {
     if (this._v === v) return;
     this._v = v;
+    this.notify();
+  }
+
+  get v() {
+    return this._v;
+  }
+
+  // Reports a change made inside the value, such as `items.v.push(x)`, and returns its result
+  touch<R>(result: R): R {
+    this.notify();
+    return result;
+  }
+
+  protected notify() {
     for (const keys of Object.getOwnPropertySymbols(this.dependencies)) {
       const [componentObj, symbolIndex] = this.dependencies[keys];
+      for (const [depBits, computed] of componentObj.computeds) {
+        if (depBits & symbolIndex) computed.invalidate();
+      }
+      // Values written while the component is set up are shown by its first render
+      if (!componentObj.__lunas_update) continue;
       componentObj.valUpdateMap |= symbolIndex;
       if (!componentObj.updatedFlag) {
         Promise.resolve().then(componentObj.__lunas_update.bind(componentObj));
@@ -66,10 +134,6 @@ class valueObj<T> {
     }
   }
 
-  get v() {
-    return this._v;
-  }
-
   addDependency(componentObj: LunasComponentState, symbolIndex: number) {
     this.dependencies[componentObj.compSymbol] = [componentObj, symbolIndex];
     return {
@@ -80,6 +144,37 @@ class valueObj<T> {
   }
 }
 
+// A value derived from other variables, computed again when it is read after one of them changes
+class computedObj<T> extends valueObj<T> {
+  private dirty = false;
+  constructor(
+    private compute: () => T,
+    componentObj: LunasComponentState,
+    componentSymbol: symbol,
+    symbolIndex: number
+  ) {
+    super(compute(), componentObj, componentSymbol, symbolIndex);
+  }
+
+  get v() {
+    if (this.dirty) {
+      this.dirty = false;
+      this._v = this.compute();
+    }
+    return this._v;
+  }
+
+  set v(_: T) {
+    throw new Error("Computed values cannot be assigned");
+  }
+
+  // Computeds only depend on the ones declared before them, so this never loops
+  invalidate() {
+    this.dirty = true;
+    this.notify();
+  }
+}
+
 export const $$lunasInitComponent = function (
   this: LunasComponentState,
   args: { [key: string]: any } = {},
@@ -93,8 +188,17 @@ export const $$lunasInitComponent = function (
   this.currentIfBlkBit = 0;
   this.isMounted = false;
   this.ifBlkRenderers = {};
+  this.unclaimedIfBlks = {};
+  this.eventTarget = null;
   this.compSymbol = Symbol();
   this.resetDependecies = [];
+  this.computeds = [];
+  this.effects = [];
+  this.mountHooks = [];
+  this.updateHooks = [];
+  this.destroyHooks = [];
+  this.mountCleanups = [];
+  this.refs = {};
 
   const genBitOfVariables = function* (this: LunasComponentState) {
     while (true) {
@@ -135,15 +239,14 @@ export const $$lunasInitComponent = function (
 
   const componentElementSetter = function (
     this: LunasComponentState,
-    innerHtml: string,
-    topElmTag: string,
+    innerHtml: string | LunasInternalElement,
+    topElmTag: string = "",
     topElmAttr: { [key: string]: string } = {}
   ) {
-    this.internalElement = {
-      innerHtml,
-      topElmTag,
-      topElmAttr,
-    };
+    this.internalElement =
+      typeof innerHtml === "string"
+        ? { innerHtml, topElmTag, topElmAttr }
+        : innerHtml;
   }.bind(this);
 
   const setAfterMount = function (
@@ -153,54 +256,146 @@ export const $$lunasInitComponent = function (
     this.__lunas_after_mount = afterMount;
   }.bind(this);
 
+  // Runs after the elements of the component are in the document
+  const finishMount = function (this: LunasComponentState) {
+    this.isMounted = true;
+    for (const hook of this.mountHooks) {
+      const cleanup = hook();
+      if (typeof cleanup === "function") this.mountCleanups.push(cleanup);
+    }
+    this.effects.forEach((effect) => effect.start());
+  }.bind(this);
+
   const mount = function (
     this: LunasComponentState,
     elm: HTMLElement
   ): LunasComponentState {
     if (this.isMounted) throw new Error("Component is already mounted");
-    elm.innerHTML = `<${this.internalElement.topElmTag} ${Object.keys(
-      this.internalElement.topElmAttr
-    )
-      .map((key) => `${key}="${this.internalElement.topElmAttr[key]}"`)
-      .join(" ")}>${this.internalElement.innerHtml}</${
-      this.internalElement.topElmTag
-    }>`;
+    const internalElement = this.internalElement;
+    if (internalElement.template !== undefined) {
+      elm.textContent = "";
+      elm.appendChild(createDomElementFromLunasElement(internalElement));
+    } else {
+      elm.innerHTML = `<${internalElement.topElmTag} ${Object.keys(
+        internalElement.topElmAttr
+      )
+        .map(
+          (key) =>
+            `${key}="${String(internalElement.topElmAttr[key]).replace(
+              /"/g,
+              "&quot;"
+            )}"`
+        )
+        .join(" ")}>${internalElement.innerHtml}</${
+        internalElement.topElmTag
+      }>`;
+    }
     this.componentElm = elm.firstElementChild as HTMLElement;
-    this.__lunas_after_mount();
-    this.isMounted = true;
+    withMountContext(this.componentElm, false, () =>
+      this.__lunas_after_mount()
+    );
+    finishMount();
     return this;
   }.bind(this);
 
   const insert = function (
     this: LunasComponentState,
     elm: HTMLElement,
-    anchor: HTMLElement | null
+    anchor: Node | null
   ): LunasComponentState {
     if (this.isMounted) throw new Error("Component is already mounted");
     this.componentElm = createDomElementFromLunasElement(this.internalElement);
     elm.insertBefore(this.componentElm, anchor);
-    this.__lunas_after_mount();
-    this.isMounted = true;
+    withMountContext(this.componentElm, false, () =>
+      this.__lunas_after_mount()
+    );
+    finishMount();
     return this;
   }.bind(this);
 
+  // Attaches to `elm`, which has been rendered by the server target, instead of creating the DOM
+  const hydrate = function (
+    this: LunasComponentState,
+    elm: HTMLElement
+  ): LunasComponentState {
+    if (this.isMounted) throw new Error("Component is already mounted");
+    this.componentElm = elm;
+    try {
+      if (elm.tagName.toLowerCase() !== this.internalElement.topElmTag) {
+        throw new HydrationMismatchError(
+          `expected <${this.internalElement.topElmTag}> but found <${elm.tagName.toLowerCase()}>`
+        );
+      }
+      withMountContext(elm, true, () => this.__lunas_after_mount());
+      Object.values(this.unclaimedIfBlks).forEach((discard) => discard());
+      this.unclaimedIfBlks = {};
+    } catch (e) {
+      // Mismatches inside child components are handled by the outermost hydrating component
+      if (!(e instanceof HydrationMismatchError) || currentHydrationRoot()) {
+        throw e;
+      }
+      warnHydrationMismatch(`${e.message}, rendering the component again`);
+      this.unclaimedIfBlks = {};
+      this.componentElm = createDomElementFromLunasElement(this.internalElement);
+      elm.replaceWith(this.componentElm);
+      withMountContext(this.componentElm, false, () =>
+        this.__lunas_after_mount()
+      );
+    }
+    finishMount();
+    return this;
+  }.bind(this);
+
+  // Components under a hydrating component claim the markup in their region
+  const mountOrHydrate = function (
+    this: LunasComponentState,
+    elm: HTMLElement,
+    hydrationKey: string | undefined,
+    render: () => LunasComponentState
+  ): LunasComponentState {
+    if (hydrationKey === undefined || !currentHydrationRoot()) return render();
+    const region = findHydrationRegion(elm, hydrationKey);
+    const rendered = region && getRegionElement(region);
+    if (rendered) return hydrate(rendered);
+    throw new HydrationMismatchError(
+      `component at ${hydrationKey} is not in the server-rendered markup`
+    );
+  }.bind(this);
+
   const __unmount = function (this: LunasComponentState) {
     if (!this.isMounted) throw new Error("Component is not mounted");
     this.componentElm!.remove();
     this.isMounted = false;
     this.resetDependecies.forEach((r) => r());
+    this.effects.forEach((effect) => effect.cleanup());
+    this.mountCleanups.forEach((cleanup) => cleanup());
+    this.mountCleanups = [];
+    this.destroyHooks.forEach((hook) => hook());
   }.bind(this);
 
+  // Takes one function checking every binding, or closures with the bits of the variables they depend on
   const updateComponent = function (
     this: LunasComponentState,
-    updateFunc: () => void
+    updateFunc: (() => void) | [number, () => void][]
   ) {
     this.__lunas_update = (() => {
       if (!this.updatedFlag) return;
-      updateFunc.call(this);
+      if (typeof updateFunc === "function") {
+        updateFunc.call(this);
+      } else {
+        for (const [depBits, update] of updateFunc) {
+          if (this.valUpdateMap & depBits) update.call(this);
+        }
+      }
+      const changed = this.valUpdateMap;
       this.updatedFlag = false;
       this.valUpdateMap = 0;
       this.blkUpdateMap = 0;
+      // Effects may write variables again, which schedules another update
+      for (const effect of this.effects) {
+        if (changed & effect.depBits) effect.run();
+      }
+      this.updateHooks.forEach((hook) => hook());
     }).bind(this);
   }.bind(this);
 
@@ -213,40 +408,213 @@ export const $$lunasInitComponent = function (
     );
   }.bind(this);
 
+  const createComputed = function <T>(
+    this: LunasComponentState,
+    compute: () => T,
+    depBits: number
+  ) {
+    const computed = new computedObj<T>(
+      compute,
+      this,
+      this.compSymbol,
+      genBitOfVariables().next().value
+    );
+    this.computeds.push([depBits, computed]);
+    return computed;
+  }.bind(this);
+
+  // The effect runs once it is mounted, and stops when the returned function is called
+  const addEffect = function (this: LunasComponentState, entry: LunasEffect) {
+    this.effects.push(entry);
+    if (this.isMounted) entry.start();
+    return () => {
+      this.effects = this.effects.filter((e) => e !== entry);
+      entry.cleanup();
+    };
+  }.bind(this);
+
+  // Runs `effect` after the component is mounted and after the variables in `depBits` change,
+  // calling the function it returns before it runs again and when the component is unmounted
+  const createEffect = function (
+    effect: () => void | (() => void),
+    depBits: number = 0
+  ) {
+    let cleanup: void | (() => void);
+    const entry: LunasEffect = {
+      depBits,
+      start: () => entry.run(),
+      run: () => {
+        entry.cleanup();
+        cleanup = effect();
+      },
+      cleanup: () => {
+        if (typeof cleanup === "function") cleanup();
+        cleanup = undefined;
+      },
+    };
+    return addEffect(entry);
+  };
+
+  // Calls `callback` with the new and the old result of `source` when the variables it reads change
+  const createWatch = function <T>(
+    source: () => T,
+    callback: (next: T, prev: T) => void | (() => void),
+    depBits: number = 0
+  ) {
+    let current: T;
+    let cleanup: void | (() => void);
+    const entry: LunasEffect = {
+      depBits,
+      start: () => {
+        current = source();
+      },
+      run: () => {
+        const next = source();
+        // Values changed in place are the same object, but are still reported
+        if (Object.is(next, current) && (typeof next !== "object" || next === null)) {
+          return;
+        }
+        const prev = current;
+        current = next;
+        entry.cleanup();
+        cleanup = callback(next, prev);
+      },
+      cleanup: () => {
+        if (typeof cleanup === "function") cleanup();
+        cleanup = undefined;
+      },
+    };
+    return addEffect(entry);
+  };
+
+  // Hooks registered after the component is mounted run at once
+  const onMount = function (
+    this: LunasComponentState,
+    hook: () => void | (() => void)
+  ) {
+    this.mountHooks.push(hook);
+    if (!this.isMounted) return;
+    const cleanup = hook();
+    if (typeof cleanup === "function") this.mountCleanups.push(cleanup);
+  }.bind(this);
+
+  const onUpdate = function (this: LunasComponentState, hook: () => void) {
+    this.updateHooks.push(hook);
+  }.bind(this);
+
+  const onDestroy = function (this: LunasComponentState, hook: () => void) {
+    this.destroyHooks.push(hook);
+  }.bind(this);
+
   const createIfBlock = function (
     this: LunasComponentState,
     name: string,
     lunasElement: () => LunasInternalElement,
-    getParentAndRefElement: () => [HTMLElement, HTMLElement | null],
-    postRender: () => void
+    getParentAndRefElement: () => [HTMLElement, Node | null],
+    postRender: () => void,
+    hydrationKey?: string
   ) {
     const ifBlkBit = genBitOfIfBlks().next().value;
+    const findRendered = () => {
+      const [parentElement] = getParentAndRefElement();
+      // The parent is missing when an outer if block has not been rendered
+      if (!parentElement) return null;
+      const region = findHydrationRegion(parentElement, hydrationKey!);
+      return region && getRegionElement(region);
+    };
+    if (hydrationKey !== undefined && currentHydrationRoot()) {
+      // Rendered by the server although the condition is false on the client
+      this.unclaimedIfBlks[name] = () => {
+        const rendered = findRendered();
+        if (!rendered) return;
+        warnHydrationMismatch(`if block at ${hydrationKey} should not be rendered`);
+        rendered.remove();
+      };
+    }
     this.ifBlkRenderers[name] = (() => {
-      const componentElm = createDomElementFromLunasElement(lunasElement());
-      const [parentElement, refElement] = getParentAndRefElement();
-      parentElement.insertBefore(componentElm, refElement);
-      postRender();
+      // Its condition can be checked again while it stays true
+      if (this.blkRenderedMap & ifBlkBit) return;
+      delete this.unclaimedIfBlks[name];
+      const hydrating =
+        hydrationKey !== undefined && currentHydrationRoot() !== null;
+      if (hydrating && findRendered()) {
+        postRender();
+      } else {
+        if (hydrating) {
+          warnHydrationMismatch(`if block at ${hydrationKey} is not in the server-rendered markup`);
+        }
+        const componentElm = createDomElementFromLunasElement(lunasElement());
+        const [parentElement, refElement] = getParentAndRefElement();
+        parentElement.insertBefore(componentElm, refElement);
+        withMountContext(this.componentElm, false, postRender);
+      }
       (this.blkRenderedMap |= ifBlkBit), (this.blkUpdateMap |= ifBlkBit);
     }).bind(this);
   }.bind(this);
 
+  // Events bubble out of shadow roots so that they can be listened to on custom elements
+  const emit = function (
+    this: LunasComponentState,
+    name: string,
+    detail?: any
+  ) {
+    const target = this.eventTarget || this.componentElm;
+    target.dispatchEvent(
+      new CustomEvent(name, { detail, bubbles: true, composed: true })
+    );
+  }.bind(this);
+
+  const setEventTarget = function (
+    this: LunasComponentState,
+    target: EventTarget
+  ) {
+    this.eventTarget = target;
+  }.bind(this);
+
   const renderIfBlock = function (this: LunasComponentState, name: string) {
     if (!this.ifBlkRenderers[name]) return;
     this.ifBlkRenderers[name]();
   }.bind(this);
 
+  // The instance seen from outside, e.g. by the parent through `Lunas.refs`
+  const componentReturn: LunasModuleExports = {
+    mount: (elm: HTMLElement, hydrationKey?: string) => (
+      mountOrHydrate(elm, hydrationKey, () => mount(elm)), componentReturn
+    ),
+    insert: (elm: HTMLElement, anchor: Node | null, hydrationKey?: string) => (
+      mountOrHydrate(elm, hydrationKey, () => insert(elm, anchor)),
+      componentReturn
+    ),
+    hydrate: (elm: HTMLElement) => (hydrate(elm), componentReturn),
+    __unmount,
+    __setEventTarget: setEventTarget,
+  };
+
+  // Adds the bindings exported from the script to the instance
+  const expose = function (api: object): LunasModuleExports {
+    return Object.defineProperties(
+      componentReturn,
+      Object.getOwnPropertyDescriptors(api)
+    );
+  };
+
   return {
     $$lunasSetComponentElement: componentElementSetter,
     $$lunasUpdateComponent: updateComponent,
     $$lunasAfterMount: setAfterMount,
     $$lunasReactive: createReactive,
+    $$lunasComputed: createComputed,
+    $$lunasEffect: createEffect,
+    $$lunasWatch: createWatch,
+    $$lunasOnMount: onMount,
+    $$lunasOnUpdate: onUpdate,
+    $$lunasOnDestroy: onDestroy,
     $$lunasCreateIfBlock: createIfBlock,
     $$lunasRenderIfBlock: renderIfBlock,
-    $$lunasComponentReturn: {
-      mount,
-      insert,
-      __unmount,
-    } as LunasModuleExports,
+    $$lunasEmit: emit,
+    $$lunasRefs: this.refs,
+    $$lunasComponentReturn: componentReturn,
+    $$lunasExpose: expose,
   };
 };
 
@@ -264,9 +632,52 @@ export function $$lunasEscapeHtml(text: any): string {
   });
 }
 
-export function $$lunasGetElmRefs(ids: string[], preserveId: number) {
+// Renders a bound attribute for server-side rendering; `undefined` omits the attribute
+export function $$lunasRenderAttr(key: string, content: any): string {
+  if (content === undefined) return "";
+  return ` ${key}="${$$lunasEscapeHtml(content)}"`;
+}
+
+// The server target renders once, so it imports this instead of the helper created for each instance
+export function $$lunasComputed<T>(compute: () => T): T {
+  return compute();
+}
+
+// Effects, watchers and lifecycle hooks only run in the browser
+export function $$lunasEffect(..._: any[]): () => void {
+  return () => {};
+}
+
+export const $$lunasWatch = $$lunasEffect;
+export const $$lunasOnMount = $$lunasEffect;
+export const $$lunasOnUpdate = $$lunasEffect;
+export const $$lunasOnDestroy = $$lunasEffect;
+
+// Nothing is mounted on the server
+export const $$lunasRefs: { [name: string]: any } = {};
+
+// Resolves after the updates scheduled by the writes made so far have been applied
+export function $$lunasNextTick(): Promise<void> {
+  return Promise.resolve();
+}
+
+export function $$lunasGetElmRefs(
+  ids: string[],
+  preserveId: number,
+  hydrationPaths?: [number[], string][]
+) {
+  const hydrationRoot = currentHydrationRoot();
+  if (hydrationPaths && hydrationRoot) {
+    return hydrationPaths.map(([path, tagName]) =>
+      claimElement(hydrationRoot, path, tagName)
+    );
+  }
+  // Elements rendered into a shadow root cannot be found through `document`
+  const context = currentMountContext();
+  const rootNode = context && context.componentElm.getRootNode();
+  const scope = rootNode instanceof ShadowRoot ? rootNode : document;
   return ids.map((id, index) => {
-    const e = document.getElementById(id)!;
+    const e = scope.getElementById(id)!;
     (2 ** index) & preserveId && e.removeAttribute("id");
     return e;
   });
@@ -302,8 +713,13 @@ export function $$lunasReplaceAttr(
 
 export function $$lunasInsertEmpty(
   parent: HTMLElement,
-  anchor: HTMLElement | null
-) {
+  anchor: Node | null,
+  hydrationKey?: string
+): Node {
+  if (hydrationKey !== undefined && currentHydrationRoot()) {
+    // The end of the region is where the content is inserted from now on
+    return claimHydrationRegion(parent, hydrationKey).end;
+  }
   const empty = document.createTextNode(" ");
   parent.insertBefore(empty, anchor);
   return empty;
@@ -312,8 +728,26 @@ export function $$lunasInsertEmpty(
 export function $$lunasInsertContent(
   content: string,
   parent: HTMLElement,
-  anchor: HTMLElement | null
+  anchor: Node | null,
+  hydrationKey?: string
 ) {
+  if (hydrationKey !== undefined && currentHydrationRoot()) {
+    const region = claimHydrationRegion(parent, hydrationKey);
+    let textNode = region.start.nextSibling;
+    if (!(textNode instanceof Text)) {
+      // Empty text is not in the server-rendered markup
+      textNode = document.createTextNode("");
+      parent.insertBefore(textNode, region.end);
+    }
+    const text = textNode as Text;
+    if (text.data.trim() !== content.trim()) {
+      warnHydrationMismatch(
+        `expected text "${content}" but found "${text.data}" at ${hydrationKey}`
+      );
+      text.data = content;
+    }
+    return text;
+  }
   const contentNode = document.createTextNode(content);
   parent.insertBefore(contentNode, anchor);
   return contentNode;
@@ -331,9 +765,82 @@ export function $$createLunasElement(
   };
 }
 
+export function $$lunasCreateTemplate(
+  strings: string[],
+  topElmTag: string
+): LunasTemplate {
+  return { strings, topElmTag, compiled: null };
+}
+
+export function $$lunasUseTemplate(
+  template: LunasTemplate,
+  values: any[]
+): LunasInternalElement {
+  return { template, values, topElmTag: template.topElmTag };
+}
+
+// Markers stand for the values while the markup is parsed, and tell where each of them goes
+const templateMarker = /lunas\$(\d+)\$/;
+
+const compileTemplate = function (template: LunasTemplate): CompiledTemplate {
+  const templateElm = document.createElement("template");
+  templateElm.innerHTML = template.strings.reduce(
+    (html, s, index) => `${html}lunas$${index - 1}$${s}`
+  );
+  const parts: TemplatePart[] = [];
+  const addPart = (path: number[], attr: string | null, content: string) => {
+    const pieces = content.split(templateMarker);
+    if (pieces.length === 1) return;
+    parts.push({
+      path,
+      attr,
+      strings: pieces.filter((_, index) => index % 2 === 0),
+      valueIndexes: pieces
+        .filter((_, index) => index % 2 === 1)
+        .map((index) => Number(index)),
+    });
+  };
+  const collectParts = (node: Node, path: number[]) => {
+    if (node.nodeType === Node.TEXT_NODE) {
+      addPart(path, null, (node as Text).data);
+    } else if (node.nodeType === Node.ELEMENT_NODE) {
+      for (const attr of Array.from((node as HTMLElement).attributes)) {
+        addPart(path, attr.name, attr.value);
+      }
+    }
+    node.childNodes.forEach((child, index) =>
+      collectParts(child, [...path, index])
+    );
+  };
+  const element = templateElm.content.firstElementChild as HTMLElement;
+  collectParts(element, []);
+  return { element, parts };
+};
+
+const instantiateTemplate = function (
+  template: LunasTemplate,
+  values: any[]
+): HTMLElement {
+  const compiled = template.compiled || (template.compiled = compileTemplate(template));
+  const element = compiled.element.cloneNode(true) as HTMLElement;
+  for (const part of compiled.parts) {
+    let node: Node = element;
+    for (const index of part.path) node = node.childNodes[index];
+    const content = part.strings.reduce(
+      (text, s, index) => `${text}${values[part.valueIndexes[index - 1]]}${s}`
+    );
+    if (part.attr === null) (node as Text).data = content;
+    else (node as HTMLElement).setAttribute(part.attr, content);
+  }
+  return element;
+};
+
 export const createDomElementFromLunasElement = function (
   lunasElement: LunasInternalElement
 ): HTMLElement {
+  if (lunasElement.template !== undefined) {
+    return instantiateTemplate(lunasElement.template, lunasElement.values);
+  }
   const componentElm = document.createElement(lunasElement.topElmTag);
   Object.keys(lunasElement.topElmAttr).forEach((key) => {
     componentElm.setAttribute(key, lunasElement.topElmAttr[key]);
@@ -348,3 +855,219 @@ export const $$lunasCreateNonReactive = function <T>(
 ) {
   return new valueObj<T>(v);
 };
+
+// `inputs` maps each property to its attribute, the type of the `@input` and its initial value
+export function $$lunasDefineCustomElement(
+  tagName: string,
+  component: ComponentDeclaration,
+  inputs: { [key: string]: [string, string, () => any] },
+  style: string | null
+) {
+  const properties = Object.keys(inputs);
+
+  class LunasCustomElement extends HTMLElement {
+    __lunasValues: { [key: string]: valueObj<any> } = {};
+    __lunasRendered = false;
+
+    static get observedAttributes() {
+      return properties.map((key) => inputs[key][0]);
+    }
+
+    constructor() {
+      super();
+      this.attachShadow({ mode: "open" });
+      for (const key of properties) {
+        this.__lunasValues[key] = new valueObj(inputs[key][2]());
+        // Properties set before the element was upgraded shadow the accessors
+        if (Object.prototype.hasOwnProperty.call(this, key)) {
+          const value = (this as any)[key];
+          delete (this as any)[key];
+          this.__lunasValues[key].v = value;
+        }
+      }
+    }
+
+    connectedCallback() {
+      if (this.__lunasRendered) return;
+      this.__lunasRendered = true;
+      const shadowRoot = this.shadowRoot!;
+      if (style !== null) {
+        const styleElm = document.createElement("style");
+        styleElm.textContent = style;
+        shadowRoot.appendChild(styleElm);
+      }
+      const instance = component(this.__lunasValues);
+      instance.__setEventTarget(this);
+      instance.insert(shadowRoot as unknown as HTMLElement, null);
+    }
+
+    attributeChangedCallback(
+      name: string,
+      _: string | null,
+      value: string | null
+    ) {
+      const key = properties.find((key) => inputs[key][0] === name)!;
+      this.__lunasValues[key].v = fromAttribute(value, inputs[key][1]);
+    }
+  }
+
+  for (const key of properties) {
+    Object.defineProperty(LunasCustomElement.prototype, key, {
+      get(this: LunasCustomElement) {
+        return this.__lunasValues[key].v;
+      },
+      set(this: LunasCustomElement, value: any) {
+        this.__lunasValues[key].v = value;
+      },
+    });
+  }
+
+  if (!customElements.get(tagName)) {
+    customElements.define(tagName, LunasCustomElement);
+  }
+  return LunasCustomElement;
+}
+
+const fromAttribute = function (value: string | null, type: string) {
+  switch (type) {
+    case "boolean":
+      return value !== null;
+    case "number":
+      return value === null ? undefined : Number(value);
+    default:
+      return value === null ? undefined : value;
+  }
+};
+
+declare const process: { env: { NODE_ENV?: string } };
+
+// Components whose after-mount or if block is running
+type MountContext = { componentElm: HTMLElement; hydrating: boolean };
+const mountContexts: MountContext[] = [];
+
+const currentMountContext = (): MountContext | null =>
+  mountContexts.length === 0 ? null : mountContexts[mountContexts.length - 1];
+
+const currentHydrationRoot = (): HTMLElement | null => {
+  const context = currentMountContext();
+  return context && context.hydrating ? context.componentElm : null;
+};
+
+const withMountContext = function (
+  componentElm: HTMLElement,
+  hydrating: boolean,
+  f: () => void
+) {
+  mountContexts.push({ componentElm, hydrating });
+  try {
+    f();
+  } finally {
+    mountContexts.pop();
+  }
+};
+
+class HydrationMismatchError extends Error {}
+
+const warnHydrationMismatch = function (message: string) {
+  // Bundlers replace `process.env.NODE_ENV` for production builds
+  if (typeof process !== "undefined" && process.env.NODE_ENV === "production")
+    return;
+  console.warn(`[lunas] Hydration mismatch: ${message}`);
+};
+
+type HydrationRegion = { start: Comment; end: Comment };
+
+// The server target wraps nodes that the client inserts after mounting in `<!--[key-->...<!--]-->`
+const isRegionStart = (node: Node): node is Comment =>
+  node.nodeType === Node.COMMENT_NODE && (node as Comment).data.startsWith("[");
+
+const findRegionEnd = function (start: Comment): Comment | null {
+  let node = start.nextSibling;
+  while (node) {
+    if (node.nodeType === Node.COMMENT_NODE && (node as Comment).data === "]")
+      return node as Comment;
+    node = node.nextSibling;
+  }
+  return null;
+};
+
+const findHydrationRegion = function (
+  parent: Node,
+  key: string
+): HydrationRegion | null {
+  for (const node of Array.from(parent.childNodes)) {
+    if (isRegionStart(node) && node.data === `[${key}`) {
+      const end = findRegionEnd(node);
+      return end && { start: node, end };
+    }
+  }
+  return null;
+};
+
+const claimHydrationRegion = function (
+  parent: Node,
+  key: string
+): HydrationRegion {
+  const region = findHydrationRegion(parent, key);
+  if (!region) {
+    throw new HydrationMismatchError(
+      `${key} is not in the server-rendered markup`
+    );
+  }
+  return region;
+};
+
+const getRegionElement = function (region: HydrationRegion) {
+  let node = region.start.nextSibling;
+  while (node && node !== region.end) {
+    if (node.nodeType === Node.ELEMENT_NODE) return node as HTMLElement;
+    node = node.nextSibling;
+  }
+  return null;
+};
+
+// Child nodes count in template order: elements, comments and regions.
+// Text outside of regions is always the only child of its element.
+const getTemplateChild = function (parent: Node, index: number) {
+  let slot = 0;
+  let node = parent.firstChild;
+  while (node) {
+    if (node.nodeType === Node.TEXT_NODE) {
+      node = node.nextSibling;
+      continue;
+    }
+    if (isRegionStart(node)) {
+      const end = findRegionEnd(node);
+      if (slot === index) {
+        return end && getRegionElement({ start: node, end });
+      }
+      node = end && end.nextSibling;
+    } else {
+      if (slot === index) return node;
+      node = node.nextSibling;
+    }
+    slot++;
+  }
+  return null;
+};
+
+const claimElement = function (
+  root: HTMLElement,
+  path: number[],
+  tagName: string
+): HTMLElement {
+  let node: Node | null = root;
+  for (const index of path) {
+    node = node && getTemplateChild(node, index);
+  }
+  if (
+    !node ||
+    node.nodeType !== Node.ELEMENT_NODE ||
+    (node as HTMLElement).tagName.toLowerCase() !== tagName
+  ) {
+    throw new HydrationMismatchError(
+      `expected <${tagName}> at ${[0, ...path].join(".")}`
+    );
+  }
+  return node as HTMLElement;
+};
@@ -1,26 +1,152 @@
-import { LunasModuleExports, ComponentDeclaration } from ".";
+import {
+  LunasModuleExports,
+  ComponentDeclaration,
+  $$lunasCreateNonReactive,
+} from ".";
 
 export type ComponentLoader = () => Promise<{ default: ComponentDeclaration }>;
 
+// `path` may contain dynamic segments (`/users/:id`) and a trailing catch-all segment (`/*rest`).
+// Paths of `children` are relative to the path of their parent, whose component renders them in its own `RouterView`.
 export type Route = {
   path: string;
-  component: ComponentLoader;
+  name?: string;
+  component?: ComponentLoader;
+  redirect?: string;
+  children?: Route[];
 };
 
+export type RouteParams = { [key: string]: string };
+
+export type RouteLocation = string | { name: string; params?: RouteParams };
+
+type MatchedRoute = { route: Route; params: RouteParams };
+
+type RenderingTarget = {
+  parent: HTMLElement;
+  anchor: HTMLElement | null;
+  haveSiblingElm: boolean;
+};
+
+const splitPath = (path: string) => path.split("/").filter((s) => s !== "");
+
+export function buildPath(pattern: string, params: RouteParams = {}): string {
+  const segments = splitPath(pattern)
+    .map((segment) => {
+      if (segment.startsWith(":")) {
+        return encodeURIComponent(params[segment.slice(1)] ?? "");
+      }
+      if (segment.startsWith("*")) {
+        return (params[segment.slice(1)] ?? "")
+          .split("/")
+          .map(encodeURIComponent)
+          .join("/");
+      }
+      return segment;
+    })
+    .filter((s) => s !== "");
+  return "/" + segments.join("/");
+}
+
+// Returns the number of path segments consumed by the pattern, or null if it does not match.
+// Without `prefix`, the pattern has to consume the whole path.
+function matchSegments(
+  patternSegments: string[],
+  pathSegments: string[],
+  params: RouteParams,
+  prefix: boolean
+): number | null {
+  for (let i = 0; i < patternSegments.length; i++) {
+    const segment = patternSegments[i];
+    if (segment.startsWith("*")) {
+      params[segment.slice(1)] = pathSegments
+        .slice(i)
+        .map(decodeURIComponent)
+        .join("/");
+      return pathSegments.length;
+    }
+    if (i >= pathSegments.length) return null;
+    if (segment.startsWith(":")) {
+      params[segment.slice(1)] = decodeURIComponent(pathSegments[i]);
+    } else if (segment !== pathSegments[i]) {
+      return null;
+    }
+  }
+  if (!prefix && patternSegments.length !== pathSegments.length) return null;
+  return patternSegments.length;
+}
+
+export function matchRoute(pattern: string, path: string): RouteParams | null {
+  const params: RouteParams = {};
+  return matchSegments(splitPath(pattern), splitPath(path), params, false) ===
+    null
+    ? null
+    : params;
+}
+
+// Finds the chain of routes matching the path, from the outermost layout to the page.
+// The params of each level include the params of the levels above it.
+function matchRouteTree(
+  routes: Route[],
+  pathSegments: string[],
+  inheritedParams: RouteParams
+): MatchedRoute[] | null {
+  for (const route of routes) {
+    const params = { ...inheritedParams };
+    const consumed = matchSegments(
+      splitPath(route.path),
+      pathSegments,
+      params,
+      route.children !== undefined
+    );
+    if (consumed === null) continue;
+    if (route.children === undefined) return [{ route, params }];
+    const nested = matchRouteTree(
+      route.children,
+      pathSegments.slice(consumed),
+      params
+    );
+    if (nested) return [{ route, params }, ...nested];
+  }
+  return null;
+}
+
+function findRoutePattern(
+  routes: Route[],
+  name: string,
+  base: string
+): string | null {
+  for (const route of routes) {
+    const pattern = base + "/" + route.path;
+    if (route.name === name) return pattern;
+    if (route.children) {
+      const found = findRoutePattern(route.children, name, pattern);
+      if (found) return found;
+    }
+  }
+  return null;
+}
+
+const sameParams = (a: RouteParams, b: RouteParams) =>
+  Object.keys(a).length === Object.keys(b).length &&
+  Object.keys(a).every((key) => a[key] === b[key]);
+
 export class Router {
   routes: Route[];
   notFound: () => void;
-  currentComponent: LunasModuleExports | null;
-  renderingTarget!: {
-    parent: HTMLElement;
-    anchor: HTMLElement | null;
-    haveSiblingElm: boolean;
-  };
+  // `views[n]` is where the component of the n-th level of the matched routes is rendered
+  views: RenderingTarget[];
+  rendered: (MatchedRoute & { component: LunasModuleExports })[];
+  renderingDepth: number;
+  navigationId: number;
 
   constructor() {
     this.routes = [];
     this.notFound = () => {};
-    this.currentComponent = null;
+    this.views = [];
+    this.rendered = [];
+    this.renderingDepth = 0;
+    this.navigationId = 0;
     window.addEventListener("popstate", this.handlePopState.bind(this));
   }
 
@@ -32,18 +158,63 @@ export class Router {
     this.notFound = notFoundHandler;
   }
 
-  navigate(path: string) {
+  resolve(location: RouteLocation): string {
+    if (typeof location === "string") return location;
+    const pattern = findRoutePattern(this.routes, location.name, "");
+    if (pattern === null) {
+      throw new Error(`Unknown route name: ${location.name}`);
+    }
+    return buildPath(pattern, location.params);
+  }
+
+  navigate(location: RouteLocation) {
+    const path = this.resolve(location);
     window.history.pushState({}, path, window.location.origin + path);
     this.handleRoute(path);
   }
 
   async handleRoute(path: string) {
-    const route = this.routes.find((route) => route.path === path);
-    if (route) {
-      const component = (await route.component()).default;
-      this.renderComponent(component);
-    } else {
+    const navigationId = ++this.navigationId;
+    const matched = matchRouteTree(this.routes, splitPath(path), {});
+    if (!matched) {
       this.notFound();
+      return;
+    }
+    const { route, params } = matched[matched.length - 1];
+    if (route.redirect !== undefined) {
+      const redirectPath = buildPath(route.redirect, params);
+      window.history.replaceState(
+        {},
+        redirectPath,
+        window.location.origin + redirectPath
+      );
+      this.handleRoute(redirectPath);
+      return;
+    }
+    if (matched.some(({ route }) => !route.component)) {
+      this.notFound();
+      return;
+    }
+    const components = await Promise.all(
+      matched.map(async ({ route }) => (await route.component!()).default)
+    );
+    // A newer navigation started while the components were loading
+    if (navigationId !== this.navigationId) return;
+
+    // Layouts that are still matched with the same params stay mounted
+    let depth = 0;
+    while (
+      depth < this.rendered.length &&
+      depth < matched.length &&
+      this.rendered[depth].route === matched[depth].route &&
+      sameParams(this.rendered[depth].params, matched[depth].params)
+    ) {
+      depth++;
+    }
+    if (depth === matched.length && depth === this.rendered.length) return;
+    this.unmountFrom(depth);
+    for (; depth < matched.length; depth++) {
+      this.renderComponent(components[depth], matched[depth], depth);
     }
   }
 
@@ -51,19 +222,47 @@ export class Router {
     this.handleRoute(window.location.pathname);
   }
 
-  renderComponent(component: ComponentDeclaration) {
-    if (this.currentComponent) {
-      this.currentComponent.__unmount();
+  unmountFrom(depth: number) {
+    for (let i = this.rendered.length - 1; i >= depth; i--) {
+      this.rendered[i].component.__unmount();
     }
-    this.currentComponent = component();
-    if (this.renderingTarget.haveSiblingElm) {
-      this.currentComponent.insert(
-        this.renderingTarget.parent,
-        this.renderingTarget.anchor
-      );
+    this.rendered.length = depth;
+    this.views.length = depth + 1;
+  }
+
+  renderComponent(
+    component: ComponentDeclaration,
+    matched: MatchedRoute,
+    depth: number
+  ) {
+    const target = this.views[depth];
+    if (!target) {
+      throw new Error(`No RouterView to render ${matched.route.path} in`);
+    }
+    // Route parameters are passed to the page as its inputs
+    const args = Object.fromEntries(
+      Object.entries(matched.params).map(([key, value]) => [
+        key,
+        $$lunasCreateNonReactive(value),
+      ])
+    );
+    const instance = component(args);
+    // A RouterView of the component being mounted renders the next level
+    this.renderingDepth = depth + 1;
+    if (target.haveSiblingElm) {
+      instance.insert(target.parent, target.anchor);
     } else {
-      this.currentComponent.mount(this.renderingTarget.parent);
+      instance.mount(target.parent);
     }
+    this.rendered.push({ ...matched, component: instance });
+  }
+
+  registerView(
+    parent: HTMLElement,
+    anchor: HTMLElement | null,
+    haveSiblingElm: boolean
+  ) {
+    this.views[this.renderingDepth] = { parent, anchor, haveSiblingElm };
   }
 
   initialize(
@@ -73,7 +272,8 @@ export class Router {
     haveSiblingElm: boolean
   ) {
     this.routes = routes;
-    this.renderingTarget = { parent, anchor, haveSiblingElm };
+    this.views = [{ parent, anchor, haveSiblingElm }];
+    this.rendered = [];
     this.handleRoute(window.location.pathname);
   }
 }